@@ -0,0 +1,104 @@
+//! Instructions and cycles per second of the VM, with and without observers
+//! and with both engines.
+//! Run with `cargo bench`.
+
+use core_war::vm::{
+    config::{Engine, VmConfig},
+    event::{Observable, Observer, VmEvent},
+    parser::parse,
+    runner::MatchRunner,
+    vms::{Outcome, Vm, WarriorDefinition},
+};
+use std::time::Instant;
+
+const ROUNDS: usize = 20;
+
+/// Does nothing, to measure the cost of building and dispatching events.
+struct NullObserver;
+
+impl<const CORE_SIZE: usize> Observer<VmEvent<CORE_SIZE>> for NullObserver {
+    fn notify(&self, _event: VmEvent<CORE_SIZE>) {}
+}
+
+fn warriors() -> Vec<WarriorDefinition<8000>> {
+    [
+        (
+            "little_something",
+            include_str!("../bots/little_something.war"),
+        ),
+        ("annoying", include_str!("../bots/annoying.war")),
+        ("thing", include_str!("../bots/thing.war")),
+        ("bot2", include_str!("../bots/bot2.war")),
+    ]
+    .iter()
+    .map(|(name, code)| WarriorDefinition::new(name.to_string(), parse(code.to_string()).unwrap()))
+    .collect()
+}
+
+/// Plays `ROUNDS` rounds on the VMs built by `new_vm` and prints how fast
+/// they went.
+fn bench<const OBSERVED: bool>(
+    name: &str,
+    new_vm: impl Fn(&MatchRunner<8000>, usize) -> Vm<8000, OBSERVED>,
+) {
+    let config = VmConfig {
+        seed: Some(1),
+        ..VmConfig::default()
+    };
+    let runner = MatchRunner::new(config, warriors());
+
+    let mut ticks = 0;
+    let mut cycles = 0;
+    let start = Instant::now();
+    for round in 0..ROUNDS {
+        let mut vm = new_vm(&runner, round);
+        while let Outcome::Running = vm.play(i32::MAX) {}
+
+        ticks += vm.ticks();
+        cycles += vm.cycles();
+    }
+    let seconds = start.elapsed().as_secs_f64();
+
+    println!(
+        "{:<24}{:>12.0} instructions/s {:>10.0} cycles/s",
+        name,
+        ticks as f64 / seconds,
+        cycles as f64 / seconds
+    );
+}
+
+fn main() {
+    let placed = |runner: &MatchRunner<8000>, round| {
+        let placement = runner.placement(round, runner.round_seed(round));
+        (VmConfig::default(), warriors(), placement)
+    };
+    let fast = VmConfig {
+        engine: Engine::Fast,
+        ..VmConfig::default()
+    };
+
+    bench("observed, 1 observer", |runner, round| {
+        let (config, warriors, placement) = placed(runner, round);
+        let mut vm = Vm::with_placement(config, warriors, placement).unwrap();
+        vm.register(Box::new(NullObserver));
+        vm
+    });
+    bench("observed, no observer", |runner, round| {
+        let (config, warriors, placement) = placed(runner, round);
+        Vm::with_placement(config, warriors, placement).unwrap()
+    });
+    bench("headless", |runner, round| {
+        let (config, warriors, placement) = placed(runner, round);
+        Vm::headless(config, warriors, placement).unwrap()
+    });
+    bench("fast, 1 observer", |runner, round| {
+        let (_, warriors, placement) = placed(runner, round);
+        let mut vm = Vm::with_placement(fast.clone(), warriors, placement).unwrap();
+        vm.register(Box::new(NullObserver));
+        vm
+    });
+    bench("fast, headless", |runner, round| {
+        let (_, warriors, placement) = placed(runner, round);
+        Vm::headless(fast.clone(), warriors, placement).unwrap()
+    });
+}
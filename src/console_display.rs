@@ -1,77 +1,284 @@
-use super::vm::event::{EventType, Observer, VmEvent};
+use crate::tui_display::Map;
+use core_war::vm::event::{EventType, Observer, VmEvent};
+use core_war::vm::palette::Palette;
+use core_war::vm::processes::ProcessTracker;
 use std::cell::RefCell;
-use std::io::{stdout, Stdout, Write};
+use std::io::{stdout, Result, Stdout, Write};
+use std::time::{Duration, Instant};
 
-pub use crossterm::{
+use crossterm::{
     cursor,
-    event::{self, Event, KeyCode, KeyEvent},
-    execute, queue,
-    style::{style, Color, PrintStyledContent, SetForegroundColor, Stylize},
-    terminal::{self, ClearType},
-    Command, ExecutableCommand, QueueableCommand, Result,
+    style::{style, Color, PrintStyledContent, Stylize},
+    terminal, QueueableCommand,
 };
 
+/// How often the terminal is flushed and its size checked
+const REFRESH: Duration = Duration::from_millis(100);
+/// Rows of the process count histogram, under the core when there is room
+const HISTOGRAM_ROWS: usize = 4;
+const MIN_HEIGHT_FOR_HISTOGRAM: usize = 16;
+
 pub struct ConsoleDisplay {
     stdout: RefCell<Stdout>,
     colors: Vec<Color>,
+    light_colors: Vec<Color>,
+    names: Vec<String>,
+    screen: RefCell<Screen>,
+}
+
+/// What is on the terminal, to draw it again when it is resized.
+struct Screen {
+    /// Character and warrior of each cell drawn
+    cells: Vec<Option<(char, usize)>>,
+    /// Character and warrior last drawn in each character of the map:
+    /// several cells share one when the core does not fit
+    chars: Vec<Option<(char, usize)>>,
+    /// Characters of the map showing processes instead
+    marked: Vec<bool>,
+    tracker: ProcessTracker,
+    size: (u16, u16),
+    map: Map,
+    /// Rows of the terminal given to the histogram, none when too small
+    histogram: usize,
+    refreshed: Instant,
 }
 
 impl ConsoleDisplay {
-    pub fn new() -> Box<ConsoleDisplay> {
-        Box::new(ConsoleDisplay {
+    pub fn new(
+        names: Vec<String>,
+        starts: &[usize],
+        core_size: usize,
+        palette: &Palette,
+    ) -> Box<ConsoleDisplay> {
+        let color = |[r, g, b]: [u8; 3]| Color::Rgb { r, g, b };
+        let display = ConsoleDisplay {
             stdout: RefCell::new(stdout()),
-            colors: vec![
-                Color::Red,
-                Color::Blue,
-                Color::Grey,
-                Color::Yellow,
-                Color::Green,
-            ],
-        })
+            colors: (0..names.len())
+                .map(|id| color(palette.color(id)))
+                .collect(),
+            light_colors: (0..names.len())
+                .map(|id| color(palette.light(id)))
+                .collect(),
+            screen: RefCell::new(Screen {
+                cells: vec![None; core_size],
+                chars: vec![],
+                marked: vec![],
+                tracker: ProcessTracker::new(starts),
+                size: (0, 0),
+                map: Map::fit(core_size, 1, 1),
+                histogram: 0,
+                refreshed: Instant::now(),
+            }),
+            names,
+        };
+        let _ = display.resize();
+
+        Box::new(display)
     }
 }
 
-impl Observer<VmEvent> for ConsoleDisplay {
-    fn notify(&self, event: VmEvent) {
-        let x = (event.offset.unwrap_or(0) % 160) as u16;
-        let y = (event.offset.unwrap_or(0) / 160) as u16;
+impl ConsoleDisplay {
+    /// Warriors on each line of the legend, for a terminal `width` wide
+    fn legend_lines(&self, width: usize) -> Vec<Vec<usize>> {
+        let mut lines: Vec<Vec<usize>> = vec![];
+        let mut line_width = 0;
+
+        for (id, name) in self.names.iter().enumerate() {
+            // the name, its process count and the space after
+            let item = name.chars().count() + 8;
+            if lines.is_empty() || line_width + item > width {
+                lines.push(vec![]);
+                line_width = 0;
+            }
+            lines.last_mut().unwrap().push(id);
+            line_width += item;
+        }
+
+        lines
+    }
+
+    /// Lays the core out for the size of the terminal and draws it again
+    fn resize(&self) -> Result<()> {
+        let size = terminal::size()?;
+        let mut screen = self.screen.borrow_mut();
+        if size == screen.size {
+            return Ok(());
+        }
+
+        let (width, height) = (size.0.max(1) as usize, size.1 as usize);
+        let core_size = screen.cells.len();
+        // a line for the legend and one for the cycle count at the least
+        let legend = self.legend_lines(width).len().max(1) + 1;
+        screen.histogram = if height >= MIN_HEIGHT_FOR_HISTOGRAM {
+            HISTOGRAM_ROWS
+        } else {
+            0
+        };
+        screen.size = size;
+        screen.map = Map::fit(
+            core_size,
+            width,
+            height.saturating_sub(legend + screen.histogram),
+        );
+
+        let chars = core_size.div_ceil(screen.map.per_char);
+        screen.chars = vec![None; chars];
+        screen.marked = vec![false; chars];
+        let per_char = screen.map.per_char;
+        for address in 0..core_size {
+            if screen.cells[address].is_some() {
+                screen.chars[address / per_char] = screen.cells[address];
+            }
+        }
+
         let mut console = self.stdout.borrow_mut();
+        console.queue(terminal::Clear(terminal::ClearType::All))?;
+        for ix in 0..chars {
+            self.draw_char(&mut console, &screen, ix)?;
+        }
 
-        match event.event_type {
-            EventType::TerminatedProgram => {
-                let styled = style(format!("Warrior {} terminated", event.warrior_id))
-                    .with(self.colors[event.warrior_id]);
-                console.queue(cursor::MoveTo(0, 81));
-                console.queue(PrintStyledContent(styled));
+        Ok(())
+    }
+
+    /// Draws what was last written in the character `ix` of the map
+    fn draw_char(&self, console: &mut Stdout, screen: &Screen, ix: usize) -> Result<()> {
+        let (x, y) = (ix % screen.map.columns, ix / screen.map.columns);
+        let styled = match screen.chars[ix] {
+            Some((c, warrior_id)) => style(c).with(self.colors[warrior_id]),
+            None => style(' ').with(Color::Reset),
+        };
+        console.queue(cursor::MoveTo(x as u16, y as u16))?;
+        console.queue(PrintStyledContent(styled))?;
+
+        Ok(())
+    }
+
+    fn set_cell(&self, address: usize, c: char, warrior_id: usize) -> Result<()> {
+        let mut screen = self.screen.borrow_mut();
+        let ix = address / screen.map.per_char;
+        screen.cells[address] = Some((c, warrior_id));
+        screen.chars[ix] = Some((c, warrior_id));
+        // a process drawn there stays until the next refresh
+        if screen.marked[ix] {
+            return Ok(());
+        }
+
+        self.draw_char(&mut self.stdout.borrow_mut(), &screen, ix)
+    }
+
+    /// Every process of every warrior, lighter, where the processes were
+    /// drawn before gone
+    fn draw_processes(&self) -> Result<()> {
+        let mut screen = self.screen.borrow_mut();
+        let mut console = self.stdout.borrow_mut();
+        let per_char = screen.map.per_char;
+
+        let mut marked = vec![None; screen.marked.len()];
+        for id in 0..self.names.len() {
+            for address in screen.tracker.positions(id).keys() {
+                marked[address / per_char] = Some(id);
             }
-            EventType::TerminatedThread => {}
-            EventType::Jump => {
-                let passed_x = (event.moved_from.unwrap_or(0) % 160) as u16;
-                let passed_y = (event.moved_from.unwrap_or(0) / 160) as u16;
-
-                let passed = style(".").with(self.colors[event.warrior_id]);
-                let head = style("*").with(self.colors[event.warrior_id]);
-
-                console.queue(cursor::MoveTo(passed_x, passed_y));
-                console.queue(PrintStyledContent(passed));
-                console.queue(cursor::MoveTo(x, y));
-                console.queue(PrintStyledContent(head));
+        }
+
+        for (ix, process) in marked.into_iter().enumerate() {
+            match process {
+                Some(id) => {
+                    let (x, y) = (ix % screen.map.columns, ix / screen.map.columns);
+                    console.queue(cursor::MoveTo(x as u16, y as u16))?;
+                    console.queue(PrintStyledContent(style('@').with(self.light_colors[id])))?;
+                }
+                None if screen.marked[ix] => self.draw_char(&mut console, &screen, ix)?,
+                None => {}
             }
-            EventType::Change => {
-                let styled = style(".").with(self.colors[event.warrior_id]);
+            screen.marked[ix] = process.is_some();
+        }
+
+        Ok(())
+    }
 
-                console.queue(cursor::MoveTo(x, y));
-                console.queue(PrintStyledContent(styled));
+    /// The process counts of the warriors stacked, over the whole game,
+    /// under the core
+    fn draw_histogram(&self) -> Result<()> {
+        let screen = self.screen.borrow();
+        let mut console = self.stdout.borrow_mut();
+        let rows = screen.histogram;
+        let columns = screen.tracker.stacked(screen.size.0 as usize, rows);
+
+        for row in 0..rows {
+            let y = screen.map.rows + rows - 1 - row;
+            console.queue(cursor::MoveTo(0, y as u16))?;
+            for column in columns.iter() {
+                let styled = match column[row] {
+                    Some(id) => style(' ').on(self.colors[id]),
+                    None => style(' ').with(Color::Reset),
+                };
+                console.queue(PrintStyledContent(styled))?;
             }
         }
 
-        if event.round % 1000 == 0 {
-            let styled = style(format!("Change #{}", event.round)).with(Color::White);
+        Ok(())
+    }
 
-            console.queue(cursor::MoveTo(0, 82));
-            console.queue(PrintStyledContent(styled));
+    /// Names and process counts under the core, then the cycle
+    fn draw_legend(&self, round: u128) -> Result<()> {
+        let screen = self.screen.borrow();
+        let mut console = self.stdout.borrow_mut();
+        let lines = self.legend_lines(screen.size.0 as usize);
+        let top = screen.map.rows + screen.histogram;
 
-            console.flush();
+        for (line, ids) in lines.iter().enumerate() {
+            console.queue(cursor::MoveTo(0, (top + line) as u16))?;
+            console.queue(terminal::Clear(terminal::ClearType::CurrentLine))?;
+            for &id in ids {
+                let processes = screen.tracker.count(id);
+                let text = format!("{} {:<6} ", self.names[id], processes);
+                let styled = if processes > 0 {
+                    style(text).with(self.colors[id])
+                } else {
+                    style(text).with(Color::DarkGrey)
+                };
+                console.queue(PrintStyledContent(styled))?;
+            }
         }
+
+        let styled = style(format!("Change #{}", round)).with(Color::White);
+        console.queue(cursor::MoveTo(0, (top + lines.len()) as u16))?;
+        console.queue(PrintStyledContent(styled))?;
+
+        Ok(())
+    }
+
+    fn draw<const CORE_SIZE: usize>(&self, event: VmEvent<CORE_SIZE>) -> Result<()> {
+        self.screen.borrow_mut().tracker.apply(&event);
+
+        let offset = event.offset.unwrap_or(0);
+        let moved_from = event.moved_from.unwrap_or(0);
+
+        match event.event_type {
+            // where the processes are is drawn on refresh
+            EventType::Jump => self.set_cell(moved_from, '.', event.warrior_id)?,
+            EventType::Change(_) => self.set_cell(offset, '.', event.warrior_id)?,
+            _ => {}
+        }
+
+        let refreshed = self.screen.borrow().refreshed;
+        if refreshed.elapsed() >= REFRESH {
+            self.screen.borrow_mut().refreshed = Instant::now();
+            self.resize()?;
+            self.draw_processes()?;
+            self.draw_histogram()?;
+            self.draw_legend(event.round)?;
+
+            self.stdout.borrow_mut().flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<const CORE_SIZE: usize> Observer<VmEvent<CORE_SIZE>> for ConsoleDisplay {
+    fn notify(&self, event: VmEvent<CORE_SIZE>) {
+        // a broken terminal must not stop the game
+        let _ = self.draw(event);
     }
 }
@@ -0,0 +1,48 @@
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread::JoinHandle;
+
+/// What an interactive display asks of the loop driving the game.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DisplayCommand {
+    TogglePause,
+    /// Execute a single instruction, and pause
+    Step,
+    Faster,
+    Slower,
+    /// The pointer is over this cell, `None` when out of the core
+    Hover(Option<usize>),
+    /// Follow the next process of this warrior, `None` for none
+    Select(Option<usize>),
+    Quit,
+}
+
+/// What the game loop tells an interactive display after each frame.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DisplayStatus {
+    /// A line on the state of the game
+    pub line: String,
+    /// Address and instruction of the cells around the next process of
+    /// the selected warrior
+    pub listing: Vec<(usize, String)>,
+    /// Address of that process
+    pub process: Option<usize>,
+}
+
+/// The game loop end of an interactive display.
+pub struct DisplayControls {
+    pub commands: Receiver<DisplayCommand>,
+    pub status: Sender<DisplayStatus>,
+    /// The thread of a display that must be done with the terminal before
+    /// the game prints anything
+    pub thread: Option<JoinHandle<()>>,
+}
+
+impl DisplayControls {
+    /// Tells the display the game is over and waits for it to be done.
+    pub fn close(self) {
+        drop(self.status);
+        if let Some(thread) = self.thread {
+            let _ = thread.join();
+        }
+    }
+}
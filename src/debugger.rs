@@ -0,0 +1,550 @@
+use core_war::vm::{
+    event::{EventType, Observable, Observer, VmEvent},
+    history::History,
+    instructions::{Instruction, OpCode},
+    parser::parse,
+    vms::{Outcome, Vm},
+};
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::mem::size_of;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+const HELP: &str = "\
+s [n]                 execute n instructions (1)
+r [n]                 play n full rounds, every warrior moving once (1)
+c                     continue until a breakpoint or the end of the battle
+back [n]              step n instructions backward (1)
+b <addr>              break before executing the instruction at addr
+b op <opcode>         break before executing an opcode
+b write <from> <to>   break after a write to a cell in from..=to
+bl                    list breakpoints
+bd <n>                delete breakpoint n
+l [addr] [n]          disassemble n cells (10) from addr (next to move)
+set <addr> <instr>    overwrite a cell, e.g. set 100 mov.i $0, $1
+q                     show the process queues
+q <warrior> [addr..]  replace the process queue of a warrior
+ps                    disassemble around every process
+quit                  leave the debugger
+An empty line repeats the last command. Editing the core or a queue clears
+the history used by `back`.";
+
+/// Processes shown per warrior by `ps`
+const SHOWN_PROCESSES: usize = 8;
+
+/// Memory given to the snapshots of the core kept for `back`
+const HISTORY_BYTES: usize = 64 << 20;
+
+/// Instructions `back` can go back, as long as the snapshots fit
+const HISTORY_REACH: u128 = 16384;
+
+#[derive(PartialEq)]
+enum Breakpoint {
+    Address(usize),
+    OpCode(OpCode),
+    Write(usize, usize),
+}
+
+impl Breakpoint {
+    /// Whether the breakpoint stops `vm`, which just wrote to `writes`.
+    fn hit<const CORE_SIZE: usize>(&self, vm: &Vm<CORE_SIZE>, writes: &[usize]) -> bool {
+        let next = vm.next_to_move().map(|(_, address)| address.value);
+
+        match *self {
+            Breakpoint::Address(a) => next == Some(a),
+            Breakpoint::OpCode(op) => next.map(|a| vm.cell(a).op == op) == Some(true),
+            Breakpoint::Write(from, to) => writes.iter().any(|&w| {
+                if from <= to {
+                    from <= w && w <= to
+                } else {
+                    // the range wraps around the end of the core
+                    w >= from || w <= to
+                }
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Breakpoint::Address(a) => write!(f, "address {}", a),
+            Breakpoint::OpCode(op) => write!(f, "opcode {}", op),
+            Breakpoint::Write(from, to) => write!(f, "writes to {}..={}", from, to),
+        }
+    }
+}
+
+#[derive(PartialEq)]
+enum Limit {
+    Ticks(u128),
+    Rounds(u128),
+    Unbounded,
+}
+
+/// A line typed at the prompt.
+#[derive(PartialEq)]
+enum Command<const CORE_SIZE: usize> {
+    Nothing,
+    Help,
+    Quit,
+    Advance(Limit),
+    Back(u128),
+    Break(Breakpoint),
+    ListBreakpoints,
+    DeleteBreakpoint(usize),
+    /// From the address, the next process when `None`
+    List(Option<usize>, usize),
+    Set(usize, Instruction<CORE_SIZE>),
+    Queues,
+    SetQueue(usize, Vec<usize>),
+    Processes,
+}
+
+impl<const CORE_SIZE: usize> FromStr for Command<CORE_SIZE> {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Command<CORE_SIZE>, String> {
+        let args = line.split_whitespace().collect::<Vec<_>>();
+
+        Ok(match args.as_slice() {
+            [] => Command::Nothing,
+            ["help"] | ["h"] => Command::Help,
+            ["quit"] | ["exit"] => Command::Quit,
+            ["s", rest @ ..] => Command::Advance(Limit::Ticks(count(rest)?)),
+            ["r", rest @ ..] => Command::Advance(Limit::Rounds(count(rest)?)),
+            ["c"] => Command::Advance(Limit::Unbounded),
+            ["back", rest @ ..] => Command::Back(count(rest)?),
+            ["b", "op", op] => Command::Break(Breakpoint::OpCode(op.parse()?)),
+            ["b", "write", from, to] => Command::Break(Breakpoint::Write(
+                address::<CORE_SIZE>(from)?,
+                address::<CORE_SIZE>(to)?,
+            )),
+            ["b", addr] => Command::Break(Breakpoint::Address(address::<CORE_SIZE>(addr)?)),
+            ["bl"] => Command::ListBreakpoints,
+            ["bd", ix] => {
+                Command::DeleteBreakpoint(ix.parse().map_err(|_| format!("No breakpoint {}", ix))?)
+            }
+            ["l"] => Command::List(None, 10),
+            ["l", addr] => Command::List(Some(address::<CORE_SIZE>(addr)?), 10),
+            ["l", addr, n] => {
+                Command::List(Some(address::<CORE_SIZE>(addr)?), count(&[*n])? as usize)
+            }
+            ["set", addr, ..] => {
+                // the instruction is everything after the address
+                let code = &line[line.find(addr).unwrap() + addr.len()..];
+                let instructions = parse::<CORE_SIZE>(code.to_string())?;
+                if instructions.len() != 1 {
+                    return Err("Exactly one instruction is expected".to_string());
+                }
+
+                Command::Set(address::<CORE_SIZE>(addr)?, instructions[0])
+            }
+            ["q"] => Command::Queues,
+            ["q", warrior, addrs @ ..] => Command::SetQueue(
+                warrior
+                    .parse()
+                    .map_err(|_| format!("Invalid warrior {}", warrior))?,
+                addrs
+                    .iter()
+                    .map(|a| address::<CORE_SIZE>(a))
+                    .collect::<Result<_, _>>()?,
+            ),
+            ["ps"] => Command::Processes,
+            _ => return Err(format!("Unknown command `{}`, try `help`", line)),
+        })
+    }
+}
+
+/// Addresses written since it was last cleared.
+struct WriteWatcher {
+    writes: Arc<Mutex<Vec<usize>>>,
+}
+
+impl<const CORE_SIZE: usize> Observer<VmEvent<CORE_SIZE>> for WriteWatcher {
+    fn notify(&self, event: VmEvent<CORE_SIZE>) {
+        if let (EventType::Change(_), Some(address)) = (event.event_type, event.offset) {
+            self.writes.lock().unwrap().push(address);
+        }
+    }
+}
+
+/// Line based debugger in the spirit of the pMARS cdb, reading commands from
+/// stdin.
+pub struct Debugger<const CORE_SIZE: usize> {
+    vm: Vm<CORE_SIZE>,
+    history: History<CORE_SIZE>,
+    breakpoints: Vec<Breakpoint>,
+    writes: Arc<Mutex<Vec<usize>>>,
+}
+
+impl<const CORE_SIZE: usize> Debugger<CORE_SIZE> {
+    pub fn new(mut vm: Vm<CORE_SIZE>) -> Debugger<CORE_SIZE> {
+        let writes = Arc::new(Mutex::new(Vec::new()));
+        vm.register(Box::new(WriteWatcher {
+            writes: writes.clone(),
+        }));
+
+        let mut history = new_history();
+        history.record(&vm);
+
+        Debugger {
+            vm,
+            history,
+            breakpoints: Vec::new(),
+            writes,
+        }
+    }
+
+    pub fn run(&mut self) {
+        println!("Type `help` for the list of commands");
+        self.show_next();
+
+        let stdin = io::stdin();
+        let mut last = String::new();
+        loop {
+            print!("(cdb) ");
+            let _ = io::stdout().flush();
+
+            let mut line = String::new();
+            match stdin.lock().read_line(&mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+
+            let line = match line.trim() {
+                "" => last.clone(),
+                l => l.to_string(),
+            };
+
+            match self.execute(&line) {
+                Ok(true) => break,
+                Ok(false) => {}
+                Err(e) => println!("{}", e),
+            }
+            last = line;
+        }
+    }
+
+    /// Runs a command, `Ok(true)` when the debugger should exit.
+    fn execute(&mut self, line: &str) -> Result<bool, String> {
+        match line.parse::<Command<CORE_SIZE>>()? {
+            Command::Nothing => {}
+            Command::Help => println!("{}", HELP),
+            Command::Quit => return Ok(true),
+            Command::Advance(limit) => self.advance(limit),
+            Command::Back(n) => {
+                self.history.rewind(&mut self.vm, n)?;
+                self.show_next();
+            }
+            Command::Break(breakpoint) => self.add_breakpoint(breakpoint),
+            Command::ListBreakpoints => {
+                for (ix, b) in self.breakpoints.iter().enumerate() {
+                    println!("{}: {}", ix, b);
+                }
+            }
+            Command::DeleteBreakpoint(ix) => {
+                if ix >= self.breakpoints.len() {
+                    return Err(format!("No breakpoint {}", ix));
+                }
+                self.breakpoints.remove(ix);
+            }
+            Command::List(addr, n) => self.list(addr.unwrap_or(self.current_address()), n),
+            Command::Set(addr, instruction) => {
+                self.vm.set_cell(addr, instruction);
+                self.clear_history();
+                self.list(addr, 1);
+            }
+            Command::Queues => self.show_queues(),
+            Command::SetQueue(warrior, addrs) => {
+                self.vm.set_processes(warrior, &addrs)?;
+                self.clear_history();
+                self.show_queues();
+            }
+            Command::Processes => self.show_processes(),
+        }
+
+        Ok(false)
+    }
+
+    /// Plays until `limit` is reached, a breakpoint is hit or the battle
+    /// ends. The first instruction is always executed, so that continuing
+    /// from a breakpoint moves on.
+    fn advance(&mut self, limit: Limit) {
+        let start_ticks = self.vm.ticks();
+        let start_round = self.vm.cycles();
+
+        loop {
+            self.writes.lock().unwrap().clear();
+            let ended = match self.vm.play(1) {
+                Outcome::Running => None,
+                Outcome::Win(_, w) => Some(format!("{} won", w.name)),
+                Outcome::Tie => Some("Tie".to_string()),
+            };
+            self.history.record(&self.vm);
+
+            if let Some(ended) = ended {
+                println!("Battle over after {} rounds: {}", self.vm.cycles(), ended);
+                return;
+            }
+
+            if let Some(ix) = self.hit_breakpoint() {
+                println!("Breakpoint {}: {}", ix, self.breakpoints[ix]);
+                break;
+            }
+
+            let done = match limit {
+                Limit::Ticks(n) => self.vm.ticks() >= start_ticks + n,
+                Limit::Rounds(n) => self.vm.cycles() >= start_round + n,
+                Limit::Unbounded => false,
+            };
+            if done {
+                break;
+            }
+        }
+
+        self.show_next();
+    }
+
+    fn hit_breakpoint(&self) -> Option<usize> {
+        let writes = self.writes.lock().unwrap();
+
+        self.breakpoints
+            .iter()
+            .position(|b| b.hit(&self.vm, &writes))
+    }
+
+    fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        println!("Breakpoint {}: {}", self.breakpoints.len(), breakpoint);
+        self.breakpoints.push(breakpoint);
+    }
+
+    /// Snapshots taken before an edit would undo it, start over.
+    fn clear_history(&mut self) {
+        self.history = new_history();
+        self.history.record(&self.vm);
+    }
+
+    fn current_address(&self) -> usize {
+        self.vm
+            .next_to_move()
+            .map(|(_, address)| address.value)
+            .unwrap_or(0)
+    }
+
+    fn show_next(&self) {
+        print!(
+            "Instruction {}, round {}: ",
+            self.vm.ticks(),
+            self.vm.cycles()
+        );
+        match self.vm.next_to_move() {
+            Some((warrior_id, address)) => {
+                println!("{} to move", self.vm.warriors()[warrior_id].name);
+                self.list(address.value, 1);
+            }
+            None => println!("nothing to execute"),
+        }
+    }
+
+    /// Prints `count` cells from `address`, marking those a process will
+    /// execute with the ids of their warriors.
+    fn list(&self, address: usize, count: usize) {
+        for offset in 0..count {
+            let address = (address + offset) % CORE_SIZE;
+            let owners = self
+                .vm
+                .alive_warriors()
+                .into_iter()
+                .filter(|&w| {
+                    self.vm
+                        .processes(w)
+                        .map(|q| q.iter().any(|p| p.value == address))
+                        == Some(true)
+                })
+                .map(|w| w.to_string())
+                .collect::<Vec<_>>();
+
+            let line = format!("{:>6}  {:<24}", address, self.vm.cell(address).to_string());
+            if owners.is_empty() {
+                println!("{}", line.trim_end());
+            } else {
+                println!("{}<- {}", line, owners.join(", "));
+            }
+        }
+    }
+
+    fn show_queues(&self) {
+        for warrior_id in self.vm.alive_warriors() {
+            let queue = self.vm.processes(warrior_id).unwrap();
+            println!(
+                "{} {}: {}",
+                warrior_id,
+                self.vm.warriors()[warrior_id].name,
+                queue
+                    .iter()
+                    .map(|a| a.value.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            );
+        }
+    }
+
+    fn show_processes(&self) {
+        for warrior_id in self.vm.alive_warriors() {
+            let queue = self.vm.processes(warrior_id).unwrap();
+            println!(
+                "{} {}, {} processes",
+                warrior_id,
+                self.vm.warriors()[warrior_id].name,
+                queue.len()
+            );
+
+            for address in queue.iter().take(SHOWN_PROCESSES) {
+                self.list(address.value + CORE_SIZE - 2, 5);
+                println!();
+            }
+            if queue.len() > SHOWN_PROCESSES {
+                println!("... and {} more", queue.len() - SHOWN_PROCESSES);
+            }
+        }
+    }
+}
+
+/// As many snapshots as fit in `HISTORY_BYTES`, spread over
+/// `HISTORY_REACH` instructions: fewer and further apart on larger cores.
+fn new_history<const CORE_SIZE: usize>() -> History<CORE_SIZE> {
+    let snapshot = CORE_SIZE * size_of::<Instruction<CORE_SIZE>>();
+    let capacity = (HISTORY_BYTES / snapshot).clamp(4, 256);
+
+    History::new(capacity, HISTORY_REACH / capacity as u128)
+}
+
+fn count(args: &[&str]) -> Result<u128, String> {
+    match args {
+        [] => Ok(1),
+        [n] => n.parse().map_err(|_| format!("Invalid count {}", n)),
+        _ => Err("Too many arguments".to_string()),
+    }
+}
+
+/// Parses an address, negative ones counting from the end of the core.
+fn address<const CORE_SIZE: usize>(s: &str) -> Result<usize, String> {
+    let n = s
+        .parse::<i64>()
+        .map_err(|_| format!("Invalid address {}", s))?;
+
+    Ok(n.rem_euclid(CORE_SIZE as i64) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core_war::vm::config::VmConfig;
+    use core_war::vm::vms::{Placement, WarriorDefinition};
+
+    const DWARF: &str = "add #4, 3\nmov 2, @2\njmp -2, 0\ndat #0, #0";
+
+    fn debugger() -> Debugger<8000> {
+        let warriors = [DWARF, "jmp 0, 0"]
+            .iter()
+            .map(|code| WarriorDefinition::new("w".to_string(), parse(code.to_string()).unwrap()))
+            .collect();
+        let vm = Vm::with_placement(
+            VmConfig::default(),
+            warriors,
+            Placement::evenly_spaced(2, 8000),
+        )
+        .unwrap();
+
+        Debugger::new(vm)
+    }
+
+    fn command(line: &str) -> Result<Command<8000>, String> {
+        line.parse()
+    }
+
+    #[test]
+    fn commands_are_parsed() {
+        assert!(command("s") == Ok(Command::Advance(Limit::Ticks(1))));
+        assert!(command("r 3") == Ok(Command::Advance(Limit::Rounds(3))));
+        assert!(command("back 2") == Ok(Command::Back(2)));
+        assert!(command("b -1") == Ok(Command::Break(Breakpoint::Address(7999))));
+        assert!(command("b op spl") == Ok(Command::Break(Breakpoint::OpCode(OpCode::Spl))));
+        assert!(command("b write 10 20") == Ok(Command::Break(Breakpoint::Write(10, 20))));
+        assert!(command("bd 1") == Ok(Command::DeleteBreakpoint(1)));
+        assert!(command("q 1 5 6") == Ok(Command::SetQueue(1, vec![5, 6])));
+        assert!(command("   ") == Ok(Command::Nothing));
+    }
+
+    #[test]
+    fn bad_input_is_rejected() {
+        for line in [
+            "frobnicate",
+            "s x",
+            "s 1 2",
+            "b nowhere",
+            "b op nop",
+            "b write 10",
+            "bd x",
+            "set 10",
+            "set x mov 0, 1",
+            "set 10 mov 0, 1\nmov 0, 1",
+            "q x",
+        ] {
+            assert!(command(line).is_err(), "{}", line);
+        }
+
+        let mut debugger = debugger();
+        assert!(debugger.execute("bd 0").is_err());
+        assert!(debugger.execute("q 5 10").is_err());
+    }
+
+    #[test]
+    fn write_breakpoints_fire() {
+        let mut debugger = debugger();
+        // the dwarf bombs 4 cells further on each loop
+        debugger.execute("b write 100 120").unwrap();
+        debugger.execute("c").unwrap();
+
+        let writes = debugger.writes.lock().unwrap().clone();
+        assert_eq!(vec![103], writes);
+        assert!(debugger.vm.cell(103) == debugger.vm.cell(3));
+
+        // around the end of the core
+        let breakpoint = command("b write -10 14").unwrap();
+        let hit = |w| matches!(&breakpoint, Command::Break(b) if b.hit(&debugger.vm, &[w]));
+        assert!(hit(7995) && hit(0) && hit(14));
+        assert!(!hit(15) && !hit(7989));
+    }
+
+    #[test]
+    fn back_restores_a_snapshot() {
+        let mut debugger = debugger();
+        debugger.execute("s 10").unwrap();
+        let core = debugger.vm.core().to_vec();
+        let ticks = debugger.vm.ticks();
+
+        debugger.execute("s 7").unwrap();
+        assert!(debugger.vm.core() != &core[..]);
+        debugger.execute("back 7").unwrap();
+
+        assert_eq!(ticks, debugger.vm.ticks());
+        assert!(debugger.vm.core() == &core[..]);
+    }
+
+    #[test]
+    fn set_changes_a_cell() {
+        let mut debugger = debugger();
+        debugger.execute("set 100 mov.i $0, $1").unwrap();
+
+        let imp = parse::<8000>("mov 0, 1".to_string()).unwrap()[0];
+        assert!(debugger.vm.cell(100) == imp);
+
+        // stepping back does not undo the edit
+        debugger.execute("s 3").unwrap();
+        debugger.execute("back 3").unwrap();
+        assert!(debugger.vm.cell(100) == imp);
+    }
+}
@@ -0,0 +1,82 @@
+/// A 3x5 pixels font, enough for warrior names and counts on the SDL
+/// window without a font library. Letters are drawn uppercase.
+pub const GLYPH_WIDTH: usize = 3;
+pub const GLYPH_HEIGHT: usize = 5;
+
+/// Rows of each glyph from the top, the leftmost pixel in the highest bit
+const GLYPHS: [(char, [u8; 5]); 44] = [
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b110, 0b001, 0b010, 0b100, 0b111]),
+    ('3', [0b110, 0b001, 0b010, 0b001, 0b110]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b110, 0b001, 0b110]),
+    ('6', [0b011, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b010, 0b010, 0b010]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b110]),
+    (' ', [0b000, 0b000, 0b000, 0b000, 0b000]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('_', [0b000, 0b000, 0b000, 0b000, 0b111]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    ('(', [0b001, 0b010, 0b010, 0b010, 0b001]),
+    (')', [0b100, 0b010, 0b010, 0b010, 0b100]),
+    ('/', [0b001, 0b001, 0b010, 0b100, 0b100]),
+];
+
+/// Shown for the characters the font does not have
+const UNKNOWN: [u8; 5] = [0b110, 0b001, 0b010, 0b000, 0b010];
+
+/// Pixels of `text` set, as `(x, y)` from its top left corner, each glyph
+/// followed by a blank column.
+pub fn pixels(text: &str) -> Vec<(usize, usize)> {
+    text.chars()
+        .enumerate()
+        .flat_map(|(ix, c)| {
+            let c = c.to_ascii_uppercase();
+            let rows = GLYPHS
+                .iter()
+                .find(|(g, _)| *g == c)
+                .map(|(_, rows)| *rows)
+                .unwrap_or(UNKNOWN);
+
+            (0..GLYPH_HEIGHT).flat_map(move |y| {
+                (0..GLYPH_WIDTH)
+                    .filter(move |x| rows[y] & (1 << (GLYPH_WIDTH - 1 - x)) != 0)
+                    .map(move |x| (ix * (GLYPH_WIDTH + 1) + x, y))
+            })
+        })
+        .collect()
+}
+
+/// Width of `text` in pixels, its last blank column included
+pub fn width(text: &str) -> usize {
+    text.chars().count() * (GLYPH_WIDTH + 1)
+}
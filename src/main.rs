@@ -1,24 +1,460 @@
-mod vm;
-
-use std::sync::mpsc::channel;
-use vm::{
-    event::Observable,
-    parser::parse,
-    vms::{Vm, WarriorDefinition},
+use core_war::vm::{
+    benchmark::benchmark,
+    classifier::classify,
+    config::{core_size_of, Engine, FirstMover, VmConfig, CORE_SIZES},
+    event::{Observable, Observer, VmEvent},
+    evolver::{EvolverSettings, Population},
+    frames::{FrameRecorder, FrameWriter},
+    heatmap::{Heatmap, Layer},
+    hill::{Hill, HillReport},
+    optimizer::{optimize, Parameter, Search},
+    palette::Palette,
+    parser::{parse, set_constant},
+    rng::Rng,
+    runner::{MatchRunner, RoundOutcome},
+    stats::StatsCollector,
+    trace::{trace_core_size, Recorder, TraceHeader},
+    vms::{Outcome, Vm, WarriorDefinition},
 };
+use std::sync::mpsc::channel;
 mod console_display;
+mod controls;
+mod debugger;
+mod font;
+mod replay;
 mod sdl_display;
-use clap::Parser;
+mod tui_display;
+use clap::{Parser, Subcommand};
+use console_display::ConsoleDisplay;
+use controls::{DisplayCommand, DisplayControls, DisplayStatus};
+use debugger::Debugger;
 use sdl_display::SdlDisplay;
 use std::fs;
 use std::path::Path;
 use std::path::PathBuf;
+use std::str::FromStr;
 use std::thread;
 use std::time::Duration;
+use tui_display::TuiDisplay;
+
+const FRAME: Duration = Duration::from_millis(25);
+
+/// Calls `$f::<CORE_SIZE>($args)` for the supported core sizes, those of
+/// `CORE_SIZES`.
+macro_rules! with_core_size {
+    ($core_size:expr, $f:ident($($arg:expr),*)) => {
+        match $core_size {
+            800 => $f::<800>($($arg),*),
+            8000 => $f::<8000>($($arg),*),
+            8192 => $f::<8192>($($arg),*),
+            55440 => $f::<55440>($($arg),*),
+            n => println!("Unsupported core size {}, use one of {:?}", n, CORE_SIZES),
+        }
+    };
+}
 
 #[derive(Parser)]
 struct CliArgs {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+#[allow(clippy::large_enum_variant)]
+enum Command {
+    /// Battle the warriors (*.war) of a folder
+    Run(RunArgs),
+    /// Play back a trace recorded with `run --record`
+    Replay(ReplayArgs),
+    /// Step through a round of the warriors of a folder
+    Debug(DebugArgs),
+    /// Keep a king of the hill in a JSON file
+    Hill(HillArgs),
+    /// Score a warrior against the warriors (*.war) of a folder
+    Bench(BenchArgs),
+    /// Search the equ constants of a warrior scoring best against a folder
+    Optimize(OptimizeArgs),
+    /// Breed warriors in a population kept in a JSON file
+    Evolve(EvolveArgs),
+    /// Label the strategy of a warrior, or of the warriors of a folder
+    Classify(ClassifyArgs),
+}
+
+#[derive(Parser)]
+struct RunArgs {
+    path: PathBuf,
+    #[clap(flatten)]
+    config: ConfigArgs,
+    /// Instructions executed between two display frames
+    #[clap(long, default_value_t = 64)]
+    ticks_per_frame: i32,
+    /// Play this many rounds without display and print the results.
+    /// Each round prints its number and seed, pass them to --round and
+    /// --seed to watch that round.
+    #[clap(long)]
+    rounds: Option<usize>,
+    /// Threads playing the rounds of --rounds, all the CPUs by default
+    #[clap(long)]
+    threads: Option<usize>,
+    /// Number of the watched round, it decides who moves first when the
+    /// first mover rotates
+    #[clap(long, default_value_t = 0)]
+    round: usize,
+    /// sdl, tui, console or none
+    #[clap(long, default_value = "sdl")]
+    display: DisplayKind,
+    /// Colors of the warriors: classic, colorblind, wheel, or #rrggbb
+    /// colors separated by commas, more are generated when needed
+    #[clap(long, default_value = "classic")]
+    palette: Palette,
+    /// Write the watched round to a trace file
+    #[clap(long)]
+    record: Option<PathBuf>,
+    /// Write what each warrior did in the watched round to a JSON file
+    #[clap(long)]
+    stats: Option<PathBuf>,
+    /// Cycles between two samples of the cells owned, for --stats
+    #[clap(long, default_value_t = 100)]
+    stats_every: u128,
+    /// Write a heatmap of the core at the end of the watched round: a PNG,
+    /// PPM or CSV file by extension, repeated for several files
+    #[clap(long)]
+    heatmap: Vec<PathBuf>,
+    /// owner, writes or executions, what the heatmap images show
+    #[clap(long, default_value = "owner")]
+    heatmap_layer: Layer,
+    /// Pixels per cell in the heatmap images
+    #[clap(long, default_value_t = 4)]
+    heatmap_scale: usize,
+    /// Draw the watched round into an animated GIF for a .gif path, or
+    /// into numbered PNG files in this folder otherwise
+    #[clap(long)]
+    frames: Option<PathBuf>,
+    /// Cycles between two frames
+    #[clap(long, default_value_t = 100)]
+    frame_every: u128,
+    /// Pixels per cell in the frames
+    #[clap(long, default_value_t = 4)]
+    frame_scale: usize,
+    /// Hundredths of a second each frame of a GIF is shown
+    #[clap(long, default_value_t = 4)]
+    frame_delay: u16,
+}
+
+#[derive(Parser)]
+struct ReplayArgs {
+    trace: PathBuf,
+    /// sdl, tui, console or none
+    #[clap(long, default_value = "sdl")]
+    display: DisplayKind,
+    /// Colors of the warriors: classic, colorblind, wheel, or #rrggbb
+    /// colors separated by commas, more are generated when needed
+    #[clap(long, default_value = "classic")]
+    palette: Palette,
+    /// Cycles played back per frame, 0 for no delay
+    #[clap(long, default_value_t = 32)]
+    speed: u128,
+}
+
+#[derive(Parser)]
+struct DebugArgs {
+    path: PathBuf,
+    #[clap(flatten)]
+    config: ConfigArgs,
+    /// Number of the debugged round, as for `run --round`
+    #[clap(long, default_value_t = 0)]
+    round: usize,
+}
+
+#[derive(Parser)]
+struct BenchArgs {
+    warrior: PathBuf,
+    /// Folder of the reference warriors
+    suite: PathBuf,
+    #[clap(flatten)]
+    config: ConfigArgs,
+    /// Rounds played against each reference warrior
+    #[clap(long, default_value_t = 100)]
+    rounds: usize,
+    /// Threads playing the rounds, all the CPUs by default
+    #[clap(long)]
+    threads: Option<usize>,
+    /// Print the report as JSON
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Parser)]
+struct OptimizeArgs {
+    warrior: PathBuf,
+    /// Folder of the reference warriors
+    suite: PathBuf,
+    /// Values tried for an equ constant, as name=from..to or
+    /// name=from..to:step, repeated for each constant
+    #[clap(long = "range", required = true)]
+    ranges: Vec<Parameter>,
+    /// Try this many random combinations instead of all of them
+    #[clap(long)]
+    random: Option<usize>,
+    #[clap(flatten)]
+    config: ConfigArgs,
+    /// Rounds played against each reference warrior
+    #[clap(long, default_value_t = 100)]
+    rounds: usize,
+    /// Threads playing the rounds, all the CPUs by default
+    #[clap(long)]
+    threads: Option<usize>,
+    /// Candidates printed, best first
+    #[clap(long, default_value_t = 10)]
+    top: usize,
+    /// Write the warrior with the best constants to this file
+    #[clap(long)]
+    output: Option<PathBuf>,
+    /// Print the report as JSON
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Parser)]
+struct ClassifyArgs {
+    /// A warrior or a folder of warriors
     path: PathBuf,
+    #[clap(flatten)]
+    config: ConfigArgs,
+    /// Cycles the warrior is watched for
+    #[clap(long, default_value_t = 4000)]
+    cycles: u128,
+    /// Print the classifications and the features behind them as JSON
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Parser)]
+struct EvolveArgs {
+    #[clap(subcommand)]
+    command: EvolveCommand,
+}
+
+#[derive(Subcommand)]
+enum EvolveCommand {
+    /// Create a population of random warriors
+    New(EvolveNewArgs),
+    /// Breed and evaluate generations
+    Run(EvolveRunArgs),
+}
+
+#[derive(Parser)]
+struct EvolveNewArgs {
+    file: PathBuf,
+    #[clap(flatten)]
+    config: ConfigArgs,
+    /// Warriors in the population
+    #[clap(long, default_value_t = 30)]
+    size: usize,
+    /// Instructions of the longest warrior bred
+    #[clap(long = "warrior-length", default_value_t = 10)]
+    warrior_length: usize,
+    /// Chance of each instruction to be mutated
+    #[clap(long, default_value_t = 0.1)]
+    mutation_rate: f64,
+    /// Best warriors kept unchanged from a generation to the next
+    #[clap(long, default_value_t = 3)]
+    elite: usize,
+    /// Rounds of each battle judging fitness
+    #[clap(long, default_value_t = 10)]
+    rounds: usize,
+    /// Members of the population each warrior battles, 0 only with a --suite
+    #[clap(long, default_value_t = 4)]
+    sparring: usize,
+}
+
+#[derive(Parser)]
+struct EvolveRunArgs {
+    file: PathBuf,
+    /// Generations bred, the population is saved after each of them
+    #[clap(long, default_value_t = 10)]
+    generations: usize,
+    /// Folder of reference warriors every warrior battles
+    #[clap(long)]
+    suite: Option<PathBuf>,
+    /// Threads playing the rounds, all the CPUs by default
+    #[clap(long)]
+    threads: Option<usize>,
+    /// Folder where the best warriors are written as redcode
+    #[clap(long)]
+    output: Option<PathBuf>,
+    /// Number of warriors written to --output
+    #[clap(long, default_value_t = 5)]
+    keep: usize,
+}
+
+#[derive(Parser)]
+struct HillArgs {
+    #[clap(subcommand)]
+    command: HillCommand,
+}
+
+#[derive(Subcommand)]
+enum HillCommand {
+    /// Create an empty hill
+    New(HillNewArgs),
+    /// Play a warrior against every member and insert it
+    Submit(HillSubmitArgs),
+    /// Print the ranking
+    Report(HillReportArgs),
+}
+
+#[derive(Parser)]
+struct HillNewArgs {
+    file: PathBuf,
+    #[clap(flatten)]
+    config: ConfigArgs,
+    /// Number of warriors kept on the hill
+    #[clap(long, default_value_t = 10)]
+    size: usize,
+    /// Rounds played against each member
+    #[clap(long, default_value_t = 100)]
+    rounds: usize,
+}
+
+#[derive(Parser)]
+struct HillSubmitArgs {
+    file: PathBuf,
+    warrior: PathBuf,
+    /// Threads playing the rounds, all the CPUs by default
+    #[clap(long)]
+    threads: Option<usize>,
+    /// Print the ranking as JSON
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Parser)]
+struct HillReportArgs {
+    file: PathBuf,
+    /// Print the ranking as JSON
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Clone, Copy)]
+enum DisplayKind {
+    Sdl,
+    Tui,
+    Console,
+    None,
+}
+
+impl FromStr for DisplayKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DisplayKind, String> {
+        match s {
+            "sdl" => Ok(DisplayKind::Sdl),
+            "tui" => Ok(DisplayKind::Tui),
+            "console" => Ok(DisplayKind::Console),
+            "none" => Ok(DisplayKind::None),
+            _ => Err(format!("Invalid display: {}", s)),
+        }
+    }
+}
+
+impl DisplayKind {
+    /// The display of the warriors named `names` starting at `starts`, with
+    /// its controls when it is interactive
+    fn create<const CORE_SIZE: usize>(
+        self,
+        names: Vec<String>,
+        starts: Vec<usize>,
+        palette: &Palette,
+    ) -> (
+        Option<Box<dyn Observer<VmEvent<CORE_SIZE>>>>,
+        Option<DisplayControls>,
+    ) {
+        match self {
+            DisplayKind::Sdl => {
+                let (display, controls) = SdlDisplay::interactive(names, starts, palette.clone());
+                (Some(display), Some(controls))
+            }
+            DisplayKind::Tui => {
+                let (display, controls) = TuiDisplay::interactive(names, starts, palette.clone());
+                (Some(display), Some(controls))
+            }
+            DisplayKind::Console => (
+                Some(ConsoleDisplay::new(names, &starts, CORE_SIZE, palette)),
+                None,
+            ),
+            DisplayKind::None => (None, None),
+        }
+    }
+}
+
+/// VM settings read from an optional TOML/JSON file and overridden by flags.
+#[derive(Parser)]
+struct ConfigArgs {
+    /// TOML or JSON file holding the VM configuration
+    #[clap(long)]
+    config: Option<PathBuf>,
+    /// One of 800, 8000, 8192 or 55440
+    #[clap(long)]
+    core_size: Option<usize>,
+    #[clap(long)]
+    max_processes: Option<usize>,
+    #[clap(long)]
+    max_cycles: Option<u128>,
+    #[clap(long)]
+    max_length: Option<usize>,
+    #[clap(long)]
+    min_distance: Option<usize>,
+    #[clap(long)]
+    read_limit: Option<usize>,
+    #[clap(long)]
+    write_limit: Option<usize>,
+    #[clap(long)]
+    max_warriors: Option<usize>,
+    #[clap(long)]
+    seed: Option<u64>,
+    /// Who moves first in each round: rotate or random
+    #[clap(long)]
+    first_mover: Option<FirstMover>,
+    /// Instruction dispatch: reference or fast
+    #[clap(long)]
+    engine: Option<Engine>,
+}
+
+impl ConfigArgs {
+    fn to_config(&self) -> Result<VmConfig, String> {
+        let mut config = match &self.config {
+            Some(path) => VmConfig::load(path)?,
+            None => VmConfig::default(),
+        };
+
+        if let Some(core_size) = self.core_size {
+            // limits default to the whole core unless set explicitly
+            if config.read_limit == config.core_size {
+                config.read_limit = core_size;
+            }
+            if config.write_limit == config.core_size {
+                config.write_limit = core_size;
+            }
+            config.core_size = core_size;
+        }
+
+        config.max_processes = self.max_processes.unwrap_or(config.max_processes);
+        config.max_cycles = self.max_cycles.unwrap_or(config.max_cycles);
+        config.max_length = self.max_length.unwrap_or(config.max_length);
+        config.min_distance = self.min_distance.unwrap_or(config.min_distance);
+        config.read_limit = self.read_limit.unwrap_or(config.read_limit);
+        config.write_limit = self.write_limit.unwrap_or(config.write_limit);
+        config.max_warriors = self.max_warriors.unwrap_or(config.max_warriors);
+        config.seed = self.seed.or(config.seed);
+        config.first_mover = self.first_mover.unwrap_or(config.first_mover);
+        config.engine = self.engine.unwrap_or(config.engine);
+
+        config.validate()?;
+
+        Ok(config)
+    }
 }
 
 fn read_warrior<const CORE_SIZE: usize>(path: &str) -> Result<WarriorDefinition<CORE_SIZE>, ()> {
@@ -28,44 +464,601 @@ fn read_warrior<const CORE_SIZE: usize>(path: &str) -> Result<WarriorDefinition<
         .to_str()
         .unwrap()
         .to_string();
-    let body = fs::read_to_string(path).expect(&format!("Can not open file {}", path));
-    let instructions = parse(body).expect(&format!("Can not parse instructions in file {}", path));
+    let body = fs::read_to_string(path).unwrap_or_else(|_| panic!("Can not open file {}", path));
+    let instructions =
+        parse(body).unwrap_or_else(|_| panic!("Can not parse instructions in file {}", path));
 
     Ok(WarriorDefinition::new(name, instructions))
 }
 
 fn main() {
-    let args = CliArgs::parse();
-    let path = fs::read_dir(args.path).unwrap();
+    match CliArgs::parse().command {
+        Command::Run(args) => {
+            if let Some(config) = config_or_print(&args.config) {
+                with_core_size!(config.core_size, run(&args, config));
+            }
+        }
+        Command::Debug(args) => {
+            if let Some(config) = config_or_print(&args.config) {
+                with_core_size!(config.core_size, debug(&args, config));
+            }
+        }
+        Command::Replay(args) => match trace_core_size(&args.trace) {
+            Ok(core_size) => with_core_size!(core_size, play_back(&args)),
+            Err(e) => println!("{}", e),
+        },
+        Command::Bench(args) => {
+            if let Some(config) = config_or_print(&args.config) {
+                with_core_size!(config.core_size, bench(&args, config));
+            }
+        }
+        Command::Optimize(args) => {
+            if let Some(config) = config_or_print(&args.config) {
+                with_core_size!(config.core_size, optimize_constants(&args, config));
+            }
+        }
+        Command::Classify(args) => {
+            if let Some(config) = config_or_print(&args.config) {
+                with_core_size!(config.core_size, classify_warriors(&args, config));
+            }
+        }
+        Command::Evolve(args) => match args.command {
+            EvolveCommand::New(args) => {
+                if let Some(config) = config_or_print(&args.config) {
+                    with_core_size!(config.core_size, new_population(&args, config));
+                }
+            }
+            EvolveCommand::Run(args) => match core_size_of(&args.file) {
+                Ok(core_size) => with_core_size!(core_size, evolve(&args)),
+                Err(e) => println!("{}", e),
+            },
+        },
+        Command::Hill(args) => match args.command {
+            HillCommand::New(args) => {
+                if let Some(config) = config_or_print(&args.config) {
+                    with_core_size!(config.core_size, new_hill(&args, config));
+                }
+            }
+            HillCommand::Submit(args) => match core_size_of(&args.file) {
+                Ok(core_size) => with_core_size!(core_size, submit_to_hill(&args)),
+                Err(e) => println!("{}", e),
+            },
+            HillCommand::Report(args) => match core_size_of(&args.file) {
+                Ok(core_size) => with_core_size!(core_size, report_hill(&args)),
+                Err(e) => println!("{}", e),
+            },
+        },
+    }
+}
+
+fn default_threads() -> usize {
+    thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+fn config_or_print(args: &ConfigArgs) -> Option<VmConfig> {
+    match args.to_config() {
+        Ok(c) => Some(c),
+        Err(e) => {
+            println!("Invalid configuration: {}", e);
+            None
+        }
+    }
+}
 
-    let warriors: Vec<WarriorDefinition<8000>> = path
+fn play_back<const CORE_SIZE: usize>(args: &ReplayArgs) {
+    let display = |names, starts| args.display.create(names, starts, &args.palette);
+    if let Err(e) = replay::replay::<CORE_SIZE, _>(&args.trace, display, args.speed, FRAME) {
+        println!("Replay failed: {}", e);
+    }
+}
+
+fn read_warriors<const CORE_SIZE: usize>(path: &Path) -> Vec<WarriorDefinition<CORE_SIZE>> {
+    fs::read_dir(path)
+        .unwrap()
         .map(|f| f.unwrap().path())
         .map(|path| path.to_str().unwrap().to_string())
         .filter(|path| path.ends_with(".war"))
         .filter_map(|path| read_warrior(&path).ok())
-        .collect();
+        .collect()
+}
+
+fn debug<const CORE_SIZE: usize>(args: &DebugArgs, config: VmConfig) {
+    let seed = config.seed.unwrap_or_else(Rng::entropy_seed);
+    println!("Seed: {}", seed);
+    let runner = MatchRunner::new(config, read_warriors::<CORE_SIZE>(&args.path));
+
+    match runner.round_vm(args.round, seed) {
+        Ok((vm, _)) => Debugger::new(vm).run(),
+        Err(e) => println!("Can not start the game: {}", e),
+    }
+}
+
+fn run<const CORE_SIZE: usize>(args: &RunArgs, config: VmConfig) {
+    let warriors = read_warriors::<CORE_SIZE>(&args.path);
+
+    if let Some(rounds) = args.rounds {
+        let threads = args.threads.unwrap_or_else(default_threads);
+        play_match(MatchRunner::new(config, warriors), rounds, threads);
+        return;
+    }
 
-    let (timer_tx, timer_rx) = channel();
-    thread::spawn(move || loop {
-        timer_tx.send(()).unwrap();
-        thread::sleep(Duration::from_millis(25));
+    // a single watched round uses the seed as is, so that a round seed
+    // printed by a match replays that very round
+    let seed = config.seed.unwrap_or_else(Rng::entropy_seed);
+    println!("Seed: {}", seed);
+    let names = warriors.iter().map(|w| w.name.clone()).collect::<Vec<_>>();
+    let runner = MatchRunner::new(config.clone(), warriors.clone());
+
+    let (mut vm, placement) = match runner.round_vm(args.round, seed) {
+        Ok(r) => r,
+        Err(e) => {
+            println!("Can not start the game: {}", e);
+            return;
+        }
+    };
+    let starts = placement.positions.clone();
+
+    let stats = args.stats.as_ref().map(|_| {
+        let collector = StatsCollector::new(names.clone(), args.stats_every);
+        let handle = collector.handle();
+        vm.register(collector);
+        handle
+    });
+
+    let heatmap = (!args.heatmap.is_empty()).then(|| {
+        let heatmap = Heatmap::new();
+        let handle = heatmap.handle();
+        vm.register(heatmap);
+        handle
+    });
+
+    let frames =
+        match args.frames.as_ref().map(|path| {
+            FrameWriter::create(path, CORE_SIZE, args.frame_scale.max(1), args.frame_delay)
+        }) {
+            Some(Ok(writer)) => {
+                let recorder = FrameRecorder::new(writer, args.frame_every, args.palette.clone());
+                let handle = recorder.handle();
+                vm.register(recorder);
+                Some(handle)
+            }
+            Some(Err(e)) => {
+                println!("{}", e);
+                return;
+            }
+            None => None,
+        };
+
+    if let Some(path) = args.record.as_ref() {
+        let header = TraceHeader {
+            config,
+            seed,
+            round: args.round,
+            placement,
+            warriors,
+        };
+
+        match Recorder::create(path, &header) {
+            Ok(recorder) => vm.register(recorder),
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        }
+    }
+
+    // without a display there is nothing to watch, play at full speed
+    let (display, controls) = args.display.create(names, starts, &args.palette);
+    let timer = display.map(|display| {
+        vm.register(display);
+
+        let (timer_tx, timer_rx) = channel();
+        thread::spawn(move || loop {
+            if timer_tx.send(()).is_err() {
+                break;
+            }
+            thread::sleep(FRAME);
+        });
+        timer_rx
     });
 
-    //let console_display = ConsoleDisplay::new();
-    let sdl_display = SdlDisplay::new();
+    let mut ticks_per_frame = args.ticks_per_frame.max(1);
+    let mut paused = false;
+    let mut hovered = None;
+    let mut selected = None;
+    let result = 'game_loop: loop {
+        if let Some(timer) = &timer {
+            timer.recv().unwrap();
+        }
+
+        let mut step = false;
+        for command in controls.iter().flat_map(|c| c.commands.try_iter()) {
+            match command {
+                DisplayCommand::TogglePause => paused = !paused,
+                DisplayCommand::Step => {
+                    paused = true;
+                    step = true;
+                }
+                DisplayCommand::Faster => ticks_per_frame = ticks_per_frame.saturating_mul(2),
+                DisplayCommand::Slower => ticks_per_frame = (ticks_per_frame / 2).max(1),
+                DisplayCommand::Hover(address) => hovered = address,
+                DisplayCommand::Select(warrior_id) => selected = warrior_id,
+                DisplayCommand::Quit => {
+                    break 'game_loop format!("Game stopped after {} rounds", vm.cycles());
+                }
+            }
+        }
+
+        if !paused || step {
+            match vm.play(if step { 1 } else { ticks_per_frame }) {
+                Outcome::Running => {}
+                Outcome::Win(_, p) => {
+                    break 'game_loop format!("Game ended! Player {} won!", p.name);
+                }
+                Outcome::Tie => {
+                    break 'game_loop format!("Game ended in a tie after {} rounds", vm.cycles());
+                }
+            }
+        }
+
+        if let Some(controls) = controls.as_ref() {
+            let process = selected
+                .and_then(|id| vm.processes(id))
+                .and_then(|queue| queue.front())
+                .map(|address| address.value);
+            let _ = controls.status.send(DisplayStatus {
+                line: status_line(&vm, ticks_per_frame, paused, hovered),
+                listing: process.map(|p| listing(&vm, p)).unwrap_or_default(),
+                process,
+            });
+        }
+    };
+
+    // the terminal must be back before anything is printed
+    if let Some(controls) = controls {
+        controls.close();
+    }
+    println!("{}", result);
+
+    if let (Some(path), Some(stats)) = (args.stats.as_ref(), stats) {
+        if let Err(e) = fs::write(path, stats.to_json()) {
+            println!("Can not write {}: {}", path.display(), e);
+        }
+    }
 
-    let mut vm = Vm::<8000, 32>::new(warriors).unwrap();
-    vm.register(sdl_display);
-    'game_loop: loop {
-        timer_rx.recv().unwrap();
-        match vm.play(64) {
-            None => {
-                println!("Played {} rounds", vm.round);
+    if let Some(heat) = heatmap.map(|h| h.heat()) {
+        for path in args.heatmap.iter() {
+            let is_csv = path
+                .extension()
+                .map(|e| e.eq_ignore_ascii_case("csv"))
+                .unwrap_or(false);
+            let result = if is_csv {
+                fs::write(path, heat.to_csv())
+                    .map_err(|e| format!("Can not write {}: {}", path.display(), e))
+            } else {
+                heat.render(args.heatmap_layer, args.heatmap_scale.max(1), &args.palette)
+                    .save(path)
+            };
+            if let Err(e) = result {
+                println!("{}", e);
             }
-            Some(p) => {
-                println!("Game ended! Player {} won!", p.name);
-                break 'game_loop;
+        }
+    }
+
+    if let Some(frames) = frames {
+        match frames.finish() {
+            Ok(count) => println!("{} frames written", count),
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+/// Cycle, speed and the cell under the pointer, for an interactive display
+fn status_line<const CORE_SIZE: usize>(
+    vm: &Vm<CORE_SIZE>,
+    ticks_per_frame: i32,
+    paused: bool,
+    hovered: Option<usize>,
+) -> String {
+    let mut line = format!(
+        "cycle {}, {} instructions per frame{}",
+        vm.cycles(),
+        ticks_per_frame,
+        if paused { ", paused" } else { "" }
+    );
+
+    if let Some(address) = hovered {
+        line.push_str(&format!(" | {}: {}", address, vm.cell(address)));
+        for (id, warrior) in vm.warriors().iter().enumerate() {
+            let here = vm
+                .processes(id)
+                .map(|p| p.iter().filter(|a| a.value == address).count())
+                .unwrap_or(0);
+            if here > 0 {
+                line.push_str(&format!(", {} process(es) of {}", here, warrior.name));
             }
         }
     }
+
+    line
+}
+
+/// Cells around `address`, as listed by an interactive display
+fn listing<const CORE_SIZE: usize>(vm: &Vm<CORE_SIZE>, address: usize) -> Vec<(usize, String)> {
+    (0..40)
+        .map(|ix| (address + CORE_SIZE + ix - 10) % CORE_SIZE)
+        .map(|a| (a, vm.cell(a).to_string()))
+        .collect()
+}
+
+fn play_match<const CORE_SIZE: usize>(
+    runner: MatchRunner<CORE_SIZE>,
+    rounds: usize,
+    threads: usize,
+) {
+    println!("Match seed: {}", runner.seed());
+
+    let result = match runner.play_parallel(rounds, threads) {
+        Ok(r) => r,
+        Err(e) => {
+            println!("Can not start the game: {}", e);
+            return;
+        }
+    };
+
+    for round in result.rounds.iter() {
+        let outcome = match &round.outcome {
+            RoundOutcome::Win(w) => format!("{} won", result.warriors[*w]),
+            RoundOutcome::Tie(_) => "tie".to_string(),
+        };
+        println!(
+            "Round {} (seed {}): {} moved first, {} after {} cycles",
+            round.round, round.seed, result.warriors[round.first_mover], outcome, round.cycles
+        );
+    }
+
+    for (name, score) in result.warriors.iter().zip(result.scores()) {
+        println!(
+            "{}: {} wins, {} losses, {} ties",
+            name, score.wins, score.losses, score.ties
+        );
+    }
+}
+
+fn new_hill<const CORE_SIZE: usize>(args: &HillNewArgs, config: VmConfig) {
+    if args.file.exists() {
+        println!("{} already exists", args.file.display());
+        return;
+    }
+
+    let saved = Hill::<CORE_SIZE>::new(config, args.size, args.rounds)
+        .and_then(|hill| hill.save(&args.file));
+    if let Err(e) = saved {
+        println!("Can not create the hill: {}", e);
+    }
+}
+
+fn submit_to_hill<const CORE_SIZE: usize>(args: &HillSubmitArgs) {
+    let mut hill = match Hill::<CORE_SIZE>::load(&args.file) {
+        Ok(h) => h,
+        Err(e) => {
+            println!("Can not load the hill: {}", e);
+            return;
+        }
+    };
+    let warrior = match read_warrior(args.warrior.to_str().unwrap()) {
+        Ok(w) => w,
+        Err(_) => return,
+    };
+    let name = warrior.name.clone();
+
+    let submission = match hill.submit(warrior, args.threads.unwrap_or_else(default_threads)) {
+        Ok(s) => s,
+        Err(e) => {
+            println!("Can not submit {}: {}", name, e);
+            return;
+        }
+    };
+    if let Err(e) = hill.save(&args.file) {
+        println!("{}", e);
+        return;
+    }
+
+    if !args.json {
+        match submission.rank {
+            Some(rank) => println!("{} enters the hill at rank {}", name, rank),
+            None => println!("{} did not make it", name),
+        }
+        if let Some(pushed_off) = submission.pushed_off.filter(|p| p.name != name) {
+            println!(
+                "{} pushed off after {} challengers",
+                pushed_off.name, pushed_off.age
+            );
+        }
+    }
+    print_hill_report(hill.report(), args.json);
+}
+
+fn report_hill<const CORE_SIZE: usize>(args: &HillReportArgs) {
+    match Hill::<CORE_SIZE>::load(&args.file) {
+        Ok(hill) => print_hill_report(hill.report(), args.json),
+        Err(e) => println!("Can not load the hill: {}", e),
+    }
+}
+
+fn print_hill_report(report: HillReport, json: bool) {
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    } else {
+        print!("{}", report);
+    }
+}
+
+fn bench<const CORE_SIZE: usize>(args: &BenchArgs, config: VmConfig) {
+    let warrior = match read_warrior::<CORE_SIZE>(args.warrior.to_str().unwrap()) {
+        Ok(w) => w,
+        Err(_) => return,
+    };
+    let mut suite = read_warriors(&args.suite);
+    suite.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let threads = args.threads.unwrap_or_else(default_threads);
+    match benchmark(&config, &warrior, &suite, args.rounds, threads) {
+        Ok(report) if args.json => {
+            println!("{}", serde_json::to_string_pretty(&report).unwrap())
+        }
+        Ok(report) => print!("{}", report),
+        Err(e) => println!("Can not run the benchmark: {}", e),
+    }
+}
+
+fn optimize_constants<const CORE_SIZE: usize>(args: &OptimizeArgs, config: VmConfig) {
+    let source = match fs::read_to_string(&args.warrior) {
+        Ok(s) => s,
+        Err(e) => {
+            println!("Can not open file {}: {}", args.warrior.display(), e);
+            return;
+        }
+    };
+    let name = args.warrior.file_stem().unwrap().to_str().unwrap();
+    let mut suite = read_warriors::<CORE_SIZE>(&args.suite);
+    suite.sort_by(|a, b| a.name.cmp(&b.name));
+    let search = match args.random {
+        Some(samples) => Search::Random(samples),
+        None => Search::Grid,
+    };
+    let threads = args.threads.unwrap_or_else(default_threads);
+
+    let mut report = match optimize(
+        &config,
+        name,
+        &source,
+        &args.ranges,
+        search,
+        &suite,
+        args.rounds,
+        threads,
+    ) {
+        Ok(r) => r,
+        Err(e) => {
+            println!("Can not optimize {}: {}", name, e);
+            return;
+        }
+    };
+
+    if let (Some(path), Some(best)) = (args.output.as_ref(), report.candidates.first()) {
+        let tuned = best.constants.iter().try_fold(source.clone(), |tuned, c| {
+            set_constant(&tuned, &c.name, c.value)
+        });
+        if let Err(e) = tuned.and_then(|t| fs::write(path, t).map_err(|e| e.to_string())) {
+            println!("Can not write {}: {}", path.display(), e);
+        }
+    }
+
+    report.candidates.truncate(args.top);
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    } else {
+        print!("{}", report);
+    }
+}
+
+fn new_population<const CORE_SIZE: usize>(args: &EvolveNewArgs, config: VmConfig) {
+    if args.file.exists() {
+        println!("{} already exists", args.file.display());
+        return;
+    }
+
+    let settings = EvolverSettings {
+        size: args.size,
+        max_length: args.warrior_length,
+        mutation_rate: args.mutation_rate,
+        elite: args.elite,
+        rounds: args.rounds,
+        sparring: args.sparring,
+    };
+    let saved = Population::<CORE_SIZE>::new(config, settings)
+        .and_then(|population| population.save(&args.file));
+    if let Err(e) = saved {
+        println!("Can not create the population: {}", e);
+    }
+}
+
+fn evolve<const CORE_SIZE: usize>(args: &EvolveRunArgs) {
+    let mut population = match Population::<CORE_SIZE>::load(&args.file) {
+        Ok(p) => p,
+        Err(e) => {
+            println!("Can not load the population: {}", e);
+            return;
+        }
+    };
+    let suite = match args.suite.as_ref() {
+        Some(path) => read_warriors(path),
+        None => Vec::new(),
+    };
+    let threads = args.threads.unwrap_or_else(default_threads);
+
+    for _ in 0..args.generations {
+        match population.step(&suite, threads) {
+            Ok(report) => println!("{}", report),
+            Err(e) => {
+                println!("Evolution failed: {}", e);
+                return;
+            }
+        }
+        if let Err(e) = population.save(&args.file) {
+            println!("{}", e);
+            return;
+        }
+    }
+
+    if let Some(folder) = args.output.as_ref() {
+        if let Err(e) = fs::create_dir_all(folder) {
+            println!("Can not create {}: {}", folder.display(), e);
+            return;
+        }
+        for member in population.members.iter().take(args.keep) {
+            let path = folder.join(format!("{}.war", member.warrior.name));
+            if let Err(e) = fs::write(&path, member.warrior.to_string()) {
+                println!("Can not write {}: {}", path.display(), e);
+                return;
+            }
+        }
+    }
+}
+
+fn classify_warriors<const CORE_SIZE: usize>(args: &ClassifyArgs, config: VmConfig) {
+    let mut warriors = if args.path.is_dir() {
+        read_warriors::<CORE_SIZE>(&args.path)
+    } else {
+        read_warrior(args.path.to_str().unwrap())
+            .into_iter()
+            .collect()
+    };
+    warriors.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let classifications = match warriors
+        .iter()
+        .map(|warrior| classify(&config, warrior, args.cycles))
+        .collect::<Result<Vec<_>, String>>()
+    {
+        Ok(c) => c,
+        Err(e) => {
+            println!("Can not classify: {}", e);
+            return;
+        }
+    };
+
+    if args.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&classifications).unwrap()
+        );
+    } else {
+        for classification in classifications.iter() {
+            println!("{}", classification);
+        }
+    }
 }
@@ -0,0 +1,206 @@
+use crate::controls::{DisplayCommand, DisplayControls};
+use core_war::vm::event::{Observer, VmEvent};
+use core_war::vm::trace::TraceReader;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+/// Feeds the events of a trace to the display `display` makes for the names
+/// of its warriors and where they start, `cycles_per_frame` cycles every
+/// `frame` as the live game loop does, or as fast as possible when
+/// `cycles_per_frame` is 0. No VM is involved, so of the display `controls`
+/// only pause and quit are followed.
+pub fn replay<const CORE_SIZE: usize, F>(
+    path: &Path,
+    display: F,
+    cycles_per_frame: u128,
+    frame: Duration,
+) -> Result<(), String>
+where
+    F: FnOnce(
+        Vec<String>,
+        Vec<usize>,
+    ) -> (
+        Option<Box<dyn Observer<VmEvent<CORE_SIZE>>>>,
+        Option<DisplayControls>,
+    ),
+{
+    let reader = TraceReader::<CORE_SIZE>::open(path)?;
+    let header = &reader.header;
+    println!(
+        "Replaying round {} (seed {}) of {}",
+        header.round,
+        header.seed,
+        header
+            .warriors
+            .iter()
+            .map(|w| w.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    let (display, controls) = display(
+        header.warriors.iter().map(|w| w.name.clone()).collect(),
+        header.placement.positions.clone(),
+    );
+
+    let mut frame_end = cycles_per_frame;
+    let mut last_round = 0;
+    let mut paused = false;
+    let mut result = Ok(());
+    'replay: for event in reader {
+        let event = match event {
+            Ok(event) => event,
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        };
+
+        // once per cycle, so that a replay at full speed can be paused or
+        // stopped too
+        if event.round != last_round && !follow(controls.as_ref(), &mut paused) {
+            break;
+        }
+        while cycles_per_frame > 0 && event.round >= frame_end || paused {
+            thread::sleep(frame);
+            if !follow(controls.as_ref(), &mut paused) {
+                break 'replay;
+            }
+            if !paused && event.round >= frame_end {
+                frame_end += cycles_per_frame;
+            }
+        }
+
+        last_round = event.round;
+        if let Some(display) = display.as_ref() {
+            display.notify(event);
+        }
+    }
+
+    // the terminal must be back before anything is printed
+    if let Some(controls) = controls {
+        controls.close();
+    }
+    result?;
+    println!("Replay ended after {} rounds", last_round);
+
+    Ok(())
+}
+
+/// Applies the commands sent by the display since the last call, `false`
+/// when it asks to quit.
+fn follow(controls: Option<&DisplayControls>, paused: &mut bool) -> bool {
+    for command in controls.iter().flat_map(|c| c.commands.try_iter()) {
+        match command {
+            DisplayCommand::TogglePause => *paused = !*paused,
+            DisplayCommand::Quit => return false,
+            _ => {}
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controls::DisplayStatus;
+    use core_war::vm::config::VmConfig;
+    use core_war::vm::event::Observable;
+    use core_war::vm::parser::parse;
+    use core_war::vm::trace::{Recorder, TraceHeader};
+    use core_war::vm::vms::{Placement, Vm, WarriorDefinition};
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::mpsc::{self, Sender};
+    use std::sync::{Arc, Mutex};
+
+    struct Rounds(Arc<Mutex<Vec<u128>>>);
+
+    impl Observer<VmEvent<8000>> for Rounds {
+        fn notify(&self, event: VmEvent<8000>) {
+            self.0.lock().unwrap().push(event.round);
+        }
+    }
+
+    /// A trace `name` of `cycles` cycles of an imp against a dwarf
+    fn trace(name: &str, cycles: u128) -> PathBuf {
+        let path = env::temp_dir().join(format!("core_war_{}_{}", std::process::id(), name));
+        let warriors = ["mov 0, 1", "add #4, 3\nmov 2, @2\njmp -2, 0\ndat #0, #0"]
+            .iter()
+            .map(|code| WarriorDefinition::new("w".to_string(), parse(code.to_string()).unwrap()))
+            .collect::<Vec<WarriorDefinition<8000>>>();
+        let config = VmConfig {
+            max_cycles: cycles,
+            ..VmConfig::default()
+        };
+        let placement = Placement::evenly_spaced(2, 8000);
+        let header = TraceHeader {
+            config: config.clone(),
+            seed: 1,
+            round: 0,
+            placement: placement.clone(),
+            warriors: warriors.clone(),
+        };
+
+        let mut vm = Vm::with_placement(config, warriors, placement).unwrap();
+        vm.register(Recorder::create(&path, &header).unwrap());
+        vm.play(i32::MAX);
+
+        path
+    }
+
+    /// Replays `path` at full speed, sending `commands` to it from the
+    /// start, the rounds of the events displayed
+    fn replay_with(path: &Path, commands: impl FnOnce(Sender<DisplayCommand>)) -> Vec<u128> {
+        let rounds = Arc::new(Mutex::new(vec![]));
+        let (command_tx, command_rx) = mpsc::channel();
+        let (status_tx, _status_rx) = mpsc::channel::<DisplayStatus>();
+        commands(command_tx);
+
+        let display = |_, _| {
+            let display: Box<dyn Observer<VmEvent<8000>>> = Box::new(Rounds(rounds.clone()));
+            let controls = DisplayControls {
+                commands: command_rx,
+                status: status_tx,
+                thread: None,
+            };
+            (Some(display), Some(controls))
+        };
+        replay::<8000, _>(path, display, 0, Duration::from_millis(1)).unwrap();
+
+        let rounds = rounds.lock().unwrap().clone();
+        rounds
+    }
+
+    #[test]
+    fn a_replay_at_full_speed_plays_everything() {
+        let path = trace("full.jsonl", 50);
+        let rounds = replay_with(&path, |_| {});
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(Some(&49), rounds.last());
+    }
+
+    #[test]
+    fn a_paused_replay_stops_on_quit() {
+        let path = trace("paused.jsonl", 50);
+        let first_cycle = replay_with(&path, |_| {})
+            .iter()
+            .filter(|&&round| round == 0)
+            .count();
+        let rounds = replay_with(&path, |commands| {
+            commands.send(DisplayCommand::TogglePause).unwrap();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                commands.send(DisplayCommand::Quit).unwrap();
+            });
+        });
+        fs::remove_file(&path).unwrap();
+
+        // paused before the first event of cycle 1, which is never shown
+        assert_eq!(vec![0; first_cycle], rounds);
+    }
+}
@@ -1,31 +1,191 @@
 extern crate sdl2;
 
-use super::vm::event::{EventType, Observer, VmEvent};
+use crate::controls::{DisplayCommand, DisplayControls, DisplayStatus};
+use crate::font;
+use core_war::vm::event::{EventType, Observer, VmEvent};
+use core_war::vm::image::{Grid, Rgb};
+use core_war::vm::palette::Palette;
+use core_war::vm::processes::ProcessTracker;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
 use sdl2::pixels::Color;
-use sdl2::rect::Point;
-use std::sync::mpsc::{self, Receiver, Sender};
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
 use std::{
     thread,
     time::{Duration, Instant},
 };
 
-pub struct SdlDisplay {
-    channel: Sender<VmEvent>,
+const MAX_ZOOM: usize = 8;
+const HELP: &str = "space: pause, s: step, +/-: speed, wheel or z/x: zoom, arrows: move, q: quit";
+/// Pixels of a font pixel in the legend
+const TEXT_SCALE: usize = 2;
+const LEGEND_LINE: usize = (font::GLYPH_HEIGHT + 2) * TEXT_SCALE;
+const LEGEND_MARGIN: usize = 6;
+const LEGEND_GAP: usize = 16;
+/// Pixels of the process count histogram between the core and the legend
+const HISTOGRAM_HEIGHT: usize = 60;
+
+pub struct SdlDisplay<const CORE_SIZE: usize> {
+    channel: Sender<VmEvent<CORE_SIZE>>,
+}
+
+/// The part of the core on the window, and where it is drawn.
+struct View {
+    /// The whole core fitted in the window, above the histogram
+    grid: Grid,
+    width: usize,
+    height: usize,
+    zoom: usize,
+    /// First column and row shown
+    x: usize,
+    y: usize,
 }
 
-impl SdlDisplay {
-    pub fn new() -> Box<SdlDisplay> {
+impl View {
+    fn new(core_size: usize, width: usize, height: usize) -> View {
+        View {
+            grid: Grid::fit(core_size, width, height),
+            width,
+            height,
+            zoom: 1,
+            x: 0,
+            y: 0,
+        }
+    }
+
+    /// Refits the core, keeping the zoom
+    fn resize(&mut self, core_size: usize, width: usize, height: usize) {
+        self.grid = Grid::fit(core_size, width.max(1), height.max(1));
+        self.width = width;
+        self.height = height;
+        self.clamp();
+    }
+
+    fn cell_size(&self) -> usize {
+        self.grid.cell * self.zoom
+    }
+
+    fn columns_shown(&self) -> usize {
+        (self.width / self.cell_size()).clamp(1, self.grid.columns)
+    }
+
+    fn rows_shown(&self) -> usize {
+        (self.height / self.cell_size()).clamp(1, self.grid.rows)
+    }
+
+    /// Keeps the view in the core
+    fn clamp(&mut self) {
+        self.x = self.x.min(self.grid.columns - self.columns_shown());
+        self.y = self.y.min(self.grid.rows - self.rows_shown());
+    }
+
+    /// Zooms by `steps`, keeping the cell under the pointer in place.
+    fn zoom_by(&mut self, steps: i32, pointer: (i32, i32)) {
+        let (column, row) = self.cell_at(pointer);
+        self.zoom = (self.zoom as i32 + steps).clamp(1, MAX_ZOOM as i32) as usize;
+
+        let size = self.cell_size();
+        self.x = column.saturating_sub(pointer.0.max(0) as usize / size);
+        self.y = row.saturating_sub(pointer.1.max(0) as usize / size);
+        self.clamp();
+    }
+
+    fn cell_at(&self, (x, y): (i32, i32)) -> (usize, usize) {
+        let size = self.cell_size();
+        (
+            self.x + x.max(0) as usize / size,
+            self.y + y.max(0) as usize / size,
+        )
+    }
+
+    fn address_at(&self, pointer: (i32, i32), core_size: usize) -> Option<usize> {
+        let (column, row) = self.cell_at(pointer);
+        let address = self.grid.address(column, row);
+        let on_core = pointer.1 >= 0 && (pointer.1 as usize) < self.height;
+
+        (on_core && column < self.grid.columns && address < core_size).then_some(address)
+    }
+}
+
+/// Ids of the warriors on each line of the legend, for a window `width`
+/// pixels wide.
+fn legend_lines(names: &[String], width: usize) -> Vec<Vec<usize>> {
+    let mut lines: Vec<Vec<usize>> = vec![];
+    let mut line_width = LEGEND_MARGIN;
+
+    for (id, name) in names.iter().enumerate() {
+        let item = legend_item_width(name);
+        if lines.is_empty() || line_width + item > width {
+            lines.push(vec![]);
+            line_width = LEGEND_MARGIN;
+        }
+        lines.last_mut().unwrap().push(id);
+        line_width += item + LEGEND_GAP;
+    }
+
+    lines
+}
+
+/// Width of the color square, the name and up to 5 digits of processes
+fn legend_item_width(name: &str) -> usize {
+    (font::GLYPH_HEIGHT + font::width(&format!(" {} 00000", name))) * TEXT_SCALE
+}
+
+fn legend_height(names: &[String], width: usize) -> usize {
+    legend_lines(names, width).len() * LEGEND_LINE + 2 * LEGEND_MARGIN
+}
+
+/// Height of the window under the core
+fn below_core(names: &[String], width: usize) -> usize {
+    HISTOGRAM_HEIGHT + legend_height(names, width)
+}
+
+impl<const CORE_SIZE: usize> SdlDisplay<CORE_SIZE> {
+    /// A display of the warriors named `names` in the colors of `palette`,
+    /// along with the controls it sends the keys and mouse moves to, and
+    /// takes the status line from.
+    pub fn interactive(
+        names: Vec<String>,
+        starts: Vec<usize>,
+        palette: Palette,
+    ) -> (Box<SdlDisplay<CORE_SIZE>>, DisplayControls) {
         let (tx, rx) = mpsc::channel();
         let (ready_tx, ready_rx) = mpsc::channel();
+        let (command_tx, command_rx) = mpsc::channel();
+        let (status_tx, status_rx) = mpsc::channel();
 
-        thread::spawn(move || SdlDisplay::handle_events(rx, ready_tx));
+        thread::spawn(move || {
+            SdlDisplay::handle_events(names, starts, palette, rx, ready_tx, command_tx, status_rx)
+        });
 
         ready_rx.recv().unwrap();
 
-        Box::new(SdlDisplay { channel: tx })
+        (
+            Box::new(SdlDisplay { channel: tx }),
+            DisplayControls {
+                commands: command_rx,
+                status: status_tx,
+                thread: None,
+            },
+        )
     }
 
-    fn handle_events(rx: Receiver<VmEvent>, ready_tx: Sender<()>) {
+    fn handle_events(
+        names: Vec<String>,
+        starts: Vec<usize>,
+        palette: Palette,
+        rx: Receiver<VmEvent<CORE_SIZE>>,
+        ready_tx: Sender<()>,
+        commands: Sender<DisplayCommand>,
+        status: Receiver<DisplayStatus>,
+    ) {
+        // 10 pixels per cell for 8000 cells, as before the legend
+        let width = 1000;
+        let height = 800 + below_core(&names, width);
+
         let sdl_context = sdl2::init().unwrap();
         let video_subsystem = match sdl_context.video() {
             Ok(s) => s,
@@ -35,8 +195,9 @@ impl SdlDisplay {
             }
         };
         let window = match video_subsystem
-            .window("rust-sdl2 demo", 1000, 800)
+            .window("Core War", width as u32, height as u32)
             .position_centered()
+            .resizable()
             .build()
         {
             Ok(s) => s,
@@ -46,8 +207,6 @@ impl SdlDisplay {
             }
         };
 
-        //window.set_bordered(false);
-        //window.set_fullscreen(FullscreenType::True).unwrap();
         let mut canvas = match window.into_canvas().build() {
             Ok(s) => s,
             Err(s) => {
@@ -55,87 +214,320 @@ impl SdlDisplay {
                 return;
             }
         };
-        canvas.set_scale(10f32, 10f32).unwrap();
         let mut last_display = Instant::now();
 
-        let colors = vec![
-            Color::RED,
-            Color::BLUE,
-            Color::GREEN,
-            Color::YELLOW,
-            Color::MAGENTA,
-            Color::WHITE,
-        ];
-        let light_colors = vec![
-            Color::RGBA(255, 114, 118, 255),
-            Color::RGBA(164, 219, 232, 255),
-            Color::RGBA(162, 228, 184, 255),
-            Color::RGBA(241, 235, 156, 255),
-            Color::RGBA(241, 178, 220, 255),
-            Color::RGBA(240, 240, 240, 255),
-        ];
+        let color = |[r, g, b]: Rgb| Color::RGB(r, g, b);
+        let colors = (0..names.len())
+            .map(|id| color(palette.color(id)))
+            .collect::<Vec<_>>();
+        let light_colors = (0..names.len())
+            .map(|id| color(palette.light(id)))
+            .collect::<Vec<_>>();
+
+        // what is drawn of each cell, kept to redraw the core when zooming
+        let mut cells = vec![Color::BLACK; CORE_SIZE];
+        let mut tracker = ProcessTracker::new(&starts);
+        let mut view = View::new(CORE_SIZE, width, height - below_core(&names, width));
+        let mut pointer = (0, 0);
+        let mut hovered = None;
+        let mut status_line = String::new();
 
         let mut sdl_event_pump = sdl_context.event_pump().unwrap();
         ready_tx.send(()).unwrap();
         loop {
-            sdl_event_pump.poll_event();
-
-            let event = rx.recv().unwrap();
-            let x = (event.offset.unwrap_or(0) % 100) as i32;
-            let y = (event.offset.unwrap_or(0) / 100) as i32;
-
-            match event.event_type {
-                EventType::TerminatedProgram => {
-                    println!(
-                        "Warrior {} terminated after {} rounds",
-                        event.warrior_id, event.round
-                    );
+            // events stop coming while the game is paused, the window must
+            // still answer
+            let first = match rx.recv_timeout(Duration::from_millis(1000 / 24)) {
+                Ok(event) => Some(event),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => return,
+            };
+            for event in first.into_iter().chain(rx.try_iter()) {
+                tracker.apply(&event);
+                let offset = event.offset.unwrap_or(0);
+                let moved_from = event.moved_from.unwrap_or(0);
+
+                match event.event_type {
+                    EventType::TerminatedThread { .. } | EventType::Jump => {
+                        cells[moved_from] = colors[event.warrior_id];
+                    }
+                    EventType::Change(_) => {
+                        cells[offset] = colors[event.warrior_id];
+                    }
+                    _ => {}
                 }
-                EventType::TerminatedThread => {
-                    let passed_x = (event.moved_from.unwrap_or(0) % 100) as i32;
-                    let passed_y = (event.moved_from.unwrap_or(0) / 100) as i32;
 
-                    canvas.set_draw_color(colors[event.warrior_id]);
-                    canvas.draw_point(Point::new(passed_x, passed_y)).unwrap();
+                if last_display.elapsed() > Duration::from_millis(1000 / 24) {
+                    break;
                 }
-                EventType::Jump => {
-                    let passed_x = (event.moved_from.unwrap_or(0) % 100) as i32;
-                    let passed_y = (event.moved_from.unwrap_or(0) / 100) as i32;
+            }
 
-                    canvas.set_draw_color(colors[event.warrior_id]);
-                    canvas.draw_point(Point::new(passed_x, passed_y)).unwrap();
+            if last_display.elapsed() < Duration::from_millis(1000 / 24) {
+                continue;
+            }
 
-                    canvas.set_draw_color(light_colors[event.warrior_id]);
-                    canvas.draw_point(Point::new(x, y)).unwrap();
-                }
-                EventType::Change => {
-                    canvas.set_draw_color(colors[event.warrior_id]);
-                    canvas.draw_point(Point::new(x, y)).unwrap();
+            for event in sdl_event_pump.poll_iter() {
+                let command = match event {
+                    Event::Quit { .. } => Some(DisplayCommand::Quit),
+                    Event::Window {
+                        win_event: WindowEvent::SizeChanged(w, h),
+                        ..
+                    } => {
+                        let (w, h) = (w.max(1) as usize, h.max(1) as usize);
+                        view.resize(CORE_SIZE, w, h.saturating_sub(below_core(&names, w)));
+                        None
+                    }
+                    Event::KeyDown {
+                        keycode: Some(key), ..
+                    } => match key {
+                        Keycode::ESCAPE | Keycode::Q => Some(DisplayCommand::Quit),
+                        Keycode::SPACE => Some(DisplayCommand::TogglePause),
+                        Keycode::S | Keycode::PERIOD => Some(DisplayCommand::Step),
+                        Keycode::PLUS | Keycode::EQUALS | Keycode::KP_PLUS => {
+                            Some(DisplayCommand::Faster)
+                        }
+                        Keycode::MINUS | Keycode::KP_MINUS => Some(DisplayCommand::Slower),
+                        Keycode::Z => {
+                            view.zoom_by(1, pointer);
+                            None
+                        }
+                        Keycode::X => {
+                            view.zoom_by(-1, pointer);
+                            None
+                        }
+                        Keycode::LEFT => {
+                            view.x = view.x.saturating_sub(1);
+                            None
+                        }
+                        Keycode::RIGHT => {
+                            view.x += 1;
+                            view.clamp();
+                            None
+                        }
+                        Keycode::UP => {
+                            view.y = view.y.saturating_sub(1);
+                            None
+                        }
+                        Keycode::DOWN => {
+                            view.y += 1;
+                            view.clamp();
+                            None
+                        }
+                        _ => None,
+                    },
+                    Event::MouseWheel { y, .. } => {
+                        view.zoom_by(y.signum(), pointer);
+                        None
+                    }
+                    Event::MouseMotion { x, y, .. } => {
+                        pointer = (x, y);
+                        None
+                    }
+                    _ => None,
+                };
+
+                if let Some(command) = command {
+                    // nobody listens during a replay
+                    let _ = commands.send(command);
                 }
             }
 
-            last_display = if last_display.elapsed() > Duration::from_millis(1000 / 24) {
-                canvas.present();
-                Instant::now()
+            // the cell under the pointer changes with the view too
+            let address = view.address_at(pointer, CORE_SIZE);
+            if address != hovered {
+                hovered = address;
+                let _ = commands.send(DisplayCommand::Hover(hovered));
+            }
+
+            if let Some(last) = status.try_iter().last() {
+                status_line = last.line;
+            }
+            let title = if status_line.is_empty() {
+                format!("Core War - {}", HELP)
             } else {
-                last_display
+                format!("Core War - {} - {}", status_line, HELP)
             };
+            let _ = canvas.window_mut().set_title(&title);
+
+            canvas.set_draw_color(Color::BLACK);
+            canvas.clear();
+            SdlDisplay::<CORE_SIZE>::draw_core(&mut canvas, &cells, &view);
+            SdlDisplay::<CORE_SIZE>::draw_processes(&mut canvas, &tracker, &light_colors, &view);
+            SdlDisplay::<CORE_SIZE>::draw_histogram(&mut canvas, &tracker, &colors, &view);
+            SdlDisplay::<CORE_SIZE>::draw_legend(&mut canvas, &names, &tracker, &colors, &view);
+            canvas.present();
+            last_display = Instant::now();
         }
     }
+
+    fn draw_core(canvas: &mut Canvas<Window>, cells: &[Color], view: &View) {
+        let size = view.cell_size();
+
+        for row in view.y..view.y + view.rows_shown() {
+            for column in view.x..view.x + view.columns_shown() {
+                match cells.get(view.grid.address(column, row)) {
+                    Some(&color) if color != Color::BLACK => {
+                        canvas.set_draw_color(color);
+                        let cell = Rect::new(
+                            ((column - view.x) * size) as i32,
+                            ((row - view.y) * size) as i32,
+                            size as u32,
+                            size as u32,
+                        );
+                        canvas.fill_rect(cell).unwrap();
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Every process of every warrior, lighter over the cells
+    fn draw_processes(
+        canvas: &mut Canvas<Window>,
+        tracker: &ProcessTracker,
+        light_colors: &[Color],
+        view: &View,
+    ) {
+        let size = view.cell_size();
+        let (columns, rows) = (
+            view.x..view.x + view.columns_shown(),
+            view.y..view.y + view.rows_shown(),
+        );
+
+        for (id, &color) in light_colors.iter().enumerate() {
+            let cells = tracker
+                .positions(id)
+                .keys()
+                .map(|&address| view.grid.position(address))
+                .filter(|(column, row)| columns.contains(column) && rows.contains(row))
+                .map(|(column, row)| {
+                    Rect::new(
+                        ((column - view.x) * size) as i32,
+                        ((row - view.y) * size) as i32,
+                        size as u32,
+                        size as u32,
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            canvas.set_draw_color(color);
+            canvas.fill_rects(&cells).unwrap();
+        }
+    }
+
+    /// The process counts of the warriors stacked, over the whole game
+    fn draw_histogram(
+        canvas: &mut Canvas<Window>,
+        tracker: &ProcessTracker,
+        colors: &[Color],
+        view: &View,
+    ) {
+        let history = tracker.history();
+        let max = tracker.peak();
+        let bottom = view.height + HISTOGRAM_HEIGHT;
+        let height = HISTOGRAM_HEIGHT - LEGEND_MARGIN;
+
+        let mut bars = vec![vec![]; colors.len()];
+        for (ix, counts) in history.iter().enumerate() {
+            let x = ix * view.width / history.len();
+            let width = ((ix + 1) * view.width / history.len()).max(x + 1) - x;
+            let mut below = 0;
+            for (id, &count) in counts.iter().enumerate() {
+                let top = (below + count) * height / max;
+                if top > below * height / max {
+                    bars[id].push(Rect::new(
+                        x as i32,
+                        (bottom - top) as i32,
+                        width as u32,
+                        (top - below * height / max) as u32,
+                    ));
+                }
+                below += count;
+            }
+        }
+
+        for (id, bars) in bars.iter().enumerate() {
+            canvas.set_draw_color(colors[id]);
+            canvas.fill_rects(bars).unwrap();
+        }
+    }
+
+    /// Each warrior's color, name and processes left, under the core
+    fn draw_legend(
+        canvas: &mut Canvas<Window>,
+        names: &[String],
+        tracker: &ProcessTracker,
+        colors: &[Color],
+        view: &View,
+    ) {
+        let top = view.height + HISTOGRAM_HEIGHT + LEGEND_MARGIN;
+        let square = font::GLYPH_HEIGHT * TEXT_SCALE;
+
+        for (line, ids) in legend_lines(names, view.width).into_iter().enumerate() {
+            let y = top + line * LEGEND_LINE;
+            let mut x = LEGEND_MARGIN;
+
+            for id in ids {
+                canvas.set_draw_color(colors[id]);
+                canvas
+                    .fill_rect(Rect::new(x as i32, y as i32, square as u32, square as u32))
+                    .unwrap();
+
+                // dead warriors are greyed out
+                let processes = tracker.count(id);
+                let text_color = if processes > 0 {
+                    Color::WHITE
+                } else {
+                    Color::GREY
+                };
+                let text = format!(" {} {}", names[id], processes);
+                draw_text(canvas, x + square, y, &text, text_color);
+
+                x += legend_item_width(&names[id]) + LEGEND_GAP;
+            }
+        }
+    }
+}
+
+fn draw_text(canvas: &mut Canvas<Window>, x: usize, y: usize, text: &str, color: Color) {
+    let pixels = font::pixels(text)
+        .into_iter()
+        .map(|(px, py)| {
+            Rect::new(
+                (x + px * TEXT_SCALE) as i32,
+                (y + py * TEXT_SCALE) as i32,
+                TEXT_SCALE as u32,
+                TEXT_SCALE as u32,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    canvas.set_draw_color(color);
+    canvas.fill_rects(&pixels).unwrap();
 }
 
-impl Observer<VmEvent> for SdlDisplay {
-    fn notify(&self, event: VmEvent) {
+impl<const CORE_SIZE: usize> Observer<VmEvent<CORE_SIZE>> for SdlDisplay<CORE_SIZE> {
+    fn notify(&self, event: VmEvent<CORE_SIZE>) {
+        if let EventType::TerminatedProgram { cause } = event.event_type {
+            println!(
+                "Warrior {} terminated after {} rounds ({:?})",
+                event.warrior_id, event.round, cause
+            );
+        }
+
         match event.event_type {
-            EventType::TerminatedProgram => {
-                println!(
-                    "Warrior {} terminated after {} rounds",
-                    event.warrior_id, event.round
-                );
-            }
-            _ => {
-                self.channel.send(event).unwrap();
+            // only these are drawn or counted
+            EventType::TerminatedProgram { .. }
+            | EventType::TerminatedThread { .. }
+            | EventType::Spawn { .. }
+            | EventType::Execute(_)
+            | EventType::Jump
+            | EventType::Change(_) => {
+                // the window may have been closed
+                let _ = self.channel.send(event);
             }
+            _ => {}
         }
     }
 }
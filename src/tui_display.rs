@@ -0,0 +1,686 @@
+use crate::controls::{DisplayCommand, DisplayControls, DisplayStatus};
+use core_war::vm::event::{DeathCause, EventType, Observer, VmEvent};
+use core_war::vm::palette::Palette;
+use core_war::vm::processes::ProcessTracker;
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor},
+    terminal, ExecutableCommand, QueueableCommand,
+};
+use std::io::{stdout, Result, Stdout, Write};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const FRAME: Duration = Duration::from_millis(1000 / 20);
+const HELP: &str = "space: pause  s: step  +/-: speed  tab: select  arrows: inspect  q: quit";
+/// Columns of the warriors and disassembly panel, left of which the core is
+const PANEL_WIDTH: usize = 36;
+/// Narrower terminals only show the core
+const MIN_WIDTH_FOR_PANEL: usize = 80;
+/// Rows of the process count histogram, under the core when there is room
+const HISTOGRAM_ROWS: usize = 6;
+const MIN_HEIGHT_FOR_HISTOGRAM: usize = 24;
+
+/// A full screen terminal display, in the alternate screen: the core with
+/// every process on it, the process counts over time, the warriors, and
+/// the code run by the selected one.
+/// Works over SSH, where the SDL window can not be opened.
+pub struct TuiDisplay<const CORE_SIZE: usize> {
+    channel: Sender<VmEvent<CORE_SIZE>>,
+}
+
+impl<const CORE_SIZE: usize> TuiDisplay<CORE_SIZE> {
+    /// The display of the warriors named `names` in the colors of
+    /// `palette`, and its controls. The terminal is restored when the
+    /// controls are closed.
+    pub fn interactive(
+        names: Vec<String>,
+        starts: Vec<usize>,
+        palette: Palette,
+    ) -> (Box<TuiDisplay<CORE_SIZE>>, DisplayControls) {
+        let (tx, rx) = mpsc::channel();
+        let (command_tx, command_rx) = mpsc::channel();
+        let (status_tx, status_rx) = mpsc::channel();
+
+        let thread = thread::spawn(move || {
+            let mut tui = Tui::<CORE_SIZE>::new(names, &starts, &palette, command_tx);
+            if let Err(e) = tui.run(rx, status_rx) {
+                println!("{}", e);
+            }
+        });
+
+        (
+            Box::new(TuiDisplay { channel: tx }),
+            DisplayControls {
+                commands: command_rx,
+                status: status_tx,
+                thread: Some(thread),
+            },
+        )
+    }
+}
+
+impl<const CORE_SIZE: usize> Observer<VmEvent<CORE_SIZE>> for TuiDisplay<CORE_SIZE> {
+    fn notify(&self, event: VmEvent<CORE_SIZE>) {
+        // the display is gone once the game is over
+        let _ = self.channel.send(event);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Glyph {
+    c: char,
+    fg: Color,
+    bg: Color,
+}
+
+const BLANK: Glyph = Glyph {
+    c: ' ',
+    fg: Color::Reset,
+    bg: Color::Reset,
+};
+
+/// What is to be on the terminal, only the glyphs that changed since the
+/// last frame are sent.
+struct Screen {
+    width: usize,
+    height: usize,
+    glyphs: Vec<Glyph>,
+    drawn: Vec<Glyph>,
+}
+
+impl Screen {
+    fn new() -> Screen {
+        Screen {
+            width: 0,
+            height: 0,
+            glyphs: vec![],
+            drawn: vec![],
+        }
+    }
+
+    /// Starts a frame for a terminal `width` x `height`
+    fn clear(&mut self, width: usize, height: usize) {
+        if (width, height) != (self.width, self.height) {
+            self.width = width;
+            self.height = height;
+            // nothing is known to be on the terminal any more
+            self.drawn = vec![];
+        }
+        self.glyphs = vec![BLANK; width * height];
+    }
+
+    /// Writes `text` from `x, y`, cut at the right edge
+    fn put(&mut self, x: usize, y: usize, text: &str, fg: Color, bg: Color) {
+        if y >= self.height {
+            return;
+        }
+
+        for (ix, c) in text.chars().enumerate().take(self.width.saturating_sub(x)) {
+            self.glyphs[y * self.width + x + ix] = Glyph { c, fg, bg };
+        }
+    }
+
+    fn flush(&mut self, out: &mut impl Write) -> Result<()> {
+        if self.drawn.is_empty() {
+            out.queue(terminal::Clear(terminal::ClearType::All))?;
+            self.drawn = vec![BLANK; self.glyphs.len()];
+        }
+
+        // where the cursor is after the last glyph printed, and its colors
+        let mut next = None;
+        let mut colors = None;
+        for (ix, glyph) in self.glyphs.iter().enumerate() {
+            if self.drawn[ix] == *glyph {
+                continue;
+            }
+
+            let (x, y) = (ix % self.width, ix / self.width);
+            if next != Some(ix) {
+                out.queue(cursor::MoveTo(x as u16, y as u16))?;
+            }
+            if colors != Some((glyph.fg, glyph.bg)) {
+                out.queue(SetForegroundColor(glyph.fg))?;
+                out.queue(SetBackgroundColor(glyph.bg))?;
+                colors = Some((glyph.fg, glyph.bg));
+            }
+            out.queue(Print(glyph.c))?;
+            // the cursor does not wrap at the end of a line
+            next = (x + 1 < self.width).then_some(ix + 1);
+        }
+        if colors.is_some() {
+            out.queue(ResetColor)?;
+        }
+        out.flush()?;
+
+        self.drawn.clone_from(&self.glyphs);
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Cell {
+    warrior_id: usize,
+    round: u128,
+}
+
+struct Warrior {
+    name: String,
+    color: Color,
+    light_color: Color,
+    death: Option<DeathCause>,
+}
+
+/// How the core is laid out in the map pane: `per_char` cells in each
+/// character, `columns` characters per row.
+#[derive(Clone, Copy)]
+pub struct Map {
+    pub columns: usize,
+    pub rows: usize,
+    pub per_char: usize,
+}
+
+impl Map {
+    pub fn fit(core_size: usize, width: usize, height: usize) -> Map {
+        let columns = width.max(1);
+        let per_char = core_size.div_ceil(columns * height.max(1)).max(1);
+        Map {
+            columns,
+            rows: core_size.div_ceil(per_char).div_ceil(columns),
+            per_char,
+        }
+    }
+
+    pub fn position(&self, address: usize) -> (usize, usize) {
+        let ix = address / self.per_char;
+        (ix % self.columns, ix / self.columns)
+    }
+
+    /// The first cell of the character at `column, row`
+    fn address(&self, column: usize, row: usize) -> usize {
+        (row * self.columns + column) * self.per_char
+    }
+}
+
+struct Tui<const CORE_SIZE: usize> {
+    screen: Screen,
+    cells: Vec<Option<Cell>>,
+    warriors: Vec<Warrior>,
+    tracker: ProcessTracker,
+    status: DisplayStatus,
+    selected: Option<usize>,
+    /// Character of the map inspected with the arrows
+    inspected: Option<(usize, usize)>,
+    map: Map,
+    commands: Sender<DisplayCommand>,
+}
+
+fn color([r, g, b]: [u8; 3]) -> Color {
+    Color::Rgb { r, g, b }
+}
+
+/// The warrior selected after `selected` out of `warriors`, going through
+/// each of them then none, `forward` or backward
+fn next_selection(selected: Option<usize>, warriors: usize, forward: bool) -> Option<usize> {
+    if forward {
+        match selected {
+            None if warriors > 0 => Some(0),
+            Some(id) if id + 1 < warriors => Some(id + 1),
+            _ => None,
+        }
+    } else {
+        match selected {
+            None => warriors.checked_sub(1),
+            Some(id) => id.checked_sub(1),
+        }
+    }
+}
+
+impl<const CORE_SIZE: usize> Tui<CORE_SIZE> {
+    fn new(
+        names: Vec<String>,
+        starts: &[usize],
+        palette: &Palette,
+        commands: Sender<DisplayCommand>,
+    ) -> Self {
+        Tui {
+            screen: Screen::new(),
+            cells: vec![None; CORE_SIZE],
+            tracker: ProcessTracker::new(starts),
+            warriors: names
+                .into_iter()
+                .enumerate()
+                .map(|(id, name)| Warrior {
+                    name,
+                    color: color(palette.color(id)),
+                    light_color: color(palette.light(id)),
+                    death: None,
+                })
+                .collect(),
+            status: DisplayStatus::default(),
+            selected: None,
+            inspected: None,
+            map: Map::fit(CORE_SIZE, 1, 1),
+            commands,
+        }
+    }
+
+    /// Takes the terminal over until the game or the controls are gone
+    fn run(
+        &mut self,
+        events: Receiver<VmEvent<CORE_SIZE>>,
+        status: Receiver<DisplayStatus>,
+    ) -> Result<()> {
+        let mut out = stdout();
+        terminal::enable_raw_mode()?;
+        out.execute(terminal::EnterAlternateScreen)?;
+        out.execute(cursor::Hide)?;
+
+        let result = self.show(&mut out, events, status);
+
+        // the terminal is given back whatever happened
+        let _ = out.execute(cursor::Show);
+        let _ = out.execute(terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+        result
+    }
+
+    fn show(
+        &mut self,
+        out: &mut Stdout,
+        events: Receiver<VmEvent<CORE_SIZE>>,
+        status: Receiver<DisplayStatus>,
+    ) -> Result<()> {
+        loop {
+            let frame_end = Instant::now() + FRAME;
+            while event::poll(frame_end.saturating_duration_since(Instant::now()))? {
+                if let Event::Key(key) = event::read()? {
+                    self.key(key);
+                }
+            }
+
+            loop {
+                match events.try_recv() {
+                    Ok(event) => self.apply(event),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                }
+            }
+            loop {
+                match status.try_recv() {
+                    Ok(s) => self.status = s,
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                }
+            }
+
+            self.draw(out)?;
+        }
+    }
+
+    fn key(&mut self, key: KeyEvent) {
+        if key.kind == KeyEventKind::Release {
+            return;
+        }
+
+        let command = match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                Some(DisplayCommand::Quit)
+            }
+            KeyCode::Char('q') | KeyCode::Esc => Some(DisplayCommand::Quit),
+            KeyCode::Char(' ') => Some(DisplayCommand::TogglePause),
+            KeyCode::Char('s') | KeyCode::Char('.') => Some(DisplayCommand::Step),
+            KeyCode::Char('+') | KeyCode::Char('=') => Some(DisplayCommand::Faster),
+            KeyCode::Char('-') => Some(DisplayCommand::Slower),
+            KeyCode::Tab | KeyCode::BackTab => {
+                self.selected =
+                    next_selection(self.selected, self.warriors.len(), key.code == KeyCode::Tab);
+                Some(DisplayCommand::Select(self.selected))
+            }
+            KeyCode::Left | KeyCode::Right | KeyCode::Up | KeyCode::Down => {
+                let (x, y) = self.inspected.unwrap_or((0, 0));
+                let last = (self.map.columns - 1, self.map.rows.max(1) - 1);
+                self.inspected = Some(match key.code {
+                    KeyCode::Left => (x.saturating_sub(1), y),
+                    KeyCode::Right => ((x + 1).min(last.0), y),
+                    KeyCode::Up => (x, y.saturating_sub(1)),
+                    _ => (x, (y + 1).min(last.1)),
+                });
+                let (x, y) = self.inspected.unwrap();
+                let address = self.map.address(x, y);
+                Some(DisplayCommand::Hover(
+                    (address < CORE_SIZE).then_some(address),
+                ))
+            }
+            _ => None,
+        };
+
+        if let Some(command) = command {
+            let _ = self.commands.send(command);
+        }
+    }
+
+    fn apply(&mut self, event: VmEvent<CORE_SIZE>) {
+        self.tracker.apply(&event);
+
+        let offset = event.offset.unwrap_or(0);
+        let moved_from = event.moved_from.unwrap_or(0);
+        let cell = Some(Cell {
+            warrior_id: event.warrior_id,
+            round: event.round,
+        });
+
+        match event.event_type {
+            EventType::TerminatedProgram { cause } => {
+                self.warriors[event.warrior_id].death = Some(cause);
+            }
+            EventType::TerminatedThread { .. } | EventType::Jump => self.cells[moved_from] = cell,
+            EventType::Change(_) => self.cells[offset] = cell,
+            _ => {}
+        }
+    }
+
+    fn draw(&mut self, out: &mut Stdout) -> Result<()> {
+        let (width, height) = terminal::size()?;
+        let (width, height) = (width as usize, height as usize);
+        self.screen.clear(width, height);
+
+        let panel = if width >= MIN_WIDTH_FOR_PANEL {
+            PANEL_WIDTH
+        } else {
+            0
+        };
+        // a title above the panes, the status and help lines below
+        let pane_height = height.saturating_sub(3);
+        let histogram = if height >= MIN_HEIGHT_FOR_HISTOGRAM {
+            HISTOGRAM_ROWS + 1
+        } else {
+            0
+        };
+        let map_height = pane_height - histogram;
+        let map_width = width.saturating_sub(panel + 1).max(1);
+        self.map = Map::fit(CORE_SIZE, map_width, map_height);
+
+        self.draw_map(map_height);
+        if histogram > 0 {
+            self.draw_histogram(map_height + 1, map_width);
+        }
+        if panel > 0 {
+            for y in 0..pane_height + 1 {
+                self.screen
+                    .put(map_width, y, "|", Color::DarkGrey, Color::Reset);
+            }
+            self.draw_warriors(map_width + 2, pane_height);
+        }
+
+        let line = self.status.line.clone();
+        self.screen.put(
+            0,
+            height.saturating_sub(2),
+            &line,
+            Color::White,
+            Color::Reset,
+        );
+        self.screen.put(
+            0,
+            height.saturating_sub(1),
+            HELP,
+            Color::DarkGrey,
+            Color::Reset,
+        );
+
+        self.screen.flush(out)
+    }
+
+    fn draw_map(&mut self, map_height: usize) {
+        let map = self.map;
+        let title = format!("Core: {} cells, {} per character", CORE_SIZE, map.per_char);
+        self.screen.put(0, 0, &title, Color::White, Color::Reset);
+
+        let chars = CORE_SIZE.div_ceil(map.per_char);
+        // the warrior with processes in each character, the last one drawn
+        // wins
+        let mut processes = vec![None; chars];
+        for id in 0..self.warriors.len() {
+            for address in self.tracker.positions(id).keys() {
+                processes[address / map.per_char] = Some(id);
+            }
+        }
+
+        let shown = chars.min(map.columns * map_height);
+        for (ix, process) in processes.into_iter().take(shown).enumerate() {
+            let start = ix * map.per_char;
+            let cells = &self.cells[start..(start + map.per_char).min(CORE_SIZE)];
+            let written = cells.iter().flatten().max_by_key(|c| c.round);
+            let (c, fg) = match (process, written) {
+                (Some(id), _) => ('@', self.warriors[id].light_color),
+                (None, Some(cell)) => ('#', self.warriors[cell.warrior_id].color),
+                (None, None) => ('.', Color::DarkGrey),
+            };
+
+            let (x, y) = (ix % map.columns, ix / map.columns);
+            let selected = self
+                .status
+                .process
+                .map(|p| map.position(p) == (x, y))
+                .unwrap_or(false);
+            let (fg, bg) = if self.inspected == Some((x, y)) {
+                (Color::Black, Color::White)
+            } else if selected {
+                (Color::Black, fg)
+            } else {
+                (fg, Color::Reset)
+            };
+            self.screen.put(x, y + 1, &c.to_string(), fg, bg);
+        }
+    }
+
+    /// The process counts of the warriors stacked, over the whole game,
+    /// from `top` under the core
+    fn draw_histogram(&mut self, top: usize, width: usize) {
+        let title = format!("Processes since the start, {} at most", self.tracker.peak());
+        self.screen.put(0, top, &title, Color::White, Color::Reset);
+
+        // a sample per column while they fit, some are skipped after that
+        for (x, column) in self
+            .tracker
+            .stacked(width, HISTOGRAM_ROWS)
+            .iter()
+            .enumerate()
+        {
+            for (row, id) in column.iter().enumerate() {
+                if let Some(id) = *id {
+                    let color = self.warriors[id].color;
+                    self.screen
+                        .put(x, top + HISTOGRAM_ROWS - row, " ", Color::Reset, color);
+                }
+            }
+        }
+    }
+
+    /// The warriors, then the code around the selected one's next process
+    fn draw_warriors(&mut self, x: usize, pane_height: usize) {
+        let width = PANEL_WIDTH - 1;
+        self.screen
+            .put(x, 0, "Warriors", Color::White, Color::Reset);
+
+        // half the panel at most, the rest is for the disassembly
+        let rows = (pane_height / 2).max(1);
+        let shown = if self.warriors.len() > rows {
+            rows - 1
+        } else {
+            rows
+        };
+        for (id, warrior) in self.warriors.iter().enumerate().take(shown) {
+            let state = match warrior.death {
+                None => "alive",
+                Some(DeathCause::Dat) => "killed by dat",
+                Some(DeathCause::DivisionByZero) => "divided by 0",
+            };
+            let name: String = warrior.name.chars().take(13).collect();
+            let line = format!("{:<13} {:>5} {}", name, self.tracker.count(id), state);
+            let marker = if self.selected == Some(id) { ">" } else { " " };
+            let fg = if warrior.death.is_some() {
+                Color::DarkGrey
+            } else {
+                Color::White
+            };
+
+            self.screen
+                .put(x, id + 1, marker, Color::White, Color::Reset);
+            self.screen
+                .put(x + 1, id + 1, "#", warrior.color, Color::Reset);
+            let line: String = line.chars().take(width - 3).collect();
+            self.screen.put(x + 3, id + 1, &line, fg, Color::Reset);
+        }
+        if shown < self.warriors.len() {
+            let more = format!("  and {} more", self.warriors.len() - shown);
+            self.screen
+                .put(x, shown + 1, &more, Color::DarkGrey, Color::Reset);
+        }
+
+        let top = rows + 2;
+        let title = match self.selected {
+            Some(id) => format!("Next process of {}", self.warriors[id].name),
+            None => "Tab selects a warrior".to_string(),
+        };
+        let title: String = title.chars().take(width).collect();
+        self.screen.put(x, top, &title, Color::White, Color::Reset);
+
+        let lines = pane_height.saturating_sub(top);
+        let current = self
+            .status
+            .listing
+            .iter()
+            .position(|(address, _)| Some(*address) == self.status.process)
+            .unwrap_or(0);
+        // the process a third of the way down
+        let first = current.saturating_sub(lines / 3);
+        for (row, (address, instruction)) in self
+            .status
+            .listing
+            .iter()
+            .skip(first)
+            .take(lines)
+            .enumerate()
+        {
+            let (marker, fg, bg) = if Some(*address) == self.status.process {
+                ('>', Color::Black, Color::White)
+            } else {
+                (' ', Color::Grey, Color::Reset)
+            };
+            let line = format!("{}{:>6}  {}", marker, address, instruction);
+            let line: String = line.chars().take(width).collect();
+            self.screen.put(x, top + 1 + row, &line, fg, bg);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tui(warriors: usize) -> (Tui<8000>, Receiver<DisplayCommand>) {
+        let (tx, rx) = mpsc::channel();
+        let names = (0..warriors).map(|id| format!("w{}", id)).collect();
+        let starts = (0..warriors)
+            .map(|id| id * 8000 / warriors)
+            .collect::<Vec<_>>();
+
+        (Tui::new(names, &starts, &Palette::default(), tx), rx)
+    }
+
+    fn press(tui: &mut Tui<8000>, code: KeyCode) {
+        tui.key(KeyEvent::new(code, KeyModifiers::NONE));
+    }
+
+    #[test]
+    fn only_changed_glyphs_are_sent() {
+        let mut screen = Screen::new();
+        screen.clear(10, 3);
+        screen.put(0, 0, "abc", Color::White, Color::Reset);
+        let mut out = vec![];
+        screen.flush(&mut out).unwrap();
+        assert!(String::from_utf8_lossy(&out).contains("abc"));
+
+        screen.clear(10, 3);
+        screen.put(0, 0, "abd", Color::White, Color::Reset);
+        let mut out = vec![];
+        screen.flush(&mut out).unwrap();
+        let sent = String::from_utf8_lossy(&out);
+        assert!(sent.contains('d'));
+        assert!(!sent.contains('a') && !sent.contains('b'));
+
+        // nothing changed, nothing but the flush
+        screen.clear(10, 3);
+        screen.put(0, 0, "abd", Color::White, Color::Reset);
+        let mut out = vec![];
+        screen.flush(&mut out).unwrap();
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn a_resize_draws_everything_again() {
+        let mut screen = Screen::new();
+        screen.clear(10, 3);
+        screen.put(0, 0, "abc", Color::White, Color::Reset);
+        screen.flush(&mut vec![]).unwrap();
+
+        screen.clear(12, 3);
+        screen.put(0, 0, "abc", Color::White, Color::Reset);
+        let mut out = vec![];
+        screen.flush(&mut out).unwrap();
+        assert!(String::from_utf8_lossy(&out).contains("abc"));
+    }
+
+    #[test]
+    fn the_core_fits_the_map() {
+        let map = Map::fit(8000, 100, 20);
+        assert_eq!((100, 20, 4), (map.columns, map.rows, map.per_char));
+        assert_eq!((1, 2), map.position(804));
+        assert_eq!(804, map.address(1, 2));
+
+        // a larger pane holds a cell per character
+        let map = Map::fit(8000, 200, 50);
+        assert_eq!((1, 40), (map.per_char, map.rows));
+    }
+
+    #[test]
+    fn selection_cycles_through_the_warriors() {
+        let (mut tui, commands) = tui(3);
+
+        for expected in [Some(0), Some(1), Some(2), None, Some(0)] {
+            press(&mut tui, KeyCode::Tab);
+            assert_eq!(expected, tui.selected);
+            assert_eq!(Ok(DisplayCommand::Select(expected)), commands.try_recv());
+        }
+        for expected in [None, Some(2), Some(1), Some(0), None] {
+            press(&mut tui, KeyCode::BackTab);
+            assert_eq!(expected, tui.selected);
+        }
+
+        assert_eq!(None, next_selection(None, 0, true));
+        assert_eq!(None, next_selection(None, 0, false));
+    }
+
+    #[test]
+    fn arrows_stay_on_the_map() {
+        let (mut tui, commands) = tui(2);
+        tui.map = Map::fit(8000, 100, 20);
+
+        press(&mut tui, KeyCode::Left);
+        press(&mut tui, KeyCode::Up);
+        assert_eq!(Some((0, 0)), tui.inspected);
+        press(&mut tui, KeyCode::Down);
+        assert_eq!(Some((0, 1)), tui.inspected);
+
+        for _ in 0..150 {
+            press(&mut tui, KeyCode::Right);
+        }
+        assert_eq!(Some((99, 1)), tui.inspected);
+        let hovered = commands.try_iter().last();
+        assert_eq!(Some(DisplayCommand::Hover(Some(796))), hovered);
+    }
+}
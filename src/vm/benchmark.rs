@@ -0,0 +1,130 @@
+use super::config::VmConfig;
+use super::rng::Rng;
+use super::runner::{MatchRunner, Score};
+use super::vms::WarriorDefinition;
+use serde::Serialize;
+use std::fmt;
+
+/// How a warrior did against each warrior of a reference suite.
+#[derive(Serialize)]
+pub struct BenchmarkReport {
+    pub warrior: String,
+    pub seed: u64,
+    pub rounds: usize,
+    pub opponents: Vec<OpponentResult>,
+    /// Points averaged over the opponents, see `Score::points`
+    pub score: f64,
+}
+
+#[derive(Serialize)]
+pub struct OpponentResult {
+    pub name: String,
+    pub score: Score,
+    pub points: f64,
+}
+
+/// Plays `rounds` one-on-one rounds of `warrior` against each of
+/// `opponents`, on `threads` threads. Each pairing gets its own seed derived
+/// from the config seed, or from a seed from the clock.
+pub fn benchmark<const CORE_SIZE: usize>(
+    config: &VmConfig,
+    warrior: &WarriorDefinition<CORE_SIZE>,
+    opponents: &[WarriorDefinition<CORE_SIZE>],
+    rounds: usize,
+    threads: usize,
+) -> Result<BenchmarkReport, String> {
+    if opponents.is_empty() {
+        return Err("No opponent to benchmark against".to_string());
+    }
+
+    let seed = config.seed.unwrap_or_else(Rng::entropy_seed);
+    let opponents = opponents
+        .iter()
+        .enumerate()
+        .map(|(ix, opponent)| {
+            let config = VmConfig {
+                seed: Some(Rng::derive(seed, ix as u64)),
+                ..config.clone()
+            };
+            let runner = MatchRunner::new(config, vec![warrior.clone(), opponent.clone()]);
+            let score = runner
+                .play_parallel(rounds, threads)?
+                .scores()
+                .swap_remove(0);
+
+            Ok(OpponentResult {
+                name: opponent.name.clone(),
+                points: score.points(),
+                score,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let score = opponents.iter().map(|o| o.points).sum::<f64>() / opponents.len() as f64;
+
+    Ok(BenchmarkReport {
+        warrior: warrior.name.clone(),
+        seed,
+        rounds,
+        opponents,
+        score,
+    })
+}
+
+impl fmt::Display for BenchmarkReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} against {} warriors, {} rounds each (seed {})",
+            self.warrior,
+            self.opponents.len(),
+            self.rounds,
+            self.seed
+        )?;
+        writeln!(
+            f,
+            "{:<24}{:>7}{:>7}{:>7}{:>9}",
+            "opponent", "wins", "losses", "ties", "points"
+        )?;
+        for o in self.opponents.iter() {
+            writeln!(
+                f,
+                "{:<24}{:>7}{:>7}{:>7}{:>9.1}",
+                o.name, o.score.wins, o.score.losses, o.score.ties, o.points
+            )?;
+        }
+        writeln!(f, "score: {:.2}", self.score)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::warrior;
+    use super::*;
+
+    #[test]
+    fn scores_are_averaged_over_opponents() {
+        let config = VmConfig {
+            seed: Some(4),
+            max_cycles: 2000,
+            ..VmConfig::default()
+        };
+        let opponents = [warrior("dead", "dat #0, #0"), warrior("imp", "mov 0, 1")];
+
+        let report = benchmark(&config, &warrior("imp", "mov 0, 1"), &opponents, 10, 2).unwrap();
+
+        assert_eq!(4, report.seed);
+        assert_eq!(10, report.opponents[0].score.wins);
+        assert_eq!(300.0, report.opponents[0].points);
+        assert_eq!(10, report.opponents[1].score.ties);
+        assert_eq!(100.0, report.opponents[1].points);
+        assert_eq!(200.0, report.score);
+    }
+
+    #[test]
+    fn an_empty_suite_is_rejected() {
+        let config = VmConfig::default();
+
+        assert!(benchmark(&config, &warrior("imp", "mov 0, 1"), &[], 10, 1).is_err());
+    }
+}
@@ -0,0 +1,537 @@
+use super::config::VmConfig;
+use super::event::{EventType, Observable, Observer, VmEvent};
+use super::instructions::{Instruction, Modifier, OpCode, Operand, OperandMode};
+use super::numeric::Numeric;
+use super::vms::{Outcome, Placement, Vm, WarriorDefinition};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub enum Strategy {
+    /// Moves through the core copying itself one step ahead
+    Imp,
+    /// Bombs the core at a constant stride
+    Stone,
+    /// Copies itself around the core and runs the copies
+    Paper,
+    /// Looks for the opponent before bombing where it found it
+    Scanner,
+    /// Wipes the core cell after cell
+    Clear,
+}
+
+impl Strategy {
+    pub const ALL: [Strategy; 5] = [
+        Strategy::Imp,
+        Strategy::Stone,
+        Strategy::Paper,
+        Strategy::Scanner,
+        Strategy::Clear,
+    ];
+}
+
+/// What the code of a warrior looks like.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct StaticFeatures {
+    pub length: usize,
+    /// Share of each opcode in the code, by `OpCode::ALL` order
+    pub opcode_mix: Vec<f64>,
+    /// `spl 0` or `spl #0`: a process splitting onto itself
+    pub spl_zero: bool,
+    /// `mov.i 0, step` with step 1 or `(n * CORE_SIZE + 1) / n`
+    pub imp_step: Option<usize>,
+    /// `add` or `sub` of an immediate, stepping a pointer
+    pub immediate_step: bool,
+    /// `mov` through a B indirection, writing away from the code
+    pub indirect_mov: bool,
+    /// `cmp` or `slt`, comparing cells of the core
+    pub comparisons: usize,
+    /// Jumps back into the code, making a loop
+    pub loops: usize,
+}
+
+/// What a warrior did when run against an opponent doing nothing.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct DynamicFeatures {
+    pub cycles: u128,
+    /// Cells written out of the code
+    pub far_writes: usize,
+    /// Share of the far writes at the most frequent stride from the
+    /// previous write of the same instruction
+    pub stride_regularity: f64,
+    /// Share of the far writes right next to the previous write of the same
+    /// instruction
+    pub sequential_writes: f64,
+    /// Cells read out of the code
+    pub far_reads: usize,
+    /// Distinct cells executed
+    pub executed: usize,
+    /// Groups of executed cells at least a warrior length apart
+    pub executed_regions: usize,
+    /// Share of the executions on a cell written by the warrior just before
+    pub executed_own_writes: f64,
+    pub max_processes: usize,
+}
+
+#[derive(Serialize)]
+pub struct Classification {
+    pub warrior: String,
+    pub strategy: Strategy,
+    /// 0 to 1, the score of `strategy` lowered by how close the runner-up is
+    pub confidence: f64,
+    /// Score of every strategy, best first
+    pub scores: Vec<(Strategy, f64)>,
+    pub static_features: StaticFeatures,
+    pub dynamic_features: DynamicFeatures,
+}
+
+/// Labels the strategy of `warrior` from its code and from `cycles` cycles
+/// of a round against `jmp 0, 0`.
+pub fn classify<const CORE_SIZE: usize>(
+    config: &VmConfig,
+    warrior: &WarriorDefinition<CORE_SIZE>,
+    cycles: u128,
+) -> Result<Classification, String> {
+    let static_features = static_features(&warrior.ops);
+    let dynamic_features = dynamic_features(config, warrior, cycles)?;
+    let scores = scores(&static_features, &dynamic_features);
+
+    let (strategy, best) = scores[0];
+    let confidence = if best > 0.0 {
+        best * (1.0 - scores[1].1 / best / 2.0)
+    } else {
+        0.0
+    };
+
+    Ok(Classification {
+        warrior: warrior.name.clone(),
+        strategy,
+        confidence,
+        scores,
+        static_features,
+        dynamic_features,
+    })
+}
+
+pub fn static_features<const CORE_SIZE: usize>(ops: &[Instruction<CORE_SIZE>]) -> StaticFeatures {
+    let length = ops.len();
+    let signed = |n: Numeric<CORE_SIZE>| {
+        if n.value > CORE_SIZE / 2 {
+            n.value as isize - CORE_SIZE as isize
+        } else {
+            n.value as isize
+        }
+    };
+
+    let opcode_mix = OpCode::ALL
+        .iter()
+        .map(|op| ops.iter().filter(|i| i.op == *op).count() as f64 / length.max(1) as f64)
+        .collect();
+    let spl_zero = ops
+        .iter()
+        .any(|i| i.op == OpCode::Spl && i.a_operand.pointer.value == 0);
+    let imp_step = ops
+        .iter()
+        .filter(|i| {
+            i.op == OpCode::Mov
+                && i.modifier == Modifier::I
+                && is_direct(&i.a_operand, 0)
+                && i.b_operand.mode == OperandMode::Direct
+        })
+        .map(|i| i.b_operand.pointer.value)
+        .find(|&step| is_imp_step::<CORE_SIZE>(step));
+    let immediate_step = ops.iter().any(|i| {
+        matches!(i.op, OpCode::Add | OpCode::Sub) && i.a_operand.mode == OperandMode::Immediate
+    });
+    let indirect_mov = ops.iter().any(|i| {
+        i.op == OpCode::Mov
+            && matches!(
+                i.b_operand.mode,
+                OperandMode::Indirect | OperandMode::Decrement | OperandMode::Increment
+            )
+    });
+    let comparisons = ops
+        .iter()
+        .filter(|i| matches!(i.op, OpCode::Cmp | OpCode::Slt))
+        .count();
+    let loops = ops
+        .iter()
+        .enumerate()
+        .filter(|(ix, i)| {
+            let offset = signed(i.a_operand.pointer);
+            matches!(i.op, OpCode::Jmp | OpCode::Jmz | OpCode::Jmn | OpCode::Djn)
+                && i.a_operand.mode == OperandMode::Direct
+                && offset <= 0
+                && -offset <= *ix as isize
+        })
+        .count();
+
+    StaticFeatures {
+        length,
+        opcode_mix,
+        spl_zero,
+        imp_step,
+        immediate_step,
+        indirect_mov,
+        comparisons,
+        loops,
+    }
+}
+
+fn is_direct<const CORE_SIZE: usize>(operand: &Operand<CORE_SIZE>, pointer: usize) -> bool {
+    operand.mode == OperandMode::Direct && operand.pointer.value == pointer
+}
+
+/// 1, or the step of an imp ring of `n` imps going around the core once.
+fn is_imp_step<const CORE_SIZE: usize>(step: usize) -> bool {
+    step == 1 || (2..=16).any(|n| step * n % CORE_SIZE == 1)
+}
+
+/// Every event of the observed warrior.
+struct Log<const CORE_SIZE: usize> {
+    events: Arc<Mutex<Vec<VmEvent<CORE_SIZE>>>>,
+}
+
+impl<const CORE_SIZE: usize> Observer<VmEvent<CORE_SIZE>> for Log<CORE_SIZE> {
+    fn notify(&self, event: VmEvent<CORE_SIZE>) {
+        if event.warrior_id == 0 {
+            self.events.lock().unwrap().push(event);
+        }
+    }
+}
+
+pub fn dynamic_features<const CORE_SIZE: usize>(
+    config: &VmConfig,
+    warrior: &WarriorDefinition<CORE_SIZE>,
+    cycles: u128,
+) -> Result<DynamicFeatures, String> {
+    let sitter = WarriorDefinition::new(
+        "sitter".to_string(),
+        vec![Instruction {
+            op: OpCode::Jmp,
+            modifier: Modifier::B,
+            a_operand: Operand {
+                pointer: Numeric::new(0),
+                mode: OperandMode::Direct,
+            },
+            b_operand: Operand {
+                pointer: Numeric::new(0),
+                mode: OperandMode::Direct,
+            },
+        }],
+    );
+    let config = VmConfig {
+        max_cycles: cycles,
+        ..config.clone()
+    };
+    let placement = Placement {
+        positions: vec![0, CORE_SIZE / 2],
+        order: vec![0, 1],
+    };
+    let mut vm = Vm::with_placement(config, vec![warrior.clone(), sitter], placement)?;
+    let events = Arc::new(Mutex::new(Vec::new()));
+    vm.register(Box::new(Log {
+        events: events.clone(),
+    }));
+
+    while let Outcome::Running = vm.play(i32::MAX) {}
+    let events = events.lock().unwrap();
+
+    let length = warrior.ops.len();
+    // distance to the code, loaded at 0
+    let far = |address: usize| {
+        address >= length && CORE_SIZE - address > length && address - length >= length
+    };
+
+    let mut features = DynamicFeatures {
+        cycles: vm.cycles(),
+        ..DynamicFeatures::default()
+    };
+    // far writes of each instruction, by address, in order
+    let mut far_writes = HashMap::<usize, Vec<usize>>::new();
+    let mut writer = 0;
+    let mut far_reads = HashSet::new();
+    let mut executed = HashSet::new();
+    let mut written = HashSet::new();
+    let mut executions = 0;
+    let mut executed_own_writes = 0;
+    for event in events.iter() {
+        let offset = event.offset.unwrap_or(0);
+        match event.event_type {
+            EventType::Change(_) => {
+                written.insert(offset);
+                if far(offset) {
+                    far_writes.entry(writer).or_default().push(offset);
+                }
+            }
+            EventType::ReadA | EventType::ReadB if far(offset) => {
+                far_reads.insert(offset);
+            }
+            EventType::Execute(_) => {
+                writer = offset;
+                executions += 1;
+                executed.insert(offset);
+                if written.contains(&offset) {
+                    executed_own_writes += 1;
+                }
+            }
+            EventType::Spawn { processes } => {
+                features.max_processes = features.max_processes.max(processes)
+            }
+            _ => {}
+        }
+    }
+
+    features.far_writes = far_writes.values().map(|w| w.len()).sum();
+    features.far_reads = far_reads.len();
+    features.executed = executed.len();
+    features.max_processes = features.max_processes.max(1);
+    if executions > 0 {
+        features.executed_own_writes = executed_own_writes as f64 / executions as f64;
+    }
+
+    // strides between the successive writes of each instruction, so that
+    // processes and bombing loops running side by side don't mix
+    let mut pairs = 0;
+    let mut regular = 0;
+    let mut sequential = 0;
+    for writes in far_writes.values().filter(|w| w.len() > 1) {
+        let mut strides = HashMap::new();
+        for pair in writes.windows(2) {
+            let stride = (pair[1] + CORE_SIZE - pair[0]) % CORE_SIZE;
+            *strides.entry(stride.min(CORE_SIZE - stride)).or_insert(0) += 1;
+        }
+        pairs += writes.len() - 1;
+        regular += strides.values().max().unwrap();
+        sequential += strides.get(&1).unwrap_or(&0);
+    }
+    if pairs > 0 {
+        features.stride_regularity = regular as f64 / pairs as f64;
+        features.sequential_writes = sequential as f64 / pairs as f64;
+    }
+
+    let mut executed = executed.into_iter().collect::<Vec<_>>();
+    executed.sort_unstable();
+    features.executed_regions = executed
+        .windows(2)
+        .filter(|pair| pair[1] - pair[0] > length.max(4))
+        .count()
+        + if executed.is_empty() { 0 } else { 1 };
+    // the code wrapping around the end of the core is one region
+    if executed.len() > 1
+        && features.executed_regions > 1
+        && executed[0] + CORE_SIZE - executed[executed.len() - 1] <= length.max(4)
+    {
+        features.executed_regions -= 1;
+    }
+
+    Ok(features)
+}
+
+/// Score of every strategy, best first.
+fn scores(
+    static_features: &StaticFeatures,
+    dynamic_features: &DynamicFeatures,
+) -> Vec<(Strategy, f64)> {
+    let mut scores = Strategy::ALL
+        .iter()
+        .map(|&strategy| {
+            let score = 0.4 * static_score(strategy, static_features)
+                + 0.6 * dynamic_score(strategy, dynamic_features, static_features.length);
+            (strategy, score)
+        })
+        .collect::<Vec<_>>();
+    scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+
+    scores
+}
+
+/// 0 to 1, how much the code looks like `strategy`.
+fn static_score(strategy: Strategy, f: &StaticFeatures) -> f64 {
+    let yes = |b: bool| if b { 1.0 } else { 0.0 };
+    let has_loop = yes(f.loops > 0);
+
+    match strategy {
+        Strategy::Imp => yes(f.imp_step.is_some()),
+        // a `spl 0` keeps more and more processes running the bombing loop
+        Strategy::Stone => {
+            (yes(f.immediate_step) + yes(f.indirect_mov) + has_loop + yes(f.spl_zero)) / 4.0
+                * yes(f.comparisons == 0)
+        }
+        // where papers split onto their copies
+        Strategy::Paper => {
+            let spl = f.opcode_mix[OpCode::Spl as usize];
+            (yes(spl > 0.0 && !f.spl_zero) + yes(f.indirect_mov) + has_loop) / 3.0
+        }
+        Strategy::Scanner => {
+            (yes(f.comparisons > 0) + yes(f.immediate_step) + yes(f.indirect_mov)) / 3.0
+        }
+        Strategy::Clear => {
+            (yes(f.indirect_mov) + has_loop + yes(!f.immediate_step)) / 3.0
+                * yes(f.comparisons == 0)
+        }
+    }
+}
+
+/// 0 to 1, how much the warrior behaved like `strategy`.
+fn dynamic_score(strategy: Strategy, f: &DynamicFeatures, length: usize) -> f64 {
+    let ratio = |n: usize, of: usize| (n as f64 / of.max(1) as f64).min(1.0);
+    let bombs = ratio(f.far_writes, 20);
+
+    match strategy {
+        // runs the cells it just wrote, all over the core
+        Strategy::Imp => f.executed_own_writes * ratio(f.executed, 10 * length),
+        Strategy::Stone => {
+            bombs
+                * f.stride_regularity
+                * (1.0 - f.sequential_writes)
+                * (1.0 - f.executed_own_writes)
+        }
+        // far copies of the code get executed, by ever more processes
+        Strategy::Paper => {
+            ratio(f.executed_regions.saturating_sub(1), 3)
+                * ratio(f.far_writes, 4 * length)
+                * ratio(f.max_processes, 8)
+        }
+        Strategy::Scanner => {
+            let reading = f.far_reads as f64 / (f.far_reads + f.far_writes).max(1) as f64;
+            ratio(f.far_reads, 20) * reading
+        }
+        Strategy::Clear => bombs * f.sequential_writes * (1.0 - f.executed_own_writes),
+    }
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Strategy::Imp => "imp",
+            Strategy::Stone => "stone",
+            Strategy::Paper => "paper",
+            Strategy::Scanner => "scanner",
+            Strategy::Clear => "clear",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for Classification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let scores = self
+            .scores
+            .iter()
+            .map(|(strategy, score)| format!("{} {:.2}", strategy, score))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        write!(
+            f,
+            "{}: {} (confidence {:.2}; {})",
+            self.warrior, self.strategy, self.confidence, scores
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::warrior;
+    use super::*;
+
+    fn classify_code(code: &str) -> Classification {
+        classify(&VmConfig::default(), &warrior("w", code), 4000).unwrap()
+    }
+
+    #[test]
+    fn imp_steps() {
+        assert!(is_imp_step::<8000>(1));
+        // 3 imps around a core of 8000: 3 * 2667 = 8001
+        assert!(is_imp_step::<8000>(2667));
+        assert!(!is_imp_step::<8000>(4));
+    }
+
+    #[test]
+    fn imps_are_recognized() {
+        let c = classify_code("mov 0, 1");
+
+        assert_eq!(Strategy::Imp, c.strategy);
+        assert_eq!(Some(1), c.static_features.imp_step);
+        assert!(c.confidence > 0.5);
+    }
+
+    #[test]
+    fn stones_are_recognized() {
+        let c = classify_code("add #4, 3\nmov 2, @2\njmp -2, 0\ndat #0, #0");
+
+        assert_eq!(Strategy::Stone, c.strategy);
+        assert!(c.dynamic_features.stride_regularity > 0.9);
+    }
+
+    #[test]
+    fn papers_are_recognized() {
+        let c = classify_code(include_str!("../../bots/bot.war"));
+
+        assert_eq!(Strategy::Paper, c.strategy);
+        assert!(c.dynamic_features.executed_regions > 2);
+    }
+
+    #[test]
+    fn scanners_are_recognized() {
+        let c = classify_code(
+            "scan: add #10, ptr\nptr: cmp 10, 15\njmp attack, 0\njmp scan, 0\n\
+             attack: mov bomb, @ptr\njmp scan, 0\nbomb: dat #0, #0",
+        );
+
+        assert_eq!(Strategy::Scanner, c.strategy);
+        assert!(c.dynamic_features.far_reads > c.dynamic_features.far_writes);
+    }
+
+    #[test]
+    fn clears_are_recognized() {
+        let c =
+            classify_code("loop: mov bomb, <ptr\njmp loop, 0\nptr: dat #0, #-10\nbomb: dat #0, #0");
+
+        assert_eq!(Strategy::Clear, c.strategy);
+        assert!(c.dynamic_features.sequential_writes > 0.9);
+    }
+
+    #[test]
+    fn spl_zero_tells_stones_from_papers() {
+        let mut opcode_mix = vec![0.0; OpCode::ALL.len()];
+        opcode_mix[OpCode::Spl as usize] = 0.25;
+        let mut features = StaticFeatures {
+            length: 4,
+            opcode_mix,
+            immediate_step: true,
+            indirect_mov: true,
+            loops: 1,
+            ..Default::default()
+        };
+        let dynamic = DynamicFeatures::default();
+
+        assert_eq!(Strategy::Paper, scores(&features, &dynamic)[0].0);
+        features.spl_zero = true;
+        assert_eq!(Strategy::Stone, scores(&features, &dynamic)[0].0);
+    }
+
+    #[test]
+    fn papers_spread_processes() {
+        let features = StaticFeatures {
+            length: 4,
+            opcode_mix: vec![0.0; OpCode::ALL.len()],
+            ..Default::default()
+        };
+        let mut dynamic = DynamicFeatures {
+            far_writes: 100,
+            executed_regions: 4,
+            max_processes: 8,
+            ..Default::default()
+        };
+
+        assert_eq!(Strategy::Paper, scores(&features, &dynamic)[0].0);
+        // copies run by a single process are not a paper
+        dynamic.max_processes = 1;
+        assert_ne!(Strategy::Paper, scores(&features, &dynamic)[0].0);
+    }
+}
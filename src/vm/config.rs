@@ -0,0 +1,291 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Core sizes the tools are built for, a `core_size` must be one of them.
+pub const CORE_SIZES: [usize; 4] = [800, 8000, 8192, 55440];
+
+/// Parameters of a battle. Defaults follow the ICWS'94 / pMARS standard hill.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VmConfig {
+    /// One of `CORE_SIZES`
+    pub core_size: usize,
+    pub max_processes: usize,
+    pub max_cycles: u128,
+    pub max_length: usize,
+    pub min_distance: usize,
+    pub read_limit: usize,
+    pub write_limit: usize,
+    /// P-space is not implemented, only the default is accepted
+    pub pspace_size: usize,
+    pub max_warriors: usize,
+    pub seed: Option<u64>,
+    pub first_mover: FirstMover,
+    pub engine: Engine,
+}
+
+/// How the warrior executing first is chosen in each round of a match.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FirstMover {
+    /// Round `r` starts with warrior `r % warriors`, as pMARS does
+    Rotate,
+    /// Drawn from the round seed
+    Random,
+}
+
+impl FromStr for FirstMover {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<FirstMover, String> {
+        match s.to_lowercase().as_str() {
+            "rotate" => Ok(FirstMover::Rotate),
+            "random" => Ok(FirstMover::Random),
+            _ => Err(format!("Invalid first mover policy: {}", s)),
+        }
+    }
+}
+
+/// Implementation of the instruction set executing the battle.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Engine {
+    /// One `match` over opcodes and modifiers, the behavior others are
+    /// checked against
+    Reference,
+    /// A handler compiled for each opcode, modifier and addressing modes
+    /// combination, picked from a table
+    Fast,
+}
+
+impl FromStr for Engine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Engine, String> {
+        match s.to_lowercase().as_str() {
+            "reference" => Ok(Engine::Reference),
+            "fast" => Ok(Engine::Fast),
+            _ => Err(format!("Invalid engine: {}", s)),
+        }
+    }
+}
+
+/// The limits a configuration file sets, if any.
+#[derive(Deserialize)]
+struct Limits {
+    read_limit: Option<usize>,
+    write_limit: Option<usize>,
+}
+
+impl Default for VmConfig {
+    fn default() -> VmConfig {
+        VmConfig {
+            core_size: 8000,
+            max_processes: 8000,
+            max_cycles: 80000,
+            max_length: 100,
+            min_distance: 100,
+            read_limit: 8000,
+            write_limit: 8000,
+            pspace_size: 500,
+            max_warriors: 50,
+            seed: None,
+            first_mover: FirstMover::Rotate,
+            engine: Engine::Reference,
+        }
+    }
+}
+
+impl VmConfig {
+    /// Loads a configuration from a `.toml` or `.json` file.
+    /// Missing keys take their default value.
+    pub fn load(path: &Path) -> Result<VmConfig, String> {
+        let body = fs::read_to_string(path)
+            .map_err(|e| format!("Can not open file {}: {}", path.display(), e))?;
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(format @ ("toml" | "json")) => VmConfig::parse(&body, format),
+            _ => Err(format!(
+                "Unsupported configuration format: {}",
+                path.display()
+            )),
+        }
+    }
+
+    /// Reads a configuration in the `toml` or `json` `format`. Missing keys
+    /// take their default value, but the read and write limits default to
+    /// the core size given.
+    pub fn parse(body: &str, format: &str) -> Result<VmConfig, String> {
+        fn from<T: DeserializeOwned>(body: &str, format: &str) -> Result<T, String> {
+            match format {
+                "toml" => toml::from_str(body).map_err(|e| e.to_string()),
+                "json" => serde_json::from_str(body).map_err(|e| e.to_string()),
+                _ => Err(format!("Unsupported configuration format: {}", format)),
+            }
+        }
+
+        let mut config: VmConfig = from(body, format)?;
+        let limits: Limits = from(body, format)?;
+        config.read_limit = limits.read_limit.unwrap_or(config.core_size);
+        config.write_limit = limits.write_limit.unwrap_or(config.core_size);
+
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !CORE_SIZES.contains(&self.core_size) {
+            return Err(format!(
+                "core_size must be one of {}",
+                CORE_SIZES.map(|size| size.to_string()).join(", ")
+            ));
+        }
+
+        if self.max_processes == 0 {
+            return Err("max_processes must be greater than 0".to_string());
+        }
+
+        if self.max_cycles == 0 {
+            return Err("max_cycles must be greater than 0".to_string());
+        }
+
+        if self.max_length == 0 || self.max_length > self.core_size {
+            return Err(format!(
+                "max_length must be between 1 and {}",
+                self.core_size
+            ));
+        }
+
+        if self.min_distance < self.max_length {
+            return Err("min_distance can not be smaller than max_length".to_string());
+        }
+
+        for (name, limit) in [
+            ("read_limit", self.read_limit),
+            ("write_limit", self.write_limit),
+        ] {
+            if limit == 0 || !self.core_size.is_multiple_of(limit) {
+                return Err(format!("{} must be a divisor of core_size", name));
+            }
+        }
+
+        if self.pspace_size != VmConfig::default().pspace_size {
+            return Err("P-space is not supported".to_string());
+        }
+
+        if self.max_warriors < 2 {
+            return Err("max_warriors must be at least 2".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+/// Core size of the configuration saved with a hill or a population in the
+/// JSON file `path`, to pick the `CORE_SIZE` to load it with.
+pub fn core_size_of(path: &Path) -> Result<usize, String> {
+    #[derive(Deserialize)]
+    struct Saved {
+        config: VmConfig,
+    }
+
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Can not open file {}: {}", path.display(), e))?;
+    let saved: Saved = serde_json::from_str(&content).map_err(|e| e.to_string())?;
+
+    Ok(saved.config.core_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_is_valid() {
+        assert_eq!(Ok(()), VmConfig::default().validate());
+    }
+
+    #[test]
+    fn partial_toml_uses_defaults() {
+        let config: VmConfig = toml::from_str("core_size = 800\nread_limit = 400").unwrap();
+
+        assert_eq!(800, config.core_size);
+        assert_eq!(400, config.read_limit);
+        assert_eq!(8000, config.max_processes);
+        assert_eq!(None, config.seed);
+        assert_eq!(FirstMover::Rotate, config.first_mover);
+        assert_eq!(Engine::Reference, config.engine);
+    }
+
+    #[test]
+    fn limits_follow_the_core_size_of_a_file() {
+        let config = VmConfig::parse("core_size = 800", "toml").unwrap();
+
+        assert_eq!((800, 800), (config.read_limit, config.write_limit));
+        assert_eq!(Ok(()), config.validate());
+
+        let config = VmConfig::parse(r#"{"core_size": 800, "write_limit": 400}"#, "json").unwrap();
+        assert_eq!((800, 400), (config.read_limit, config.write_limit));
+    }
+
+    #[test]
+    fn partial_json_uses_defaults() {
+        let config: VmConfig = serde_json::from_str(
+            r#"{"max_cycles": 500, "seed": 7, "first_mover": "random", "engine": "fast"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(500, config.max_cycles);
+        assert_eq!(Some(7), config.seed);
+        assert_eq!(FirstMover::Random, config.first_mover);
+        assert_eq!(Engine::Fast, config.engine);
+        assert_eq!(8000, config.core_size);
+    }
+
+    #[test]
+    fn limits_must_divide_core_size() {
+        let config = VmConfig {
+            read_limit: 3000,
+            ..VmConfig::default()
+        };
+
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn core_sizes_are_checked() {
+        for core_size in [0, 100, 8001] {
+            let config = VmConfig::parse(&format!("core_size = {}", core_size), "toml").unwrap();
+            assert_eq!(
+                Err("core_size must be one of 800, 8000, 8192, 55440".to_string()),
+                config.validate()
+            );
+        }
+        for core_size in CORE_SIZES {
+            let config = VmConfig::parse(&format!("core_size = {}", core_size), "toml").unwrap();
+            assert_eq!(Ok(()), config.validate());
+        }
+    }
+
+    #[test]
+    fn pspace_is_not_supported() {
+        let config = VmConfig::parse("core_size = 800\npspace_size = 100", "toml").unwrap();
+
+        assert_eq!(
+            Err("P-space is not supported".to_string()),
+            config.validate()
+        );
+    }
+
+    #[test]
+    fn min_distance_must_fit_a_warrior() {
+        let config = VmConfig {
+            min_distance: 50,
+            ..VmConfig::default()
+        };
+
+        assert!(config.validate().is_err());
+    }
+}
@@ -1,4 +1,8 @@
-pub trait Observer<T> {
+use super::instructions::Instruction;
+use serde::{Deserialize, Serialize};
+
+/// Observers are `Send` so that VMs can be played on other threads.
+pub trait Observer<T>: Send {
     fn notify(&self, event: T);
 }
 
@@ -6,19 +10,43 @@ pub trait Observable<T> {
     fn register(&mut self, observer: Box<dyn Observer<T>>);
 }
 
-#[derive(Clone, Copy)]
-pub struct VmEvent {
-    pub event_type: EventType,
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct VmEvent<const CORE_SIZE: usize> {
+    pub event_type: EventType<CORE_SIZE>,
     pub moved_from: Option<usize>,
     pub offset: Option<usize>,
     pub warrior_id: usize,
     pub round: u128,
 }
 
-#[derive(Clone, Copy)]
-pub enum EventType {
-    TerminatedProgram,
-    TerminatedThread,
-    Change,
+/// What happened, addresses being in the `moved_from` and `offset` fields of
+/// the event.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum EventType<const CORE_SIZE: usize> {
+    /// The warrior lost its last process
+    TerminatedProgram { cause: DeathCause },
+    /// The process running `moved_from` died, `processes` are left
+    TerminatedThread { cause: DeathCause, processes: usize },
+    /// `offset` was written, it now holds the instruction
+    Change(Instruction<CORE_SIZE>),
+    /// A process moved from `moved_from` to `offset`
     Jump,
+    /// The instruction at `offset` is executed, before any of its effects
+    Execute(Instruction<CORE_SIZE>),
+    /// The instruction at `moved_from` read its A target at `offset`
+    ReadA,
+    /// The instruction at `moved_from` read its B target at `offset`
+    ReadB,
+    /// `spl` at `moved_from` queued a new process at `offset`, the warrior
+    /// now has `processes`
+    Spawn { processes: usize },
+    /// `spl` at `moved_from` could not queue a process at `offset`, the
+    /// warrior already has the maximum number of processes
+    QueueFull,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum DeathCause {
+    Dat,
+    DivisionByZero,
 }
@@ -0,0 +1,522 @@
+use super::benchmark::benchmark;
+use super::config::VmConfig;
+use super::instructions::{Instruction, Modifier, OpCode, Operand, OperandMode};
+use super::numeric::Numeric;
+use super::rng::Rng;
+use super::vms::WarriorDefinition;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EvolverSettings {
+    /// Warriors in the population
+    pub size: usize,
+    /// Instructions of the longest warrior bred
+    pub max_length: usize,
+    /// Chance of every instruction to be mutated, and of a warrior to gain
+    /// or lose an instruction
+    pub mutation_rate: f64,
+    /// Best warriors copied unchanged into the next generation
+    pub elite: usize,
+    /// Rounds of each battle judging fitness
+    pub rounds: usize,
+    /// Members of the population each warrior battles, on top of the
+    /// reference suite. Can only be 0 with a suite.
+    pub sparring: usize,
+}
+
+impl Default for EvolverSettings {
+    fn default() -> EvolverSettings {
+        EvolverSettings {
+            size: 30,
+            max_length: 10,
+            mutation_rate: 0.1,
+            elite: 3,
+            rounds: 10,
+            sparring: 4,
+        }
+    }
+}
+
+/// Warriors bred generation after generation, kept on disk between runs.
+/// Everything random comes from the seed and the generation, so a run
+/// resumed from the file goes on as if it had never stopped.
+#[derive(Serialize, Deserialize)]
+pub struct Population<const CORE_SIZE: usize> {
+    pub config: VmConfig,
+    pub settings: EvolverSettings,
+    pub seed: u64,
+    pub generation: usize,
+    /// Number in the name of the next warrior born
+    pub next_id: usize,
+    /// Best first once evaluated
+    pub members: Vec<Individual<CORE_SIZE>>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Individual<const CORE_SIZE: usize> {
+    pub warrior: WarriorDefinition<CORE_SIZE>,
+    /// Benchmark score in its generation, `None` until evaluated
+    pub fitness: Option<f64>,
+    /// Generation it was born in
+    pub born: usize,
+}
+
+pub struct GenerationReport {
+    pub generation: usize,
+    pub best: String,
+    pub best_fitness: f64,
+    pub mean_fitness: f64,
+}
+
+impl<const CORE_SIZE: usize> Population<CORE_SIZE> {
+    /// A population of random warriors. Uses `config.seed` when set, a seed
+    /// from the clock otherwise.
+    pub fn new(
+        config: VmConfig,
+        settings: EvolverSettings,
+    ) -> Result<Population<CORE_SIZE>, String> {
+        config.validate()?;
+        if config.core_size != CORE_SIZE {
+            return Err(format!(
+                "Config core size {} does not match the population core size {}",
+                config.core_size, CORE_SIZE
+            ));
+        }
+        if settings.size < 2 || settings.elite >= settings.size {
+            return Err("A population needs 2 warriors and fewer elites than warriors".to_string());
+        }
+        if settings.max_length == 0 || settings.max_length > config.max_length {
+            return Err(format!(
+                "Warriors must be 1 to {} instructions long",
+                config.max_length
+            ));
+        }
+        if settings.rounds == 0 {
+            return Err("Battles need at least one round".to_string());
+        }
+
+        let mut population = Population {
+            seed: config.seed.unwrap_or_else(Rng::entropy_seed),
+            config,
+            settings,
+            generation: 0,
+            next_id: 0,
+            members: Vec::new(),
+        };
+
+        let mut rng = Rng::new(population.stream(Stream::Breed));
+        for _ in 0..population.settings.size {
+            let length = 1 + rng.below(population.settings.max_length);
+            let ops = (0..length)
+                .map(|_| random_instruction(&mut rng, length))
+                .collect();
+            population.add(ops);
+        }
+
+        Ok(population)
+    }
+
+    pub fn load(path: &Path) -> Result<Population<CORE_SIZE>, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Can not open file {}: {}", path.display(), e))?;
+
+        serde_json::from_str(&content).map_err(|e| e.to_string())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+
+        fs::write(path, content)
+            .map_err(|e| format!("Can not write file {}: {}", path.display(), e))
+    }
+
+    /// Breeds the next generation from the evaluated one, if any, then
+    /// evaluates every member against `suite` and some other members.
+    pub fn step(
+        &mut self,
+        suite: &[WarriorDefinition<CORE_SIZE>],
+        threads: usize,
+    ) -> Result<GenerationReport, String> {
+        if suite.is_empty() && self.settings.sparring == 0 {
+            return Err("Without a suite, warriors need sparring partners".to_string());
+        }
+        if self.members.iter().all(|m| m.fitness.is_some()) {
+            self.breed();
+        }
+        self.evaluate(suite, threads)?;
+
+        let best = &self.members[0];
+        let best_fitness = best.fitness.unwrap();
+        let mean_fitness = self.members.iter().map(|m| m.fitness.unwrap()).sum::<f64>()
+            / self.members.len() as f64;
+
+        Ok(GenerationReport {
+            generation: self.generation,
+            best: best.warrior.name.clone(),
+            best_fitness,
+            mean_fitness,
+        })
+    }
+
+    fn evaluate(
+        &mut self,
+        suite: &[WarriorDefinition<CORE_SIZE>],
+        threads: usize,
+    ) -> Result<(), String> {
+        // every member plays the same seeds, only its opponents differ
+        let config = VmConfig {
+            seed: Some(self.stream(Stream::Battles)),
+            ..self.config.clone()
+        };
+        let mut rng = Rng::new(self.stream(Stream::Sparring));
+
+        let fitness = (0..self.members.len())
+            .map(|ix| {
+                let mut others = (0..self.members.len())
+                    .filter(|&other| other != ix)
+                    .collect::<Vec<_>>();
+                rng.shuffle(&mut others);
+                let opponents = suite
+                    .iter()
+                    .cloned()
+                    .chain(
+                        others
+                            .iter()
+                            .take(self.settings.sparring)
+                            .map(|&other| self.members[other].warrior.clone()),
+                    )
+                    .collect::<Vec<_>>();
+
+                let report = benchmark(
+                    &config,
+                    &self.members[ix].warrior,
+                    &opponents,
+                    self.settings.rounds,
+                    threads,
+                )?;
+
+                Ok(report.score)
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        for (member, fitness) in self.members.iter_mut().zip(fitness) {
+            member.fitness = Some(fitness);
+        }
+        // stable: the elder first on equal fitness
+        self.members
+            .sort_by(|a, b| b.fitness.partial_cmp(&a.fitness).unwrap());
+
+        Ok(())
+    }
+
+    /// Keeps the elite and fills the rest with mutated children of parents
+    /// picked by tournaments.
+    fn breed(&mut self) {
+        self.generation += 1;
+        let mut rng = Rng::new(self.stream(Stream::Breed));
+
+        let parents = std::mem::take(&mut self.members);
+        self.members = parents[..self.settings.elite]
+            .iter()
+            .cloned()
+            .map(|member| Individual {
+                fitness: None,
+                ..member
+            })
+            .collect();
+
+        while self.members.len() < self.settings.size {
+            let mother = &tournament(&mut rng, &parents).warrior.ops;
+            let father = &tournament(&mut rng, &parents).warrior.ops;
+
+            let mut child = crossover(&mut rng, mother, father, self.settings.max_length);
+            mutate(
+                &mut rng,
+                &mut child,
+                self.settings.mutation_rate,
+                self.settings.max_length,
+            );
+            self.add(child);
+        }
+    }
+
+    fn add(&mut self, ops: Vec<Instruction<CORE_SIZE>>) {
+        let name = format!("evolved_{}", self.next_id);
+        self.next_id += 1;
+
+        self.members.push(Individual {
+            warrior: WarriorDefinition::new(name, ops),
+            fitness: None,
+            born: self.generation,
+        });
+    }
+
+    fn stream(&self, stream: Stream) -> u64 {
+        Rng::derive(self.seed, self.generation as u64 * 3 + stream as u64)
+    }
+}
+
+/// Random draws of a generation, each from its own seed.
+#[derive(Clone, Copy)]
+enum Stream {
+    Breed,
+    Sparring,
+    Battles,
+}
+
+/// The fittest of 3 members drawn at random.
+fn tournament<'a, const CORE_SIZE: usize>(
+    rng: &mut Rng,
+    members: &'a [Individual<CORE_SIZE>],
+) -> &'a Individual<CORE_SIZE> {
+    (0..3)
+        .map(|_| &members[rng.below(members.len())])
+        .max_by(|a, b| a.fitness.partial_cmp(&b.fitness).unwrap())
+        .unwrap()
+}
+
+/// The head of `mother` followed by the tail of `father`.
+fn crossover<const CORE_SIZE: usize>(
+    rng: &mut Rng,
+    mother: &[Instruction<CORE_SIZE>],
+    father: &[Instruction<CORE_SIZE>],
+    max_length: usize,
+) -> Vec<Instruction<CORE_SIZE>> {
+    let head = 1 + rng.below(mother.len());
+    let tail = rng.below(father.len() + 1);
+
+    mother[..head]
+        .iter()
+        .chain(father[tail..].iter())
+        .take(max_length)
+        .copied()
+        .collect()
+}
+
+fn mutate<const CORE_SIZE: usize>(
+    rng: &mut Rng,
+    ops: &mut Vec<Instruction<CORE_SIZE>>,
+    rate: f64,
+    max_length: usize,
+) {
+    let length = ops.len();
+    for op in ops.iter_mut() {
+        if !rng.chance(rate) {
+            continue;
+        }
+        match rng.below(6) {
+            0 => op.op = OpCode::ALL[rng.below(OpCode::ALL.len())],
+            1 => op.modifier = Modifier::ALL[rng.below(Modifier::ALL.len())],
+            2 => op.a_operand.mode = OperandMode::ALL[rng.below(OperandMode::ALL.len())],
+            3 => op.b_operand.mode = OperandMode::ALL[rng.below(OperandMode::ALL.len())],
+            4 => op.a_operand.pointer = random_pointer(rng, length),
+            _ => op.b_operand.pointer = random_pointer(rng, length),
+        }
+    }
+
+    if ops.len() < max_length && rng.chance(rate) {
+        let at = rng.below(ops.len() + 1);
+        ops.insert(at, random_instruction(rng, length));
+    }
+    if ops.len() > 1 && rng.chance(rate) {
+        ops.remove(rng.below(ops.len()));
+    }
+}
+
+fn random_instruction<const CORE_SIZE: usize>(
+    rng: &mut Rng,
+    length: usize,
+) -> Instruction<CORE_SIZE> {
+    let operand = |rng: &mut Rng| Operand {
+        pointer: random_pointer(rng, length),
+        mode: OperandMode::ALL[rng.below(OperandMode::ALL.len())],
+    };
+
+    Instruction {
+        op: OpCode::ALL[rng.below(OpCode::ALL.len())],
+        modifier: Modifier::ALL[rng.below(Modifier::ALL.len())],
+        a_operand: operand(rng),
+        b_operand: operand(rng),
+    }
+}
+
+/// Half of the time close to a warrior of `length` instructions, anywhere in
+/// the core otherwise.
+fn random_pointer<const CORE_SIZE: usize>(rng: &mut Rng, length: usize) -> Numeric<CORE_SIZE> {
+    if rng.chance(0.5) {
+        Numeric::new(CORE_SIZE + rng.below(4 * length + 1) - 2 * length)
+    } else {
+        Numeric::new(rng.below(CORE_SIZE))
+    }
+}
+
+impl fmt::Display for GenerationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "generation {}: best {} ({:.1}), mean {:.1}",
+            self.generation, self.best, self.best_fitness, self.mean_fitness
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::config::core_size_of;
+    use super::super::parser::parse;
+    use super::super::testing::temp_path;
+    use super::*;
+
+    fn settings() -> EvolverSettings {
+        EvolverSettings {
+            size: 8,
+            max_length: 6,
+            mutation_rate: 0.3,
+            elite: 2,
+            rounds: 2,
+            sparring: 2,
+        }
+    }
+
+    fn population(seed: u64) -> Population<800> {
+        let config = VmConfig {
+            core_size: 800,
+            read_limit: 800,
+            write_limit: 800,
+            max_cycles: 500,
+            seed: Some(seed),
+            ..VmConfig::default()
+        };
+
+        Population::new(config, settings()).unwrap()
+    }
+
+    #[test]
+    fn random_warriors_fit_the_settings() {
+        let population = population(1);
+
+        assert_eq!(8, population.members.len());
+        assert_eq!(8, population.next_id);
+        for member in population.members.iter() {
+            assert!((1..=6).contains(&member.warrior.ops.len()));
+            assert_eq!(None, member.fitness);
+        }
+    }
+
+    #[test]
+    fn generations_keep_the_elite() {
+        let mut population = population(2);
+        let suite = [WarriorDefinition::new(
+            "imp".to_string(),
+            parse("mov 0, 1".to_string()).unwrap(),
+        )];
+
+        let first = population.step(&suite, 1).unwrap();
+        assert_eq!(0, first.generation);
+        let elite = population.members[..2]
+            .iter()
+            .map(|m| m.warrior.clone())
+            .collect::<Vec<_>>();
+
+        let second = population.step(&suite, 1).unwrap();
+        assert_eq!(1, second.generation);
+        assert_eq!(8, population.members.len());
+        for warrior in elite.iter() {
+            assert!(population.members.iter().any(|m| &m.warrior == warrior));
+        }
+        for member in population.members.iter() {
+            assert!((1..=6).contains(&member.warrior.ops.len()));
+            assert!(member.fitness.is_some());
+        }
+        assert!(population
+            .members
+            .windows(2)
+            .all(|w| w[0].fitness >= w[1].fitness));
+    }
+
+    #[test]
+    fn a_saved_population_resumes_where_it_stopped() {
+        let path = temp_path("population.json");
+        let mut straight = population(3);
+        let mut resumed = population(3);
+
+        straight.step(&[], 1).unwrap();
+        straight.step(&[], 1).unwrap();
+        resumed.step(&[], 1).unwrap();
+        resumed.save(&path).unwrap();
+        let mut resumed = Population::<800>::load(&path).unwrap();
+        assert_eq!(800, core_size_of(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+        resumed.step(&[], 1).unwrap();
+
+        let names = |p: &Population<800>| {
+            p.members
+                .iter()
+                .map(|m| m.warrior.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&straight), names(&resumed));
+    }
+
+    #[test]
+    fn warriors_need_opponents() {
+        let config = VmConfig {
+            seed: Some(5),
+            ..VmConfig::default()
+        };
+        let settings = EvolverSettings {
+            sparring: 0,
+            ..settings()
+        };
+        let mut population = Population::<8000>::new(config, settings).unwrap();
+        let suite = [WarriorDefinition::new(
+            "imp".to_string(),
+            parse("mov 0, 1".to_string()).unwrap(),
+        )];
+
+        assert!(population.step(&[], 1).is_err());
+        assert!(population.members.iter().all(|m| m.fitness.is_none()));
+        assert!(population.step(&suite, 1).is_ok());
+    }
+
+    #[test]
+    fn children_mix_their_parents() {
+        let mut rng = Rng::new(4);
+        let parse = |code: &str| parse::<800>(code.to_string()).unwrap();
+        let mother = parse("mov 0, 1\nmov 0, 2\nmov 0, 3");
+        let father = parse("jmp 0, 1\njmp 0, 2\njmp 0, 3");
+
+        for _ in 0..50 {
+            let child = crossover(&mut rng, &mother, &father, 4);
+
+            assert!(!child.is_empty() && child.len() <= 4);
+            assert!(child[0] == mother[0]);
+            let tail = child.iter().skip_while(|op| op.op == OpCode::Mov);
+            assert!(tail.clone().all(|op| op.op == OpCode::Jmp));
+        }
+    }
+
+    #[test]
+    fn invalid_settings_are_rejected() {
+        let config = VmConfig::default();
+        let with =
+            |settings: EvolverSettings| Population::<8000>::new(config.clone(), settings).is_err();
+
+        assert!(with(EvolverSettings {
+            elite: 8,
+            ..settings()
+        }));
+        assert!(with(EvolverSettings {
+            max_length: 0,
+            ..settings()
+        }));
+        assert!(with(EvolverSettings {
+            size: 1,
+            elite: 0,
+            ..settings()
+        }));
+    }
+}
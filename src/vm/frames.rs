@@ -0,0 +1,312 @@
+use super::event::{EventType, Observer, VmEvent};
+use super::image::{Image, Rgb};
+use super::palette::Palette;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Where frames go: an animated GIF, or numbered PNG files in a folder.
+pub enum FrameWriter {
+    Gif {
+        encoder: gif::Encoder<BufWriter<File>>,
+        /// Hundredths of a second each frame is shown
+        delay: u16,
+        scale: usize,
+    },
+    Png {
+        folder: PathBuf,
+        scale: usize,
+        written: usize,
+    },
+}
+
+impl FrameWriter {
+    /// A GIF for a `.gif` path, a folder of PNG files otherwise. Frames are
+    /// the core `scale` times larger, as `Image::of_core` lays it out.
+    pub fn create(
+        path: &Path,
+        core_size: usize,
+        scale: usize,
+        delay: u16,
+    ) -> Result<FrameWriter, String> {
+        let error = |e: String| format!("Can not write {}: {}", path.display(), e);
+        let is_gif = path
+            .extension()
+            .map(|e| e.eq_ignore_ascii_case("gif"))
+            .unwrap_or(false);
+
+        if is_gif {
+            let size = Image::of_core(core_size, scale);
+            if size.width > u16::MAX as usize || size.height > u16::MAX as usize {
+                return Err(error("too large for a GIF".to_string()));
+            }
+            let file = File::create(path).map_err(|e| error(e.to_string()))?;
+            let mut encoder = gif::Encoder::new(
+                BufWriter::new(file),
+                size.width as u16,
+                size.height as u16,
+                &[],
+            )
+            .map_err(|e| error(e.to_string()))?;
+            encoder
+                .set_repeat(gif::Repeat::Infinite)
+                .map_err(|e| error(e.to_string()))?;
+
+            Ok(FrameWriter::Gif {
+                encoder,
+                delay,
+                scale,
+            })
+        } else {
+            fs::create_dir_all(path).map_err(|e| error(e.to_string()))?;
+
+            Ok(FrameWriter::Png {
+                folder: path.to_path_buf(),
+                scale,
+                written: 0,
+            })
+        }
+    }
+
+    /// Writes `core`, drawn with one pixel per cell.
+    pub fn write(&mut self, core: &Image) -> Result<(), String> {
+        match self {
+            FrameWriter::Gif {
+                encoder,
+                delay,
+                scale,
+            } => {
+                let image = core.scaled(*scale);
+                let mut frame = match indexed(&image) {
+                    Some((palette, pixels)) => gif::Frame::from_palette_pixels(
+                        image.width as u16,
+                        image.height as u16,
+                        pixels,
+                        palette,
+                        None,
+                    ),
+                    None => {
+                        gif::Frame::from_rgb(image.width as u16, image.height as u16, &image.pixels)
+                    }
+                };
+                frame.delay = *delay;
+
+                encoder.write_frame(&frame).map_err(|e| e.to_string())
+            }
+            FrameWriter::Png {
+                folder,
+                scale,
+                written,
+            } => {
+                let path = folder.join(format!("frame_{:05}.png", written));
+                *written += 1;
+                core.scaled(*scale).save(&path)
+            }
+        }
+    }
+}
+
+/// The palette and the palette index of each pixel, `None` past the 256
+/// colors a GIF frame can have.
+fn indexed(image: &Image) -> Option<(Vec<u8>, Vec<u8>)> {
+    let mut colors: Vec<Rgb> = Vec::new();
+    let mut pixels = Vec::with_capacity(image.width * image.height);
+
+    for pixel in image.pixels.chunks(3) {
+        let color = [pixel[0], pixel[1], pixel[2]];
+        let ix = match colors.iter().position(|c| *c == color) {
+            Some(ix) => ix,
+            None if colors.len() < 256 => {
+                colors.push(color);
+                colors.len() - 1
+            }
+            None => return None,
+        };
+        pixels.push(ix as u8);
+    }
+
+    Some((colors.concat(), pixels))
+}
+
+struct State {
+    /// One pixel per cell, drawn as the SDL display does
+    core: Image,
+    every: u128,
+    next_frame: u128,
+    frames: usize,
+    writer: Option<FrameWriter>,
+    error: Option<String>,
+}
+
+impl State {
+    fn write_frame(&mut self) {
+        if let Some(writer) = self.writer.as_mut() {
+            match writer.write(&self.core) {
+                Ok(()) => self.frames += 1,
+                Err(e) => {
+                    // no point going on, keep the first error for `finish`
+                    self.error = Some(e);
+                    self.writer = None;
+                }
+            }
+        }
+    }
+}
+
+/// Observer drawing the core every `every` cycles into a `FrameWriter`.
+/// Call `finish` on a `FramesHandle` kept before registering it to write
+/// the last frame once the battle is over.
+pub struct FrameRecorder<const CORE_SIZE: usize> {
+    state: Arc<Mutex<State>>,
+    palette: Palette,
+}
+
+#[derive(Clone)]
+pub struct FramesHandle {
+    state: Arc<Mutex<State>>,
+}
+
+impl<const CORE_SIZE: usize> FrameRecorder<CORE_SIZE> {
+    pub fn new(
+        writer: FrameWriter,
+        every: u128,
+        palette: Palette,
+    ) -> Box<FrameRecorder<CORE_SIZE>> {
+        Box::new(FrameRecorder {
+            state: Arc::new(Mutex::new(State {
+                core: Image::of_core(CORE_SIZE, 1),
+                every: every.max(1),
+                next_frame: 0,
+                frames: 0,
+                writer: Some(writer),
+                error: None,
+            })),
+            palette,
+        })
+    }
+
+    pub fn handle(&self) -> FramesHandle {
+        FramesHandle {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl FramesHandle {
+    /// Writes the last frame and closes the writer, returns the number of
+    /// frames written.
+    pub fn finish(&self) -> Result<usize, String> {
+        let mut state = self.state.lock().unwrap();
+        state.write_frame();
+        // a GIF is terminated when its encoder is dropped
+        state.writer = None;
+
+        match state.error.take() {
+            Some(e) => Err(e),
+            None => Ok(state.frames),
+        }
+    }
+}
+
+impl<const CORE_SIZE: usize> Observer<VmEvent<CORE_SIZE>> for FrameRecorder<CORE_SIZE> {
+    fn notify(&self, event: VmEvent<CORE_SIZE>) {
+        let mut state = self.state.lock().unwrap();
+        // a frame shows the core before the first event of its cycle
+        while event.round >= state.next_frame {
+            state.write_frame();
+            state.next_frame += state.every;
+        }
+
+        let offset = event.offset.unwrap_or(0);
+        let moved_from = event.moved_from.unwrap_or(0);
+        let color = self.palette.color(event.warrior_id);
+        match event.event_type {
+            EventType::TerminatedThread { .. } => state.core.set_cell(moved_from, 1, color),
+            EventType::Jump => {
+                state.core.set_cell(moved_from, 1, color);
+                state
+                    .core
+                    .set_cell(offset, 1, self.palette.light(event.warrior_id));
+            }
+            EventType::Change(_) => state.core.set_cell(offset, 1, color),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::event::Observable;
+    use super::super::testing::{temp_path, vm};
+    use super::super::vms::Outcome;
+    use super::*;
+
+    fn record(path: &Path, scale: usize) -> usize {
+        let mut vm = vm(&["jmp 0, 0", "mov 0, 1"], 250);
+        let writer = FrameWriter::create(path, 8000, scale, 4).unwrap();
+        let recorder = FrameRecorder::new(writer, 100, Palette::default());
+        let handle = recorder.handle();
+        vm.register(recorder);
+
+        while let Outcome::Running = vm.play(i32::MAX) {}
+
+        handle.finish().unwrap()
+    }
+
+    #[test]
+    fn png_frames_are_numbered() {
+        let folder = temp_path("frames");
+
+        // cycles 0, 100 and 200, then the end
+        assert_eq!(4, record(&folder, 1));
+
+        let last = fs::File::open(folder.join("frame_00003.png")).unwrap();
+        let mut reader = png::Decoder::new(last).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        let ix = |x: usize, y: usize| (y * 100 + x) * 3;
+        // written by the imp, the cell of its process is lighter
+        assert_eq!(
+            Palette::default().color(1),
+            pixels[ix(1, 40)..ix(1, 40) + 3]
+        );
+        assert_eq!(
+            Palette::default().light(1),
+            pixels[ix(50, 42)..ix(50, 42) + 3]
+        );
+        assert_eq!([0, 0, 0], pixels[ix(51, 42)..ix(51, 42) + 3]);
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn gif_frames_are_animated() {
+        let path = temp_path("battle.gif");
+
+        assert_eq!(4, record(&path, 2));
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::RGBA);
+        let mut decoder = options.read_info(File::open(&path).unwrap()).unwrap();
+        assert_eq!((200, 160), (decoder.width(), decoder.height()));
+        let mut frames = 0;
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!(4, frame.delay);
+            frames += 1;
+        }
+        assert_eq!(4, frames);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn colors_are_indexed() {
+        let mut image = Image::new(3, 1);
+        image.set_pixel(1, 0, [1, 2, 3]);
+
+        let (palette, pixels) = indexed(&image).unwrap();
+        assert_eq!(vec![0, 0, 0, 1, 2, 3], palette);
+        assert_eq!(vec![0, 1, 0], pixels);
+    }
+}
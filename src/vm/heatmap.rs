@@ -0,0 +1,216 @@
+use super::event::{EventType, Observer, VmEvent};
+use super::image::{heat_color, Image, BLACK, CORE_WIDTH};
+use super::palette::Palette;
+use std::fmt::Write;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+/// What a heatmap image shows of each cell.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Layer {
+    /// The color of the warrior that last wrote the cell
+    Owner,
+    /// How often the cell was written, relative to the most written one
+    Writes,
+    /// How often the cell was executed, relative to the most executed one
+    Executions,
+}
+
+impl FromStr for Layer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Layer, String> {
+        match s.to_lowercase().as_str() {
+            "owner" => Ok(Layer::Owner),
+            "writes" => Ok(Layer::Writes),
+            "executions" => Ok(Layer::Executions),
+            _ => Err(format!("Invalid heatmap layer: {}", s)),
+        }
+    }
+}
+
+/// Per cell counters of a battle.
+#[derive(Clone)]
+pub struct CoreHeat {
+    /// Warrior that last wrote each cell
+    pub owners: Vec<Option<usize>>,
+    pub writes: Vec<u64>,
+    pub executions: Vec<u64>,
+}
+
+/// Observer tracking who wrote each cell last and how often each cell was
+/// written and executed. Keep a `HeatmapHandle` before registering it.
+pub struct Heatmap<const CORE_SIZE: usize> {
+    heat: Arc<Mutex<CoreHeat>>,
+}
+
+#[derive(Clone)]
+pub struct HeatmapHandle {
+    heat: Arc<Mutex<CoreHeat>>,
+}
+
+impl<const CORE_SIZE: usize> Heatmap<CORE_SIZE> {
+    pub fn new() -> Box<Heatmap<CORE_SIZE>> {
+        Box::new(Heatmap {
+            heat: Arc::new(Mutex::new(CoreHeat {
+                owners: vec![None; CORE_SIZE],
+                writes: vec![0; CORE_SIZE],
+                executions: vec![0; CORE_SIZE],
+            })),
+        })
+    }
+
+    pub fn handle(&self) -> HeatmapHandle {
+        HeatmapHandle {
+            heat: self.heat.clone(),
+        }
+    }
+}
+
+impl HeatmapHandle {
+    pub fn heat(&self) -> CoreHeat {
+        self.heat.lock().unwrap().clone()
+    }
+}
+
+impl<const CORE_SIZE: usize> Observer<VmEvent<CORE_SIZE>> for Heatmap<CORE_SIZE> {
+    fn notify(&self, event: VmEvent<CORE_SIZE>) {
+        let address = event.offset.unwrap_or(0);
+        match event.event_type {
+            EventType::Change(_) => {
+                let mut heat = self.heat.lock().unwrap();
+                heat.owners[address] = Some(event.warrior_id);
+                heat.writes[address] += 1;
+            }
+            EventType::Execute(_) => {
+                self.heat.lock().unwrap().executions[address] += 1;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl CoreHeat {
+    /// The core `CORE_WIDTH` cells wide, each cell a `scale` pixels square.
+    /// Counts are drawn on a log scale, cells never touched stay black.
+    pub fn render(&self, layer: Layer, scale: usize, palette: &Palette) -> Image {
+        let mut image = Image::of_core(self.owners.len(), scale);
+
+        let heat_of = |counts: &[u64]| {
+            let max = (counts.iter().copied().max().unwrap_or(0) as f64).ln_1p();
+            counts
+                .iter()
+                .map(|c| match c {
+                    0 => BLACK,
+                    c => heat_color((*c as f64).ln_1p() / max),
+                })
+                .collect::<Vec<_>>()
+        };
+        let colors = match layer {
+            Layer::Owner => self
+                .owners
+                .iter()
+                .map(|o| o.map(|id| palette.color(id)).unwrap_or(BLACK))
+                .collect(),
+            Layer::Writes => heat_of(&self.writes),
+            Layer::Executions => heat_of(&self.executions),
+        };
+
+        for (address, color) in colors.into_iter().enumerate() {
+            image.set_cell(address, scale, color);
+        }
+
+        image
+    }
+
+    /// One line per cell, with its position on the images
+    pub fn to_csv(&self) -> String {
+        let mut csv = "address,x,y,owner,writes,executions\n".to_string();
+        for address in 0..self.owners.len() {
+            let owner = self.owners[address]
+                .map(|o| o.to_string())
+                .unwrap_or_default();
+            writeln!(
+                csv,
+                "{},{},{},{},{},{}",
+                address,
+                address % CORE_WIDTH,
+                address / CORE_WIDTH,
+                owner,
+                self.writes[address],
+                self.executions[address]
+            )
+            .unwrap();
+        }
+
+        csv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::event::Observable;
+    use super::super::image::heat_color;
+    use super::super::testing::vm;
+    use super::super::vms::Outcome;
+    use super::*;
+
+    fn heat(codes: &[&str], max_cycles: u128) -> CoreHeat {
+        let mut vm = vm(codes, max_cycles);
+        let heatmap = Heatmap::new();
+        let handle = heatmap.handle();
+        vm.register(heatmap);
+
+        while let Outcome::Running = vm.play(i32::MAX) {}
+
+        handle.heat()
+    }
+
+    #[test]
+    fn writes_and_executions_are_counted() {
+        // the dwarf bombs every 4th cell from its dat, 3 cells below the add
+        let heat = heat(
+            &["jmp 0, 0", "add #4, 3\nmov 2, @2\njmp -2, 0\ndat #0, #0"],
+            30,
+        );
+
+        assert_eq!(30, heat.executions[0]);
+        assert_eq!(10, heat.executions[4000]);
+        assert_eq!(10, heat.writes[4003]);
+        assert_eq!(Some(1), heat.owners[4007]);
+        assert_eq!(1, heat.writes[4007]);
+        assert_eq!(None, heat.owners[4005]);
+        assert_eq!(None, heat.owners[0]);
+    }
+
+    #[test]
+    fn layers_are_drawn() {
+        let heat = heat(&["jmp 0, 0", "mov 0, 1"], 10);
+
+        let palette = Palette::colorblind();
+        let owner = heat.render(Layer::Owner, 1, &palette);
+        assert_eq!(palette.color(1), owner.pixel(1, 40));
+        assert_eq!(BLACK, owner.pixel(0, 40));
+
+        let executions = heat.render(Layer::Executions, 1, &palette);
+        assert_eq!(heat_color(1.0), executions.pixel(0, 0));
+        assert_eq!(BLACK, executions.pixel(1, 0));
+    }
+
+    #[test]
+    fn cells_are_listed_in_csv() {
+        let heat = heat(&["jmp 0, 0", "mov 0, 1"], 10);
+        let csv = heat.to_csv();
+        let lines = csv.lines().collect::<Vec<_>>();
+
+        assert_eq!(8001, lines.len());
+        assert_eq!("0,0,0,,0,10", lines[1]);
+        assert_eq!("4001,1,40,1,1,1", lines[4002]);
+    }
+
+    #[test]
+    fn layers_are_parsed() {
+        assert_eq!(Ok(Layer::Writes), "Writes".parse());
+        assert!("heat".parse::<Layer>().is_err());
+    }
+}
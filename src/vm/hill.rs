@@ -0,0 +1,378 @@
+use super::config::VmConfig;
+use super::rng::Rng;
+use super::runner::{MatchRunner, Score};
+use super::vms::WarriorDefinition;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// A king of the hill: the `size` best warriors submitted so far. Every
+/// member has played `rounds` one-on-one rounds against every other member,
+/// its score is the sum of its points against each of them, see
+/// `Score::points`.
+#[derive(Serialize, Deserialize)]
+pub struct Hill<const CORE_SIZE: usize> {
+    pub config: VmConfig,
+    pub size: usize,
+    pub rounds: usize,
+    /// Seeds of the pairings are derived from it, see `submit`
+    pub seed: u64,
+    /// Pairings played since the hill was created
+    pub pairings: u64,
+    pub members: Vec<Member<CORE_SIZE>>,
+    /// Warriors pushed off the hill, oldest first
+    pub pushed_off: Vec<PushedOff>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Member<const CORE_SIZE: usize> {
+    pub warrior: WarriorDefinition<CORE_SIZE>,
+    /// Challengers survived since it entered the hill
+    pub age: usize,
+    pub results: Vec<Pairing>,
+}
+
+/// Outcome of the rounds a member played against one opponent.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Pairing {
+    pub opponent: String,
+    pub score: Score,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PushedOff {
+    pub name: String,
+    pub age: usize,
+    pub score: f64,
+}
+
+/// What became of a submitted warrior.
+pub struct Submission {
+    /// Rank of the challenger starting at 1, `None` if it didn't make it
+    pub rank: Option<usize>,
+    pub pushed_off: Option<PushedOff>,
+}
+
+#[derive(Serialize)]
+pub struct HillReport {
+    pub size: usize,
+    pub rounds: usize,
+    pub ranking: Vec<Ranked>,
+    pub pushed_off: Vec<PushedOff>,
+}
+
+#[derive(Serialize)]
+pub struct Ranked {
+    pub rank: usize,
+    pub name: String,
+    pub score: f64,
+    pub wins: usize,
+    pub losses: usize,
+    pub ties: usize,
+    pub age: usize,
+}
+
+impl<const CORE_SIZE: usize> Member<CORE_SIZE> {
+    pub fn score(&self) -> f64 {
+        self.results
+            .iter()
+            .map(|p| p.score.points())
+            // a plain sum of nothing is -0
+            .fold(0.0, |total, points| total + points)
+    }
+
+    fn total(&self) -> Score {
+        self.results
+            .iter()
+            .fold(Score::default(), |total, p| Score {
+                wins: total.wins + p.score.wins,
+                losses: total.losses + p.score.losses,
+                ties: total.ties + p.score.ties,
+            })
+    }
+}
+
+impl<const CORE_SIZE: usize> Hill<CORE_SIZE> {
+    /// Uses `config.seed` when set, a seed from the clock otherwise.
+    pub fn new(config: VmConfig, size: usize, rounds: usize) -> Result<Hill<CORE_SIZE>, String> {
+        config.validate()?;
+        if config.core_size != CORE_SIZE {
+            return Err(format!(
+                "Config core size {} does not match the hill core size {}",
+                config.core_size, CORE_SIZE
+            ));
+        }
+        if size == 0 || rounds == 0 {
+            return Err("A hill needs a size and a number of rounds".to_string());
+        }
+
+        Ok(Hill {
+            seed: config.seed.unwrap_or_else(Rng::entropy_seed),
+            config,
+            size,
+            rounds,
+            pairings: 0,
+            members: Vec::new(),
+            pushed_off: Vec::new(),
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Hill<CORE_SIZE>, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Can not open file {}: {}", path.display(), e))?;
+
+        serde_json::from_str(&content).map_err(|e| e.to_string())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+
+        fs::write(path, content)
+            .map_err(|e| format!("Can not write file {}: {}", path.display(), e))
+    }
+
+    /// Plays `warrior` against every member, on `threads` threads, and
+    /// inserts it. When the hill overflows the lowest scorer, possibly the
+    /// challenger, is pushed off. Members still there get one year older.
+    pub fn submit(
+        &mut self,
+        warrior: WarriorDefinition<CORE_SIZE>,
+        threads: usize,
+    ) -> Result<Submission, String> {
+        if self.members.iter().any(|m| m.warrior.name == warrior.name) {
+            return Err(format!("{} is already on the hill", warrior.name));
+        }
+
+        let mut challenger = Member {
+            warrior,
+            age: 0,
+            results: Vec::new(),
+        };
+        let mut results = Vec::with_capacity(self.members.len());
+        for (pairing, member) in self.members.iter().enumerate() {
+            let config = VmConfig {
+                seed: Some(Rng::derive(self.seed, self.pairings + pairing as u64)),
+                ..self.config.clone()
+            };
+            let runner = MatchRunner::new(
+                config,
+                vec![challenger.warrior.clone(), member.warrior.clone()],
+            );
+            results.push(runner.play_parallel(self.rounds, threads)?.scores());
+        }
+        // nothing changes unless every pairing could be played
+        self.pairings += self.members.len() as u64;
+
+        for (member, scores) in self.members.iter_mut().zip(results) {
+            challenger.results.push(Pairing {
+                opponent: member.warrior.name.clone(),
+                score: scores[0].clone(),
+            });
+            member.results.push(Pairing {
+                opponent: challenger.warrior.name.clone(),
+                score: scores[1].clone(),
+            });
+        }
+        let name = challenger.warrior.name.clone();
+        self.members.push(challenger);
+
+        let pushed_off = if self.members.len() > self.size {
+            Some(self.push_off_lowest())
+        } else {
+            None
+        };
+        for member in self.members.iter_mut() {
+            if member.warrior.name != name {
+                member.age += 1;
+            }
+        }
+
+        self.sort();
+        let rank = self
+            .members
+            .iter()
+            .position(|m| m.warrior.name == name)
+            .map(|ix| ix + 1);
+
+        Ok(Submission { rank, pushed_off })
+    }
+
+    fn push_off_lowest(&mut self) -> PushedOff {
+        self.sort();
+        let lowest = self.members.pop().unwrap();
+        for member in self.members.iter_mut() {
+            member.results.retain(|p| p.opponent != lowest.warrior.name);
+        }
+
+        let pushed_off = PushedOff {
+            score: lowest.score(),
+            name: lowest.warrior.name,
+            age: lowest.age,
+        };
+        self.pushed_off.push(pushed_off.clone());
+
+        pushed_off
+    }
+
+    /// Best first, the older one first on equal scores.
+    fn sort(&mut self) {
+        self.members.sort_by(|a, b| {
+            b.score()
+                .partial_cmp(&a.score())
+                .unwrap()
+                .then(b.age.cmp(&a.age))
+        });
+    }
+
+    pub fn report(&self) -> HillReport {
+        let ranking = self
+            .members
+            .iter()
+            .enumerate()
+            .map(|(ix, member)| {
+                let total = member.total();
+                Ranked {
+                    rank: ix + 1,
+                    name: member.warrior.name.clone(),
+                    score: member.score(),
+                    wins: total.wins,
+                    losses: total.losses,
+                    ties: total.ties,
+                    age: member.age,
+                }
+            })
+            .collect();
+
+        HillReport {
+            size: self.size,
+            rounds: self.rounds,
+            ranking,
+            pushed_off: self.pushed_off.clone(),
+        }
+    }
+}
+
+impl fmt::Display for HillReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>4}  {:<24}{:>9}{:>7}{:>7}{:>7}{:>5}",
+            "rank", "name", "score", "wins", "losses", "ties", "age"
+        )?;
+        for r in self.ranking.iter() {
+            writeln!(
+                f,
+                "{:>4}  {:<24}{:>9.1}{:>7}{:>7}{:>7}{:>5}",
+                r.rank, r.name, r.score, r.wins, r.losses, r.ties, r.age
+            )?;
+        }
+        if let Some(last) = self.pushed_off.last() {
+            writeln!(
+                f,
+                "last pushed off: {} (age {}, score {:.1})",
+                last.name, last.age, last.score
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::config::core_size_of;
+    use super::super::testing::{temp_path, warrior};
+    use super::*;
+
+    fn hill(size: usize) -> Hill<8000> {
+        let config = VmConfig {
+            seed: Some(11),
+            max_cycles: 2000,
+            ..VmConfig::default()
+        };
+
+        Hill::new(config, size, 6).unwrap()
+    }
+
+    #[test]
+    fn the_lowest_scorer_is_pushed_off() {
+        let mut hill = hill(2);
+
+        let first = hill.submit(warrior("imp", "mov 0, 1"), 2).unwrap();
+        assert_eq!(Some(1), first.rank);
+        hill.submit(
+            warrior("dwarf", "add #4, 3\nmov 2, @2\njmp -2, 0\ndat #0, #0"),
+            2,
+        )
+        .unwrap();
+        let last = hill.submit(warrior("dead", "dat #0, #0"), 2).unwrap();
+
+        assert_eq!(None, last.rank);
+        let pushed_off = last.pushed_off.unwrap();
+        assert_eq!("dead", pushed_off.name);
+        assert_eq!(0, pushed_off.age);
+        assert_eq!(2, hill.members.len());
+        for member in hill.members.iter() {
+            assert_eq!(1, member.results.len());
+            assert!(member.results.iter().all(|p| p.opponent != "dead"));
+        }
+        assert_eq!(3, hill.pairings);
+    }
+
+    #[test]
+    fn members_age_with_every_challenger() {
+        let mut hill = hill(3);
+
+        hill.submit(warrior("imp", "mov 0, 1"), 1).unwrap();
+        hill.submit(warrior("dead", "dat #0, #0"), 1).unwrap();
+        hill.submit(warrior("dead2", "dat #1, #0"), 1).unwrap();
+
+        let ages = hill
+            .members
+            .iter()
+            .map(|m| (m.warrior.name.as_str(), m.age))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![("imp", 2), ("dead", 1), ("dead2", 0)], ages);
+    }
+
+    #[test]
+    fn scores_follow_the_koth_formula() {
+        let mut hill = hill(2);
+
+        hill.submit(warrior("imp", "mov 0, 1"), 1).unwrap();
+        hill.submit(warrior("dead", "dat #0, #0"), 1).unwrap();
+
+        let report = hill.report();
+        assert_eq!("imp", report.ranking[0].name);
+        assert_eq!(300.0, report.ranking[0].score);
+        assert_eq!(6, report.ranking[0].wins);
+        assert_eq!(0.0, report.ranking[1].score);
+        assert_eq!(6, report.ranking[1].losses);
+    }
+
+    #[test]
+    fn names_are_unique() {
+        let mut hill = hill(2);
+
+        hill.submit(warrior("imp", "mov 0, 1"), 1).unwrap();
+        assert!(hill.submit(warrior("imp", "mov 0, 2"), 1).is_err());
+    }
+
+    #[test]
+    fn hills_are_saved_and_loaded() {
+        let path = temp_path("hill.json");
+        let mut hill = hill(2);
+        hill.submit(warrior("imp", "mov 0, 1"), 1).unwrap();
+        hill.submit(warrior("dead", "dat #0, #0"), 1).unwrap();
+
+        hill.save(&path).unwrap();
+        let loaded = Hill::<8000>::load(&path).unwrap();
+        assert_eq!(8000, core_size_of(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(hill.seed, loaded.seed);
+        assert_eq!(hill.pairings, loaded.pairings);
+        assert_eq!(hill.report().to_string(), loaded.report().to_string());
+    }
+}
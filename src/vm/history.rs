@@ -0,0 +1,132 @@
+use super::vms::{Vm, VmSnapshot};
+use std::collections::VecDeque;
+
+/// Ring buffer of snapshots taken every `interval` instructions, to step a
+/// `Vm` backward. Only the last `capacity` snapshots are kept, so the VM can
+/// be rewound up to `capacity * interval` instructions.
+pub struct History<const CORE_SIZE: usize> {
+    snapshots: VecDeque<VmSnapshot<CORE_SIZE>>,
+    capacity: usize,
+    interval: u128,
+}
+
+impl<const CORE_SIZE: usize> History<CORE_SIZE> {
+    pub fn new(capacity: usize, interval: u128) -> History<CORE_SIZE> {
+        History {
+            snapshots: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+            interval: interval.max(1),
+        }
+    }
+
+    /// Takes a snapshot if `interval` instructions were executed since the
+    /// last one. Call it after every `Vm::play`.
+    pub fn record(&mut self, vm: &Vm<CORE_SIZE>) {
+        let due = match self.snapshots.back() {
+            Some(last) => vm.ticks() >= last.ticks() + self.interval,
+            None => true,
+        };
+
+        if due {
+            if self.snapshots.len() == self.capacity {
+                self.snapshots.pop_front();
+            }
+            self.snapshots.push_back(vm.snapshot());
+        }
+    }
+
+    /// Instructions executed at the oldest point the VM can be rewound to.
+    pub fn oldest(&self) -> Option<u128> {
+        self.snapshots.front().map(|s| s.ticks())
+    }
+
+    /// Moves `vm` back by `ticks` instructions: restores the closest earlier
+    /// snapshot and plays forward from it, observers seeing those
+    /// instructions again. Snapshots taken after the new position are
+    /// dropped.
+    pub fn rewind(&mut self, vm: &mut Vm<CORE_SIZE>, ticks: u128) -> Result<(), String> {
+        let target = vm.ticks().saturating_sub(ticks);
+        if self.oldest().map(|t| t > target) != Some(false) {
+            return Err("Can not rewind that far".to_string());
+        }
+
+        while self.snapshots.back().map(|s| s.ticks() > target) == Some(true) {
+            self.snapshots.pop_back();
+        }
+
+        vm.restore(self.snapshots.back().unwrap())?;
+
+        while vm.ticks() < target {
+            let remaining = (target - vm.ticks()).min(i32::MAX as u128) as i32;
+            vm.play(remaining);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::config::VmConfig;
+    use super::super::parser::parse;
+    use super::super::vms::WarriorDefinition;
+    use super::*;
+
+    fn vm() -> Vm<8000> {
+        let warriors = [
+            "spl 0, 0\nmov 0, 1",
+            "add #4, 3\nmov 2, @2\njmp -2, 0\ndat #0, #0",
+        ]
+        .iter()
+        .map(|code| WarriorDefinition::new("w".to_string(), parse(code.to_string()).unwrap()))
+        .collect();
+
+        Vm::new(VmConfig::default(), warriors).unwrap()
+    }
+
+    #[test]
+    fn restore_brings_back_the_state() {
+        let mut vm = vm();
+        vm.play(100);
+        let snapshot = vm.snapshot();
+
+        vm.play(500);
+        assert!(vm.snapshot() != snapshot);
+
+        vm.restore(&snapshot).unwrap();
+        assert!(vm.snapshot() == snapshot);
+    }
+
+    #[test]
+    fn snapshot_survives_serialization() {
+        let mut vm = vm();
+        vm.play(321);
+        let snapshot = vm.snapshot();
+
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let restored = Vm::from_snapshot(&serde_json::from_str(&json).unwrap()).unwrap();
+
+        assert!(restored.snapshot() == snapshot);
+    }
+
+    #[test]
+    fn rewind_replays_to_the_exact_instruction() {
+        let mut vm = vm();
+        let mut history = History::new(5, 50);
+        let mut states = Vec::new();
+
+        for _ in 0..40 {
+            states.push(vm.snapshot());
+            history.record(&vm);
+            vm.play(10);
+        }
+
+        history.rewind(&mut vm, 73).unwrap();
+        assert_eq!(327, vm.ticks());
+        let mut expected = Vm::from_snapshot(&states[32]).unwrap();
+        expected.play(7);
+        assert!(vm.snapshot() == expected.snapshot());
+
+        assert!(history.rewind(&mut vm, 1000).is_err());
+    }
+}
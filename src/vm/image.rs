@@ -0,0 +1,214 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+/// Cells per row when the core is drawn, as on the SDL window.
+pub const CORE_WIDTH: usize = 100;
+
+pub type Rgb = [u8; 3];
+
+pub const BLACK: Rgb = [0, 0, 0];
+
+/// From black for 0 through red and yellow to white for 1.
+pub fn heat_color(heat: f64) -> Rgb {
+    let heat = heat.clamp(0.0, 1.0) * 3.0;
+    let channel = |from: f64| ((heat - from).clamp(0.0, 1.0) * 255.0).round() as u8;
+
+    [channel(0.0), channel(1.0), channel(2.0)]
+}
+
+/// How the core is laid out in an area: `columns` cells per row, each
+/// cell a square of `cell` pixels or characters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Grid {
+    pub columns: usize,
+    pub rows: usize,
+    pub cell: usize,
+}
+
+impl Grid {
+    /// The largest cells the whole core fits with in `width` x `height`,
+    /// cells of 1 filling the width when even those do not fit.
+    pub fn fit(core_size: usize, width: usize, height: usize) -> Grid {
+        let grid = |cell: usize| {
+            let columns = (width / cell).clamp(1, core_size.max(1));
+            Grid {
+                columns,
+                rows: core_size.div_ceil(columns),
+                cell,
+            }
+        };
+
+        (1..=width.min(height).max(1))
+            .rev()
+            .map(grid)
+            .find(|g| g.rows * g.cell <= height)
+            .unwrap_or_else(|| grid(1))
+    }
+
+    /// Column and row of the cell at `address`
+    pub fn position(&self, address: usize) -> (usize, usize) {
+        (address % self.columns, address / self.columns)
+    }
+
+    pub fn address(&self, column: usize, row: usize) -> usize {
+        row * self.columns + column
+    }
+}
+
+/// An RGB image, row by row.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![0; width * height * 3],
+        }
+    }
+
+    /// An image of the core, `CORE_WIDTH` cells wide, each cell drawn as a
+    /// `scale` pixels square.
+    pub fn of_core(core_size: usize, scale: usize) -> Image {
+        let rows = core_size.div_ceil(CORE_WIDTH);
+        Image::new(CORE_WIDTH * scale, rows * scale)
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Rgb {
+        let ix = (y * self.width + x) * 3;
+        [self.pixels[ix], self.pixels[ix + 1], self.pixels[ix + 2]]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: Rgb) {
+        let ix = (y * self.width + x) * 3;
+        self.pixels[ix..ix + 3].copy_from_slice(&color);
+    }
+
+    /// Colors the square of the cell at `address`, laid out as by
+    /// `of_core` with the same `scale`.
+    pub fn set_cell(&mut self, address: usize, scale: usize, color: Rgb) {
+        let x = address % CORE_WIDTH * scale;
+        let y = address / CORE_WIDTH * scale;
+        for dy in 0..scale {
+            for dx in 0..scale {
+                self.set_pixel(x + dx, y + dy, color);
+            }
+        }
+    }
+
+    /// Each pixel becomes a `scale` pixels square.
+    pub fn scaled(&self, scale: usize) -> Image {
+        if scale == 1 {
+            return self.clone();
+        }
+
+        let mut image = Image::new(self.width * scale, self.height * scale);
+        for y in 0..image.height {
+            for x in 0..image.width {
+                image.set_pixel(x, y, self.pixel(x / scale, y / scale));
+            }
+        }
+
+        image
+    }
+
+    /// Binary PPM (P6)
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut ppm = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        ppm.extend_from_slice(&self.pixels);
+        ppm
+    }
+
+    pub fn write_png<W: Write>(&self, writer: W) -> Result<(), String> {
+        let mut encoder = png::Encoder::new(writer, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        encoder
+            .write_header()
+            .and_then(|mut w| w.write_image_data(&self.pixels))
+            .map_err(|e| e.to_string())
+    }
+
+    /// Saves as PNG, or as PPM for a `.ppm` extension.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let is_ppm = path
+            .extension()
+            .map(|e| e.eq_ignore_ascii_case("ppm"))
+            .unwrap_or(false);
+
+        let result = if is_ppm {
+            fs::write(path, self.to_ppm()).map_err(|e| e.to_string())
+        } else {
+            fs::File::create(path)
+                .map_err(|e| e.to_string())
+                .and_then(|f| self.write_png(std::io::BufWriter::new(f)))
+        };
+
+        result.map_err(|e| format!("Can not write {}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cells_are_laid_out_in_rows_of_100() {
+        let mut image = Image::of_core(8000, 2);
+        image.set_cell(101, 2, [1, 2, 3]);
+
+        assert_eq!((200, 160), (image.width, image.height));
+        assert_eq!([1, 2, 3], image.pixel(2, 2));
+        assert_eq!([1, 2, 3], image.pixel(3, 3));
+        assert_eq!(BLACK, image.pixel(4, 2));
+    }
+
+    #[test]
+    fn grids_fit_the_core_in_the_area() {
+        assert_eq!(
+            Grid {
+                columns: 100,
+                rows: 80,
+                cell: 10
+            },
+            Grid::fit(8000, 1000, 800)
+        );
+        // a wide window gets more columns of smaller cells
+        let wide = Grid::fit(8000, 1600, 400);
+        assert!(wide.rows * wide.cell <= 400 && wide.columns * wide.cell <= 1600);
+        assert_eq!(Grid::fit(55440, 1000, 800).cell, 3);
+        // too small, the rows overflow
+        assert_eq!(
+            Grid {
+                columns: 10,
+                rows: 800,
+                cell: 1
+            },
+            Grid::fit(8000, 10, 10)
+        );
+        assert_eq!((7, 1), Grid::fit(800, 100, 100).position(40));
+    }
+
+    #[test]
+    fn images_are_encoded() {
+        let mut image = Image::new(2, 1);
+        image.set_pixel(1, 0, [255, 0, 0]);
+
+        assert_eq!(b"P6\n2 1\n255\n\0\0\0\xff\0\0".to_vec(), image.to_ppm());
+
+        let mut png = Vec::new();
+        image.write_png(&mut png).unwrap();
+        let decoder = png::Decoder::new(png.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(image.pixels, pixels);
+    }
+}
@@ -1,6 +1,9 @@
 use super::numeric::Numeric;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub enum OpCode {
     Dat,
     Mov,
@@ -18,7 +21,7 @@ pub enum OpCode {
     Spl,
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub enum Modifier {
     A,
     B,
@@ -29,7 +32,7 @@ pub enum Modifier {
     I,
 }
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Instruction<const CORE_SIZE: usize> {
     pub op: OpCode,
     pub modifier: Modifier,
@@ -37,13 +40,13 @@ pub struct Instruction<const CORE_SIZE: usize> {
     pub b_operand: Operand<CORE_SIZE>,
 }
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Operand<const CORE_SIZE: usize> {
     pub pointer: Numeric<CORE_SIZE>,
     pub mode: OperandMode,
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
 pub enum OperandMode {
     Immediate,
     Direct,
@@ -51,3 +54,144 @@ pub enum OperandMode {
     Decrement,
     Increment,
 }
+
+impl OpCode {
+    pub const ALL: [OpCode; 14] = [
+        OpCode::Dat,
+        OpCode::Mov,
+        OpCode::Add,
+        OpCode::Sub,
+        OpCode::Mul,
+        OpCode::Div,
+        OpCode::Mod,
+        OpCode::Jmp,
+        OpCode::Jmz,
+        OpCode::Jmn,
+        OpCode::Djn,
+        OpCode::Cmp,
+        OpCode::Slt,
+        OpCode::Spl,
+    ];
+}
+
+impl Modifier {
+    pub const ALL: [Modifier; 7] = [
+        Modifier::A,
+        Modifier::B,
+        Modifier::AB,
+        Modifier::BA,
+        Modifier::F,
+        Modifier::X,
+        Modifier::I,
+    ];
+}
+
+impl OperandMode {
+    pub const ALL: [OperandMode; 5] = [
+        OperandMode::Immediate,
+        OperandMode::Direct,
+        OperandMode::Indirect,
+        OperandMode::Decrement,
+        OperandMode::Increment,
+    ];
+}
+
+impl FromStr for OpCode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<OpCode, String> {
+        match s.to_lowercase().as_str() {
+            "dat" => Ok(OpCode::Dat),
+            "mov" => Ok(OpCode::Mov),
+            "add" => Ok(OpCode::Add),
+            "sub" => Ok(OpCode::Sub),
+            "mul" => Ok(OpCode::Mul),
+            "div" => Ok(OpCode::Div),
+            "mod" => Ok(OpCode::Mod),
+            "jmp" => Ok(OpCode::Jmp),
+            "jmz" => Ok(OpCode::Jmz),
+            "jmn" => Ok(OpCode::Jmn),
+            "djn" => Ok(OpCode::Djn),
+            "cmp" => Ok(OpCode::Cmp),
+            "slt" => Ok(OpCode::Slt),
+            "spl" => Ok(OpCode::Spl),
+            _ => Err(format!("Invalid OpCode: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for OpCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            OpCode::Dat => "dat",
+            OpCode::Mov => "mov",
+            OpCode::Add => "add",
+            OpCode::Sub => "sub",
+            OpCode::Mul => "mul",
+            OpCode::Div => "div",
+            OpCode::Mod => "mod",
+            OpCode::Jmp => "jmp",
+            OpCode::Jmz => "jmz",
+            OpCode::Jmn => "jmn",
+            OpCode::Djn => "djn",
+            OpCode::Cmp => "cmp",
+            OpCode::Slt => "slt",
+            OpCode::Spl => "spl",
+        };
+
+        f.write_str(s)
+    }
+}
+
+impl fmt::Display for Modifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Modifier::A => "a",
+            Modifier::B => "b",
+            Modifier::AB => "ab",
+            Modifier::BA => "ba",
+            Modifier::F => "f",
+            Modifier::X => "x",
+            Modifier::I => "i",
+        };
+
+        f.write_str(s)
+    }
+}
+
+impl fmt::Display for OperandMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            OperandMode::Immediate => "#",
+            OperandMode::Direct => "$",
+            OperandMode::Indirect => "@",
+            OperandMode::Decrement => "<",
+            OperandMode::Increment => ">",
+        };
+
+        f.write_str(s)
+    }
+}
+
+/// Pointers are shown as signed offsets, `-1` rather than `CORE_SIZE - 1`.
+impl<const CORE_SIZE: usize> fmt::Display for Operand<CORE_SIZE> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let value = self.pointer.value;
+        if value > CORE_SIZE / 2 {
+            write!(f, "{}-{}", self.mode, CORE_SIZE - value)
+        } else {
+            write!(f, "{}{}", self.mode, value)
+        }
+    }
+}
+
+/// Redcode that `parser::parse` reads back into the same instruction.
+impl<const CORE_SIZE: usize> fmt::Display for Instruction<CORE_SIZE> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}.{} {}, {}",
+            self.op, self.modifier, self.a_operand, self.b_operand
+        )
+    }
+}
@@ -1,5 +1,23 @@
+pub mod benchmark;
+pub mod classifier;
+pub mod config;
 pub mod event;
+pub mod evolver;
+pub mod frames;
+pub mod heatmap;
+pub mod hill;
+pub mod history;
+pub mod image;
 pub mod instructions;
 pub mod numeric;
+pub mod optimizer;
+pub mod palette;
 pub mod parser;
+pub mod processes;
+pub mod rng;
+pub mod runner;
+pub mod stats;
+#[cfg(test)]
+mod testing;
+pub mod trace;
 pub mod vms;
@@ -1,6 +1,8 @@
+use serde::{Deserialize, Serialize};
 use std::ops::{Add, AddAssign, Div, Mul, Rem, Sub, SubAssign};
 
-#[derive(Clone, Copy, PartialEq, PartialOrd)]
+#[derive(Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(from = "usize", into = "usize")]
 pub struct Numeric<const CORE_SIZE: usize> {
     pub value: usize,
 }
@@ -11,9 +13,9 @@ impl<const CORE_SIZE: usize> From<usize> for Numeric<CORE_SIZE> {
     }
 }
 
-impl<const CORE_SIZE: usize> Into<usize> for Numeric<CORE_SIZE> {
-    fn into(self) -> usize {
-        self.value
+impl<const CORE_SIZE: usize> From<Numeric<CORE_SIZE>> for usize {
+    fn from(item: Numeric<CORE_SIZE>) -> usize {
+        item.value
     }
 }
 
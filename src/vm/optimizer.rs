@@ -0,0 +1,299 @@
+use super::benchmark::benchmark;
+use super::config::VmConfig;
+use super::parser::{parse, set_constant};
+use super::rng::Rng;
+use super::vms::WarriorDefinition;
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+
+/// Values tried for an `equ` constant: `from`, `from + step`, ... up to `to`
+/// included. Written `name=from..to` or `name=from..to:step`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Parameter {
+    pub name: String,
+    pub from: usize,
+    pub to: usize,
+    pub step: usize,
+}
+
+impl Parameter {
+    fn values(&self) -> usize {
+        (self.to - self.from) / self.step + 1
+    }
+
+    fn value(&self, ix: usize) -> usize {
+        self.from + ix * self.step
+    }
+}
+
+impl FromStr for Parameter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Parameter, String> {
+        let invalid = || format!("Invalid parameter {}, expected name=from..to[:step]", s);
+
+        let (name, range) = s.split_once('=').ok_or_else(invalid)?;
+        let (range, step) = match range.split_once(':') {
+            Some((range, step)) => (range, step.parse().map_err(|_| invalid())?),
+            None => (range, 1),
+        };
+        let (from, to) = range.split_once("..").ok_or_else(invalid)?;
+        let from = from.parse().map_err(|_| invalid())?;
+        let to = to.parse().map_err(|_| invalid())?;
+
+        if name.is_empty() || step == 0 || to < from {
+            return Err(invalid());
+        }
+
+        Ok(Parameter {
+            name: name.to_string(),
+            from,
+            to,
+            step,
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Search {
+    /// Every combination of values
+    Grid,
+    /// This many combinations drawn at random
+    Random(usize),
+}
+
+#[derive(Serialize)]
+pub struct OptimizationReport {
+    pub seed: u64,
+    /// Candidates benchmarked, `candidates` may have been cut since
+    pub tried: usize,
+    /// Best first
+    pub candidates: Vec<Candidate>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Constant {
+    pub name: String,
+    pub value: usize,
+}
+
+#[derive(Clone, Serialize)]
+pub struct Candidate {
+    /// Value of each parameter, in the order they were given
+    pub constants: Vec<Constant>,
+    /// Benchmark score, see `benchmark`
+    pub score: f64,
+}
+
+/// Benchmarks `source` against `suite` with the constants of `parameters`
+/// set to the values picked by `search`. Every candidate plays the same
+/// rounds: the pairing seeds come from the config seed, or from a seed from
+/// the clock, which also drives the random search.
+#[allow(clippy::too_many_arguments)]
+pub fn optimize<const CORE_SIZE: usize>(
+    config: &VmConfig,
+    name: &str,
+    source: &str,
+    parameters: &[Parameter],
+    search: Search,
+    suite: &[WarriorDefinition<CORE_SIZE>],
+    rounds: usize,
+    threads: usize,
+) -> Result<OptimizationReport, String> {
+    if parameters.is_empty() {
+        return Err("Nothing to optimize".to_string());
+    }
+
+    let seed = config.seed.unwrap_or_else(Rng::entropy_seed);
+    let config = VmConfig {
+        seed: Some(seed),
+        ..config.clone()
+    };
+
+    let mut candidates = candidate_values(parameters, search, seed)
+        .into_iter()
+        .map(|values| {
+            let mut tuned = source.to_string();
+            for (parameter, value) in parameters.iter().zip(values.iter()) {
+                tuned = set_constant(&tuned, &parameter.name, *value)?;
+            }
+            let warrior = WarriorDefinition::new(name.to_string(), parse(tuned)?);
+            let report = benchmark(&config, &warrior, suite, rounds, threads)?;
+
+            Ok(Candidate {
+                constants: parameters
+                    .iter()
+                    .zip(values)
+                    .map(|(p, value)| Constant {
+                        name: p.name.clone(),
+                        value,
+                    })
+                    .collect(),
+                score: report.score,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    // stable: the first candidate tried wins on equal scores
+    candidates.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+
+    Ok(OptimizationReport {
+        seed,
+        tried: candidates.len(),
+        candidates,
+    })
+}
+
+fn candidate_values(parameters: &[Parameter], search: Search, seed: u64) -> Vec<Vec<usize>> {
+    match search {
+        Search::Grid => {
+            let total = parameters.iter().map(|p| p.values()).product();
+            // the first parameter changes slowest
+            (0..total)
+                .map(|mut ix| {
+                    let mut values = parameters
+                        .iter()
+                        .rev()
+                        .map(|p| {
+                            let value = p.value(ix % p.values());
+                            ix /= p.values();
+                            value
+                        })
+                        .collect::<Vec<_>>();
+                    values.reverse();
+                    values
+                })
+                .collect()
+        }
+        Search::Random(samples) => {
+            // not the stream of any pairing, see `benchmark`
+            let mut rng = Rng::new(Rng::derive(seed, u64::MAX));
+            (0..samples)
+                .map(|_| {
+                    parameters
+                        .iter()
+                        .map(|p| p.value(rng.below(p.values())))
+                        .collect()
+                })
+                .collect()
+        }
+    }
+}
+
+impl fmt::Display for OptimizationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} candidates tried (seed {}), best first",
+            self.tried, self.seed
+        )?;
+        for candidate in self.candidates.iter() {
+            let constants = candidate
+                .constants
+                .iter()
+                .map(|c| format!("{}={}", c.name, c.value))
+                .collect::<Vec<_>>()
+                .join(" ");
+            writeln!(f, "{:>9.2}  {}", candidate.score, constants)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::warrior;
+    use super::*;
+
+    fn parameter(s: &str) -> Parameter {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parameters_are_parsed() {
+        assert_eq!(
+            Parameter {
+                name: "step".to_string(),
+                from: 10,
+                to: 40,
+                step: 10
+            },
+            parameter("step=10..40:10")
+        );
+        assert_eq!(1, parameter("gap=3..5").step);
+        assert!("step=5..1".parse::<Parameter>().is_err());
+        assert!("step=1..5:0".parse::<Parameter>().is_err());
+        assert!("step".parse::<Parameter>().is_err());
+    }
+
+    #[test]
+    fn grid_covers_every_combination() {
+        let values = candidate_values(
+            &[parameter("a=1..2"), parameter("b=10..30:10")],
+            Search::Grid,
+            0,
+        );
+
+        assert_eq!(
+            vec![
+                vec![1, 10],
+                vec![1, 20],
+                vec![1, 30],
+                vec![2, 10],
+                vec![2, 20],
+                vec![2, 30]
+            ],
+            values
+        );
+    }
+
+    #[test]
+    fn random_search_stays_in_range() {
+        let values = candidate_values(&[parameter("a=5..50:5")], Search::Random(50), 3);
+
+        assert_eq!(50, values.len());
+        assert!(values
+            .iter()
+            .all(|v| v[0] % 5 == 0 && (5..=50).contains(&v[0])));
+        assert_eq!(
+            values,
+            candidate_values(&[parameter("a=5..50:5")], Search::Random(50), 3)
+        );
+    }
+
+    #[test]
+    fn the_best_constants_come_first() {
+        let config = VmConfig {
+            seed: Some(2),
+            max_cycles: 2000,
+            ..VmConfig::default()
+        };
+        let suite = [warrior("loop", "jmp 0, 0")];
+        // only a jump of 2 lands on the loop, the others on a dat
+        let source = "step equ 1\njmp step, 0\ndat #0, #0\njmp 0, 0";
+
+        let report = optimize(
+            &config,
+            "jumper",
+            source,
+            &[parameter("step=1..3")],
+            Search::Grid,
+            &suite,
+            4,
+            1,
+        )
+        .unwrap();
+
+        assert_eq!(3, report.candidates.len());
+        assert_eq!(
+            vec![Constant {
+                name: "step".to_string(),
+                value: 2
+            }],
+            report.candidates[0].constants
+        );
+        assert_eq!(100.0, report.candidates[0].score);
+        assert_eq!(0.0, report.candidates[2].score);
+    }
+}
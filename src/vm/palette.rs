@@ -0,0 +1,200 @@
+use super::image::Rgb;
+use std::str::FromStr;
+
+/// The colors of the first warriors of the classic palette, as the SDL
+/// window has always drawn them.
+const CLASSIC: [Rgb; 6] = [
+    [255, 0, 0],
+    [0, 0, 255],
+    [0, 255, 0],
+    [255, 255, 0],
+    [255, 0, 255],
+    [255, 255, 255],
+];
+
+/// Lighter `CLASSIC`, where the processes of a warrior are.
+const CLASSIC_LIGHT: [Rgb; 6] = [
+    [255, 114, 118],
+    [164, 219, 232],
+    [162, 228, 184],
+    [241, 235, 156],
+    [241, 178, 220],
+    [240, 240, 240],
+];
+
+/// Okabe and Ito's colors, told apart with the common color blindnesses.
+const COLORBLIND: [Rgb; 7] = [
+    [230, 159, 0],
+    [86, 180, 233],
+    [0, 158, 115],
+    [240, 228, 66],
+    [0, 114, 178],
+    [213, 94, 0],
+    [204, 121, 167],
+];
+
+/// Colors of the warriors, by id. Past the colors given, colors are
+/// generated around the hue wheel, so any number of warriors has one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    colors: Vec<Rgb>,
+    /// Light variant of the first colors, the others are computed
+    light: Vec<Rgb>,
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::classic()
+    }
+}
+
+impl Palette {
+    pub fn classic() -> Palette {
+        Palette {
+            colors: CLASSIC.to_vec(),
+            light: CLASSIC_LIGHT.to_vec(),
+        }
+    }
+
+    /// Safe for the first 7 warriors only, generated colors are not
+    pub fn colorblind() -> Palette {
+        Palette::custom(COLORBLIND.to_vec())
+    }
+
+    /// Every color generated
+    pub fn wheel() -> Palette {
+        Palette::custom(vec![])
+    }
+
+    pub fn custom(colors: Vec<Rgb>) -> Palette {
+        Palette {
+            colors,
+            light: vec![],
+        }
+    }
+
+    pub fn color(&self, warrior_id: usize) -> Rgb {
+        match self.colors.get(warrior_id) {
+            Some(color) => *color,
+            None => generated(warrior_id - self.colors.len()),
+        }
+    }
+
+    /// A lighter `color`, where the processes of a warrior are
+    pub fn light(&self, warrior_id: usize) -> Rgb {
+        match self.light.get(warrior_id) {
+            Some(color) => *color,
+            None => self
+                .color(warrior_id)
+                .map(|c| c + ((255 - c) as f64 * 0.45) as u8),
+        }
+    }
+}
+
+/// The `ix`th generated color: hues a golden angle apart never repeat and
+/// stay far apart from the ones before, lightness alternates.
+fn generated(ix: usize) -> Rgb {
+    let hue = (ix as f64 * 137.508) % 360.0;
+    let lightness = if ix.is_multiple_of(2) { 0.5 } else { 0.65 };
+
+    hsl_to_rgb(hue, 0.9, lightness)
+}
+
+/// `hue` in degrees, `saturation` and `lightness` from 0 to 1
+pub fn hsl_to_rgb(hue: f64, saturation: f64, lightness: f64) -> Rgb {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let sector = (hue % 360.0) / 60.0;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let (r, g, b) = match sector as usize {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    let channel = |c: f64| ((c + m) * 255.0).round() as u8;
+
+    [channel(r), channel(g), channel(b)]
+}
+
+fn parse_color(s: &str) -> Result<Rgb, String> {
+    let invalid = || format!("Invalid color {}, expected #rrggbb", s);
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    if hex.len() != 6 {
+        return Err(invalid());
+    }
+
+    let channel = |ix: usize| {
+        hex.get(ix..ix + 2)
+            .and_then(|c| u8::from_str_radix(c, 16).ok())
+            .ok_or_else(invalid)
+    };
+    Ok([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// `classic`, `colorblind`, `wheel`, or colors as `#rrggbb` separated by
+/// commas.
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Palette, String> {
+        match s.to_lowercase().as_str() {
+            "classic" => Ok(Palette::classic()),
+            "colorblind" => Ok(Palette::colorblind()),
+            "wheel" => Ok(Palette::wheel()),
+            _ => Ok(Palette::custom(
+                s.split(',')
+                    .map(|c| parse_color(c.trim()))
+                    .collect::<Result<_, _>>()
+                    .map_err(|e| format!("Invalid palette: {}", e))?,
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classic_colors_come_first() {
+        let palette = Palette::classic();
+
+        assert_eq!([255, 0, 0], palette.color(0));
+        assert_eq!([164, 219, 232], palette.light(1));
+        // the seventh warrior gets a generated color
+        assert_eq!(generated(0), palette.color(6));
+    }
+
+    #[test]
+    fn generated_colors_are_distinct() {
+        let palette = Palette::wheel();
+        let colors = (0..50).map(|id| palette.color(id)).collect::<Vec<_>>();
+
+        for (ix, color) in colors.iter().enumerate() {
+            assert!(!colors[ix + 1..].contains(color));
+            assert_ne!(*color, palette.light(ix));
+        }
+    }
+
+    #[test]
+    fn hsl_is_converted() {
+        assert_eq!([255, 0, 0], hsl_to_rgb(0.0, 1.0, 0.5));
+        assert_eq!([0, 255, 0], hsl_to_rgb(120.0, 1.0, 0.5));
+        assert_eq!([0, 0, 255], hsl_to_rgb(240.0, 1.0, 0.5));
+        assert_eq!([128, 128, 128], hsl_to_rgb(0.0, 0.0, 0.5));
+    }
+
+    #[test]
+    fn palettes_are_parsed() {
+        assert_eq!(Ok(Palette::colorblind()), "Colorblind".parse());
+        assert_eq!(
+            Ok(Palette::custom(vec![[255, 16, 0], [0, 0, 1]])),
+            "#ff1000, 000001".parse()
+        );
+        assert!("#ff10".parse::<Palette>().is_err());
+        assert!("rainbow".parse::<Palette>().is_err());
+    }
+}
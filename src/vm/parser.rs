@@ -56,7 +56,7 @@ fn expand_variable<'a>(
     let mut result = String::new();
 
     for token in split_into_tokens(value) {
-        if token.len() == 1 && TOKEN_BREAKER.contains(&token.chars().nth(0).unwrap())
+        if token.len() == 1 && TOKEN_BREAKER.contains(&token.chars().next().unwrap())
             || token.chars().all(|c| c.is_numeric())
             || labels.contains_key(token)
         {
@@ -137,6 +137,41 @@ pub fn parse<const CORE_SIZE: usize>(input: String) -> Result<Vec<Instruction<CO
     Ok(result)
 }
 
+/// `input` with the value of the `name equ ...` line replaced by `value`,
+/// comments kept.
+pub fn set_constant(input: &str, name: &str, value: usize) -> Result<String, String> {
+    let mut found = false;
+    let lines = input
+        .lines()
+        .map(|line| {
+            let mut parts = line.splitn(2, ';');
+            let code = parts.next().unwrap();
+            match get_variable_definition(code.trim()) {
+                Some((variable, _)) if variable == name => {
+                    found = true;
+                    let indent = &code[..code.len() - code.trim_start().len()];
+                    match parts.next() {
+                        Some(comment) => format!("{}{} equ {} ;{}", indent, name, value, comment),
+                        None => format!("{}{} equ {}", indent, name, value),
+                    }
+                }
+                _ => line.to_string(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if !found {
+        return Err(format!("No constant {}", name));
+    }
+
+    let mut output = lines.join("\n");
+    if input.ends_with('\n') {
+        output.push('\n');
+    }
+
+    Ok(output)
+}
+
 fn implicit_modifier<const CORE_SIZE: usize>(
     op_code: &OpCode,
     a_operand: &Operand<CORE_SIZE>,
@@ -173,23 +208,9 @@ fn parse_op_code(s: &str) -> Result<(OpCode, Option<Modifier>), String> {
         None
     };
 
-    let op_code = match op_code_string.to_lowercase().as_str() {
-        "dat" => OpCode::Dat,
-        "mov" => OpCode::Mov,
-        "add" => OpCode::Add,
-        "sub" => OpCode::Sub,
-        "mul" => OpCode::Mul,
-        "div" => OpCode::Div,
-        "mod" => OpCode::Mod,
-        "jmp" => OpCode::Jmp,
-        "jmz" => OpCode::Jmz,
-        "jmn" => OpCode::Jmn,
-        "djn" => OpCode::Djn,
-        "cmp" => OpCode::Cmp,
-        "slt" => OpCode::Slt,
-        "spl" => OpCode::Spl,
-        _ => return Err(format!("Invalid OpCode: {}", s)),
-    };
+    let op_code = op_code_string
+        .parse::<OpCode>()
+        .map_err(|_| format!("Invalid OpCode: {}", s))?;
 
     let modifier = match modifier_string {
         None => None,
@@ -229,7 +250,7 @@ fn parse_operand<const CORE_SIZE: usize>(
 
     let op_value = evaluate_operand::<CORE_SIZE>(&s[start_ix..], labels, variables, current_index)?;
 
-    let pointer = Numeric::from(op_value as usize);
+    let pointer = Numeric::from(op_value);
 
     Ok(Operand {
         pointer,
@@ -374,7 +395,7 @@ fn split_into_tokens(s: &str) -> Vec<&str> {
     res
 }
 
-static TOKEN_BREAKER: &'static [char] = &['+', '-', '*', '/', '%', '(', ')'];
+static TOKEN_BREAKER: &[char] = &['+', '-', '*', '/', '%', '(', ')'];
 
 #[derive(Debug)]
 enum ExpressionToken {
@@ -451,14 +472,14 @@ impl ExpressionOperator {
             v => return Err(format!("Invalid operand {}", v)),
         };
 
-        return Ok(r);
+        Ok(r)
     }
 
     fn takes_precedence(self, other: ExpressionOperator) -> bool {
-        return (self == ExpressionOperator::Mul
+        (self == ExpressionOperator::Mul
             || self == ExpressionOperator::Div
             || self == ExpressionOperator::Mod)
-            && (other == ExpressionOperator::Add || other == ExpressionOperator::Sub);
+            && (other == ExpressionOperator::Add || other == ExpressionOperator::Sub)
     }
 }
 
@@ -576,6 +597,35 @@ mod tests {
         test_parse::<80000>();
     }
 
+    #[test]
+    fn constants_can_be_changed() {
+        let input = "step equ 5 ; tuned\n  gap equ step*2\nmov 0, step\nmov 0, gap";
+
+        let changed = set_constant(input, "step", 7).unwrap();
+        let res = parse::<8000>(changed.clone()).unwrap();
+
+        assert!(changed.starts_with("step equ 7 ; tuned\n  gap equ step*2"));
+        assert_eq!(7, res[0].b_operand.pointer.value);
+        assert_eq!(14, res[1].b_operand.pointer.value);
+        assert!(set_constant(input, "missing", 1).is_err());
+        assert!(set_constant("step equ 5\n", "step", 1)
+            .unwrap()
+            .ends_with('\n'));
+    }
+
+    #[test]
+    fn displayed_instructions_parse_back() {
+        let code = "mov 0, 1\nspl.a #3, <-4\njmz @-1, >2\ndat.f <3999, $4000\nslt.x #0, @-7";
+        let instructions = parse::<8000>(code.to_string()).unwrap();
+
+        for instruction in instructions.iter() {
+            let reparsed = parse::<8000>(instruction.to_string()).unwrap();
+            assert!(reparsed[0] == *instruction, "{}", instruction);
+        }
+        assert_eq!("mov.i $0, $1", instructions[0].to_string());
+        assert_eq!("spl.a #3, <-4", instructions[1].to_string());
+    }
+
     fn test_parse<const CORE_SIZE: usize>() {
         let code = "
             lozzero equ 66
@@ -0,0 +1,216 @@
+use super::event::{EventType, VmEvent};
+use std::collections::BTreeMap;
+
+/// Samples kept of the process counts, every other one is dropped past
+/// this many.
+const HISTORY_SAMPLES: usize = 512;
+
+/// Where the processes of each warrior are and how many they were over
+/// time, followed from the events alone so that a replayed trace shows
+/// them as a live VM does.
+#[derive(Clone, Debug)]
+pub struct ProcessTracker {
+    /// Processes at each address, for each warrior
+    positions: Vec<BTreeMap<usize, usize>>,
+    counts: Vec<usize>,
+    /// Process count of each warrior, every `every` cycles from cycle 0
+    history: Vec<Vec<usize>>,
+    every: u128,
+}
+
+impl ProcessTracker {
+    /// Warriors start with a process at `starts`, indexed by warrior id as
+    /// `Placement::positions`.
+    pub fn new(starts: &[usize]) -> ProcessTracker {
+        ProcessTracker {
+            positions: starts
+                .iter()
+                .map(|&start| BTreeMap::from([(start, 1)]))
+                .collect(),
+            counts: vec![1; starts.len()],
+            history: vec![],
+            every: 1,
+        }
+    }
+
+    pub fn apply<const CORE_SIZE: usize>(&mut self, event: &VmEvent<CORE_SIZE>) {
+        // a sample holds the counts before the first event of its cycle
+        while event.round >= self.history.len() as u128 * self.every {
+            self.history.push(self.counts.clone());
+            if self.history.len() > HISTORY_SAMPLES {
+                self.history = self.history.iter().step_by(2).cloned().collect();
+                self.every *= 2;
+            }
+        }
+
+        let positions = &mut self.positions[event.warrior_id];
+        match event.event_type {
+            // the process was taken out of the queue to run
+            EventType::Execute(_) => {
+                if let Some(address) = event.offset {
+                    if let Some(count) = positions.get_mut(&address) {
+                        *count -= 1;
+                        if *count == 0 {
+                            positions.remove(&address);
+                        }
+                    }
+                }
+            }
+            // and queued again where it goes, twice for a `spl`
+            EventType::Jump => {
+                if let Some(address) = event.offset {
+                    *positions.entry(address).or_insert(0) += 1;
+                }
+            }
+            EventType::Spawn { processes } | EventType::TerminatedThread { processes, .. } => {
+                self.counts[event.warrior_id] = processes;
+            }
+            EventType::TerminatedProgram { .. } => {
+                self.counts[event.warrior_id] = 0;
+                positions.clear();
+            }
+            _ => {}
+        }
+    }
+
+    /// The addresses of the processes of `warrior_id`, with how many are
+    /// there
+    pub fn positions(&self, warrior_id: usize) -> &BTreeMap<usize, usize> {
+        &self.positions[warrior_id]
+    }
+
+    pub fn count(&self, warrior_id: usize) -> usize {
+        self.counts[warrior_id]
+    }
+
+    /// The process count of each warrior, sampled every `every()` cycles
+    pub fn history(&self) -> &[Vec<usize>] {
+        &self.history
+    }
+
+    pub fn every(&self) -> u128 {
+        self.every
+    }
+
+    /// Most processes of all the warriors together in a sample, 1 at the
+    /// least
+    pub fn peak(&self) -> usize {
+        self.history
+            .iter()
+            .map(|counts| counts.iter().sum::<usize>())
+            .max()
+            .unwrap_or(0)
+            .max(1)
+    }
+
+    /// The process counts stacked up to `peak()` over `rows` rows, for at
+    /// most `columns` samples spread over the history: in each row from the
+    /// bottom up, the warrior whose part holds its middle.
+    pub fn stacked(&self, columns: usize, rows: usize) -> Vec<Vec<Option<usize>>> {
+        let max = self.peak() as f64;
+        let columns = self.history.len().min(columns);
+
+        (0..columns)
+            .map(|x| {
+                let counts = &self.history[x * self.history.len() / columns];
+                (0..rows)
+                    .map(|row| {
+                        let level = (row as f64 + 0.5) * max / rows as f64;
+                        let mut below = 0;
+                        counts.iter().position(|&count| {
+                            below += count;
+                            below as f64 > level
+                        })
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::config::VmConfig;
+    use super::super::event::{Observable, Observer};
+    use super::super::testing;
+    use super::super::vms::{Placement, Vm};
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    struct Tracking(Arc<Mutex<ProcessTracker>>);
+
+    impl Observer<VmEvent<8000>> for Tracking {
+        fn notify(&self, event: VmEvent<8000>) {
+            self.0.lock().unwrap().apply(&event);
+        }
+    }
+
+    fn vm(codes: &[&str]) -> (Vm<8000>, Arc<Mutex<ProcessTracker>>) {
+        let mut vm = testing::vm(codes, VmConfig::default().max_cycles);
+        let tracker = Arc::new(Mutex::new(ProcessTracker::new(
+            &Placement::evenly_spaced(codes.len(), 8000).positions,
+        )));
+        vm.register(Box::new(Tracking(tracker.clone())));
+
+        (vm, tracker)
+    }
+
+    #[test]
+    fn processes_follow_the_queues() {
+        let (mut vm, tracker) = vm(&["spl 0, 0\nmov 0, 1", "spl 2, 0\njmp -1, 0\nmov 0, 1"]);
+        let same_queues = |vm: &Vm<8000>, tracker: &ProcessTracker| {
+            for id in 0..2 {
+                let mut queue = BTreeMap::new();
+                for address in vm.processes(id).unwrap() {
+                    *queue.entry(address.value).or_insert(0) += 1;
+                }
+                assert_eq!(&queue, tracker.positions(id));
+                assert_eq!(vm.processes(id).unwrap().len(), tracker.count(id));
+            }
+        };
+
+        // known before anything runs
+        same_queues(&vm, &tracker.lock().unwrap());
+        vm.play(2000);
+        same_queues(&vm, &tracker.lock().unwrap());
+        assert!(tracker.lock().unwrap().positions(0).len() > 10);
+    }
+
+    #[test]
+    fn history_is_sampled_and_halved() {
+        let (mut vm, tracker) = vm(&["spl 0, 0\njmp -1, 0", "jmp 0, 0"]);
+        vm.play(6);
+
+        // the spl, the jmp back to it, the spl again
+        assert_eq!(
+            vec![vec![1, 1], vec![2, 1], vec![2, 1]],
+            tracker.lock().unwrap().history()
+        );
+
+        vm.play(4000);
+        let tracker = tracker.lock().unwrap();
+        // cycles 0 to 2000 by 4
+        assert_eq!(4, tracker.every());
+        assert_eq!(501, tracker.history().len());
+        assert_eq!(vec![1, 1], tracker.history()[0]);
+        assert_eq!(1, tracker.history()[500][1]);
+    }
+
+    #[test]
+    fn counts_are_stacked() {
+        let (mut vm, tracker) = vm(&["spl 0, 0\njmp -1, 0", "jmp 0, 0"]);
+        vm.play(6);
+
+        // 2 + 1 processes at the peak, over 3 rows
+        let tracker = tracker.lock().unwrap();
+        assert_eq!(3, tracker.peak());
+        assert_eq!(
+            vec![
+                vec![Some(0), Some(1), None],
+                vec![Some(0), Some(0), Some(1)],
+            ],
+            tracker.stacked(2, 3)
+        );
+        assert_eq!(3, tracker.stacked(10, 1).len());
+    }
+}
@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// SplitMix64 generator. It is implemented here rather than pulled from a
+/// crate so that a recorded seed replays the same battle on every platform
+/// and every future release.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    /// Seed taken from the clock, for runs where no seed was given.
+    pub fn entropy_seed() -> u64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        Rng::new(now.as_secs() ^ now.subsec_nanos() as u64).next_u64()
+    }
+
+    /// Seed of the `index`-th child stream of `seed`. Children depend only on
+    /// the parent seed and their index, not on the order they are requested.
+    pub fn derive(seed: u64, index: u64) -> u64 {
+        Rng::new(seed ^ index.wrapping_mul(0xD1B5_4A32_D192_ED03)).next_u64()
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        // rejection sampling, to avoid the modulo bias
+        let n = n as u64;
+        let zone = u64::MAX - u64::MAX % n;
+        loop {
+            let v = self.next_u64();
+            if v < zone {
+                return (v % n) as usize;
+            }
+        }
+    }
+
+    /// True with probability `p`.
+    pub fn chance(&mut self, p: f64) -> bool {
+        // the 53 high bits make a uniform f64 in 0..1
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < p
+    }
+
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+    }
+
+    #[test]
+    fn known_sequence() {
+        // reference values of SplitMix64 seeded with 0
+        let mut rng = Rng::new(0);
+
+        assert_eq!(0xE220_A839_7B1D_CDAF, rng.next_u64());
+        assert_eq!(0x6E78_9E6A_A1B9_65F4, rng.next_u64());
+    }
+
+    #[test]
+    fn below_stays_in_range() {
+        let mut rng = Rng::new(7);
+
+        assert!((0..1000).map(|_| rng.below(13)).all(|v| v < 13));
+    }
+
+    #[test]
+    fn chance_follows_the_probability() {
+        let mut rng = Rng::new(3);
+        let hits = (0..10000).filter(|_| rng.chance(0.25)).count();
+
+        assert!((2300..2700).contains(&hits));
+        assert!(!rng.chance(0.0));
+        assert!(rng.chance(1.0));
+    }
+
+    #[test]
+    fn derived_seeds_differ() {
+        assert_ne!(Rng::derive(1, 0), Rng::derive(1, 1));
+        assert_eq!(Rng::derive(1, 5), Rng::derive(1, 5));
+    }
+}
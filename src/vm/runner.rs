@@ -0,0 +1,388 @@
+use super::config::{FirstMover, VmConfig};
+use super::rng::Rng;
+use super::vms::{Outcome, Placement, Vm, WarriorDefinition};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub enum RoundOutcome {
+    Win(usize),
+    /// Warriors still alive when `max_cycles` was reached
+    Tie(Vec<usize>),
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RoundResult {
+    pub round: usize,
+    /// Seed of this round alone, see `MatchRunner::round_vm`
+    pub seed: u64,
+    pub placement: Placement,
+    /// Warrior that executed the first instruction of the round
+    pub first_mover: usize,
+    pub outcome: RoundOutcome,
+    pub cycles: u128,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Score {
+    pub wins: usize,
+    pub losses: usize,
+    pub ties: usize,
+}
+
+impl Score {
+    /// The usual king of the hill score, `(3 * wins + ties) * 100 / rounds`:
+    /// 300 for winning every round, 100 for tying every round.
+    pub fn points(&self) -> f64 {
+        let rounds = self.wins + self.losses + self.ties;
+        if rounds == 0 {
+            return 0.0;
+        }
+
+        (3 * self.wins + self.ties) as f64 * 100.0 / rounds as f64
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct MatchResult {
+    pub seed: u64,
+    pub warriors: Vec<String>,
+    pub rounds: Vec<RoundResult>,
+}
+
+impl MatchResult {
+    /// Wins, losses and ties of each warrior, indexed by warrior id.
+    pub fn scores(&self) -> Vec<Score> {
+        let mut scores = vec![Score::default(); self.warriors.len()];
+
+        for round in self.rounds.iter() {
+            for (warrior_id, score) in scores.iter_mut().enumerate() {
+                match &round.outcome {
+                    RoundOutcome::Win(w) if *w == warrior_id => score.wins += 1,
+                    RoundOutcome::Tie(alive) if alive.contains(&warrior_id) => score.ties += 1,
+                    _ => score.losses += 1,
+                }
+            }
+        }
+
+        scores
+    }
+}
+
+/// Plays rounds of a battle between the same warriors. All the randomness of
+/// a match comes from its seed: every round gets its own seed derived from
+/// it, which alone is enough to replay that round.
+pub struct MatchRunner<const CORE_SIZE: usize> {
+    config: VmConfig,
+    warriors: Vec<WarriorDefinition<CORE_SIZE>>,
+    seed: u64,
+}
+
+impl<const CORE_SIZE: usize> MatchRunner<CORE_SIZE> {
+    /// Uses `config.seed` when set, a seed from the clock otherwise.
+    pub fn new(
+        config: VmConfig,
+        warriors: Vec<WarriorDefinition<CORE_SIZE>>,
+    ) -> MatchRunner<CORE_SIZE> {
+        let seed = config.seed.unwrap_or_else(Rng::entropy_seed);
+
+        MatchRunner {
+            config,
+            warriors,
+            seed,
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn round_seed(&self, round: usize) -> u64 {
+        Rng::derive(self.seed, round as u64)
+    }
+
+    /// Random start positions at least `min_distance` apart. Warriors take
+    /// turns in id order starting from the first mover, or in a random order
+    /// with `FirstMover::Random`.
+    pub fn placement(&self, round: usize, round_seed: u64) -> Placement {
+        let mut rng = Rng::new(round_seed);
+        let warriors = self.warriors.len();
+        let min_distance = self.config.min_distance;
+        let free = match CORE_SIZE.checked_sub(warriors * min_distance) {
+            // too few warriors or can't fit, let the VM report it
+            Some(free) if warriors >= 2 => free,
+            _ => return Placement::evenly_spaced(warriors, CORE_SIZE),
+        };
+
+        // spread the free space between the warriors: the gap after each
+        // slot is min_distance plus its share of the free cells
+        let mut extra = (1..warriors)
+            .map(|_| rng.below(free + 1))
+            .collect::<Vec<_>>();
+        extra.sort_unstable();
+
+        let slots = std::iter::once(0)
+            .chain(
+                extra
+                    .iter()
+                    .enumerate()
+                    .map(|(ix, e)| (ix + 1) * min_distance + e),
+            )
+            .collect::<Vec<_>>();
+
+        let mut slot_of = (0..warriors).collect::<Vec<_>>();
+        rng.shuffle(&mut slot_of);
+        let mut order = (0..warriors).collect::<Vec<_>>();
+        match self.config.first_mover {
+            FirstMover::Rotate => order.rotate_left(round % warriors),
+            FirstMover::Random => rng.shuffle(&mut order),
+        }
+
+        Placement {
+            positions: slot_of.iter().map(|&slot| slots[slot]).collect(),
+            order,
+        }
+    }
+
+    /// Builds the VM of the `round`-th round played with `round_seed`.
+    pub fn round_vm(
+        &self,
+        round: usize,
+        round_seed: u64,
+    ) -> Result<(Vm<CORE_SIZE>, Placement), String> {
+        let placement = self.placement(round, round_seed);
+        let vm = Vm::with_placement(
+            self.config.clone(),
+            self.warriors.clone(),
+            placement.clone(),
+        )?;
+
+        Ok((vm, placement))
+    }
+
+    /// Plays a round without observers, see `round_vm` to watch it.
+    pub fn play_round(&self, round: usize) -> Result<RoundResult, String> {
+        let seed = self.round_seed(round);
+        let placement = self.placement(round, seed);
+        let mut vm = Vm::headless(
+            self.config.clone(),
+            self.warriors.clone(),
+            placement.clone(),
+        )?;
+
+        let outcome = loop {
+            match vm.play(i32::MAX) {
+                Outcome::Running => continue,
+                Outcome::Win(warrior_id, _) => break RoundOutcome::Win(warrior_id),
+                Outcome::Tie => break RoundOutcome::Tie(vm.alive_warriors()),
+            }
+        };
+
+        Ok(RoundResult {
+            round,
+            seed,
+            first_mover: placement.order[0],
+            placement,
+            outcome,
+            cycles: vm.cycles(),
+        })
+    }
+
+    pub fn play(&self, rounds: usize) -> Result<MatchResult, String> {
+        let rounds = (0..rounds)
+            .map(|round| self.play_round(round))
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(self.result(rounds))
+    }
+
+    /// Same as `play`, with the rounds spread over `threads` threads. Rounds
+    /// depend only on their seed, so the result is the one `play` gives
+    /// whatever the number of threads.
+    pub fn play_parallel(&self, rounds: usize, threads: usize) -> Result<MatchResult, String> {
+        let next_round = AtomicUsize::new(0);
+
+        let mut played = thread::scope(|scope| {
+            let workers = (0..threads.clamp(1, rounds.max(1)))
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut played = Vec::new();
+                        loop {
+                            let round = next_round.fetch_add(1, Ordering::Relaxed);
+                            if round >= rounds {
+                                break played;
+                            }
+                            played.push((round, self.play_round(round)));
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        played.sort_unstable_by_key(|(round, _)| *round);
+
+        let rounds = played
+            .into_iter()
+            .map(|(_, result)| result)
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(self.result(rounds))
+    }
+
+    fn result(&self, rounds: Vec<RoundResult>) -> MatchResult {
+        MatchResult {
+            seed: self.seed,
+            warriors: self.warriors.iter().map(|w| w.name.clone()).collect(),
+            rounds,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::warrior;
+    use super::*;
+
+    fn runner(seed: u64) -> MatchRunner<8000> {
+        runner_with(seed, FirstMover::Rotate)
+    }
+
+    fn runner_with(seed: u64, first_mover: FirstMover) -> MatchRunner<8000> {
+        let config = VmConfig {
+            seed: Some(seed),
+            first_mover,
+            max_cycles: 2000,
+            ..VmConfig::default()
+        };
+        let warriors = ["mov 0, 1", "add #4, 3\nmov 2, @2\njmp -2, 0\ndat #0, #0"]
+            .iter()
+            .enumerate()
+            .map(|(ix, code)| warrior(&format!("w{}", ix), code))
+            .collect();
+
+        MatchRunner::new(config, warriors)
+    }
+
+    #[test]
+    fn missing_warriors_are_reported() {
+        for count in 0..2 {
+            let warriors = runner(1).warriors[..count].to_vec();
+            let runner = MatchRunner::<8000>::new(VmConfig::default(), warriors);
+
+            assert!(runner.play_round(0).is_err());
+            assert!(runner.play(3).is_err());
+        }
+    }
+
+    #[test]
+    fn same_seed_same_match() {
+        let first = runner(1234).play(20).unwrap();
+        let second = runner(1234).play(20).unwrap();
+
+        assert_eq!(1234, first.seed);
+        for (a, b) in first.rounds.iter().zip(second.rounds.iter()) {
+            assert_eq!(a.seed, b.seed);
+            assert_eq!(a.placement, b.placement);
+            assert_eq!(a.outcome, b.outcome);
+            assert_eq!(a.cycles, b.cycles);
+        }
+    }
+
+    #[test]
+    fn a_round_replays_from_its_seed() {
+        let runner = runner(99);
+        let result = runner.play_round(7).unwrap();
+        let (_, placement) = runner.round_vm(result.round, result.seed).unwrap();
+
+        assert_eq!(result.placement, placement);
+    }
+
+    #[test]
+    fn placements_vary_and_keep_distance() {
+        let runner = runner(5);
+        let placements = (0..50)
+            .map(|r| runner.placement(r, runner.round_seed(r)))
+            .collect::<Vec<_>>();
+
+        assert!(placements.iter().any(|p| p != &placements[0]));
+        for p in placements.iter() {
+            let distance = (p.positions[0] + 8000 - p.positions[1]) % 8000;
+            assert!((100..=7900).contains(&distance));
+        }
+    }
+
+    #[test]
+    fn first_mover_rotates() {
+        let result = runner(8).play(6).unwrap();
+        let first_movers = result
+            .rounds
+            .iter()
+            .map(|r| r.first_mover)
+            .collect::<Vec<_>>();
+
+        assert_eq!(vec![0, 1, 0, 1, 0, 1], first_movers);
+    }
+
+    #[test]
+    fn random_first_mover_uses_both_warriors() {
+        let result = runner_with(8, FirstMover::Random).play(30).unwrap();
+
+        assert!(result.rounds.iter().any(|r| r.first_mover == 0));
+        assert!(result.rounds.iter().any(|r| r.first_mover == 1));
+    }
+
+    #[test]
+    fn parallel_match_is_the_sequential_one() {
+        let runner = runner(77);
+        let sequential = runner.play(16).unwrap();
+
+        for threads in [1, 3, 8] {
+            let parallel = runner.play_parallel(16, threads).unwrap();
+
+            assert_eq!(sequential.seed, parallel.seed);
+            assert_eq!(sequential.rounds.len(), parallel.rounds.len());
+            for (a, b) in sequential.rounds.iter().zip(parallel.rounds.iter()) {
+                assert_eq!(a.round, b.round);
+                assert_eq!(a.seed, b.seed);
+                assert_eq!(a.outcome, b.outcome);
+                assert_eq!(a.cycles, b.cycles);
+            }
+            assert_eq!(sequential.scores(), parallel.scores());
+        }
+    }
+
+    #[test]
+    fn vms_can_move_between_threads() {
+        fn send<T: Send>(_: &T) {}
+
+        let (vm, _) = runner(1).round_vm(0, 1).unwrap();
+        send(&vm);
+        send(&runner(1));
+    }
+
+    #[test]
+    fn scores_count_every_round() {
+        let result = runner(3).play(10).unwrap();
+        let scores = result.scores();
+
+        assert_eq!(10, scores[0].wins + scores[0].losses + scores[0].ties);
+        assert_eq!(scores[0].wins, scores[1].losses);
+    }
+
+    #[test]
+    fn points_are_normalized_by_rounds() {
+        let score = Score {
+            wins: 2,
+            losses: 1,
+            ties: 1,
+        };
+
+        assert_eq!(175.0, score.points());
+        assert_eq!(0.0, Score::default().points());
+    }
+}
@@ -0,0 +1,241 @@
+use super::event::{DeathCause, EventType, Observer, VmEvent};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// What every warrior did during a battle, indexed by warrior id.
+#[derive(Clone, Serialize)]
+pub struct BattleStats {
+    /// Cycles between two samples of `WarriorStats::owned`
+    pub sample_every: u128,
+    /// Cycles played, up to the last event seen
+    pub cycles: u128,
+    pub warriors: Vec<WarriorStats>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct WarriorStats {
+    pub name: String,
+    /// Instructions executed, by opcode
+    pub executed: BTreeMap<String, u64>,
+    /// Cells written
+    pub writes: u64,
+    pub spawned: u64,
+    /// Processes that died, the last one included
+    pub killed: u64,
+    pub peak_processes: usize,
+    /// Processes alive at the end
+    pub processes: usize,
+    /// Cells last written by the warrior at every sample, as
+    /// `(cycle, cells)`
+    pub owned: Vec<(u128, usize)>,
+    /// Cycle of the death of the last process, `None` if alive
+    pub died_at: Option<u128>,
+    pub death_cause: Option<DeathCause>,
+    /// Up to the death of the last process, or to the last cycle seen
+    pub cycles_survived: u128,
+}
+
+struct State<const CORE_SIZE: usize> {
+    stats: BattleStats,
+    /// Warrior that last wrote each cell
+    owners: Vec<Option<usize>>,
+    owned: Vec<usize>,
+    next_sample: u128,
+}
+
+/// Observer aggregating the events of a battle per warrior. Keep a
+/// `StatsHandle` before registering it to read the statistics afterwards.
+pub struct StatsCollector<const CORE_SIZE: usize> {
+    state: Arc<Mutex<State<CORE_SIZE>>>,
+}
+
+#[derive(Clone)]
+pub struct StatsHandle<const CORE_SIZE: usize> {
+    state: Arc<Mutex<State<CORE_SIZE>>>,
+}
+
+impl<const CORE_SIZE: usize> StatsCollector<CORE_SIZE> {
+    /// `names` of the warriors by id, the cells owned being sampled every
+    /// `sample_every` cycles.
+    pub fn new(names: Vec<String>, sample_every: u128) -> Box<StatsCollector<CORE_SIZE>> {
+        let warriors = names
+            .into_iter()
+            .map(|name| WarriorStats {
+                name,
+                executed: BTreeMap::new(),
+                writes: 0,
+                spawned: 0,
+                killed: 0,
+                peak_processes: 1,
+                processes: 1,
+                owned: Vec::new(),
+                died_at: None,
+                death_cause: None,
+                cycles_survived: 0,
+            })
+            .collect::<Vec<_>>();
+        let count = warriors.len();
+
+        Box::new(StatsCollector {
+            state: Arc::new(Mutex::new(State {
+                stats: BattleStats {
+                    sample_every: sample_every.max(1),
+                    cycles: 0,
+                    warriors,
+                },
+                owners: vec![None; CORE_SIZE],
+                owned: vec![0; count],
+                next_sample: 0,
+            })),
+        })
+    }
+
+    pub fn handle(&self) -> StatsHandle<CORE_SIZE> {
+        StatsHandle {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<const CORE_SIZE: usize> StatsHandle<CORE_SIZE> {
+    pub fn stats(&self) -> BattleStats {
+        let state = self.state.lock().unwrap();
+        let mut stats = state.stats.clone();
+
+        for (warrior, owned) in stats.warriors.iter_mut().zip(state.owned.iter()) {
+            warrior.cycles_survived = warrior.died_at.unwrap_or(stats.cycles);
+            // the state at the end, unless just sampled
+            if warrior.owned.last().map(|(cycle, _)| *cycle) != Some(stats.cycles) {
+                warrior.owned.push((stats.cycles, *owned));
+            }
+        }
+
+        stats
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.stats()).unwrap()
+    }
+}
+
+impl<const CORE_SIZE: usize> State<CORE_SIZE> {
+    fn sample(&mut self, cycle: u128) {
+        while self.next_sample <= cycle {
+            for (warrior, owned) in self.stats.warriors.iter_mut().zip(self.owned.iter()) {
+                warrior.owned.push((self.next_sample, *owned));
+            }
+            self.next_sample += self.stats.sample_every;
+        }
+    }
+}
+
+impl<const CORE_SIZE: usize> Observer<VmEvent<CORE_SIZE>> for StatsCollector<CORE_SIZE> {
+    fn notify(&self, event: VmEvent<CORE_SIZE>) {
+        let mut state = self.state.lock().unwrap();
+        // samples are taken before the first event of their cycle
+        state.sample(event.round);
+        state.stats.cycles = event.round + 1;
+
+        let id = event.warrior_id;
+        match event.event_type {
+            EventType::Execute(instruction) => {
+                *state.stats.warriors[id]
+                    .executed
+                    .entry(instruction.op.to_string())
+                    .or_insert(0) += 1;
+            }
+            EventType::Change(_) => {
+                state.stats.warriors[id].writes += 1;
+                let address = event.offset.unwrap_or(0);
+                if let Some(previous) = state.owners[address].replace(id) {
+                    state.owned[previous] -= 1;
+                }
+                state.owned[id] += 1;
+            }
+            EventType::Spawn { processes } => {
+                let warrior = &mut state.stats.warriors[id];
+                warrior.spawned += 1;
+                warrior.peak_processes = warrior.peak_processes.max(processes);
+                warrior.processes = processes;
+            }
+            EventType::TerminatedThread { processes, .. } => {
+                let warrior = &mut state.stats.warriors[id];
+                warrior.killed += 1;
+                warrior.processes = processes;
+            }
+            EventType::TerminatedProgram { cause } => {
+                let warrior = &mut state.stats.warriors[id];
+                warrior.died_at = Some(event.round);
+                warrior.death_cause = Some(cause);
+                warrior.processes = 0;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::event::Observable;
+    use super::super::testing::vm;
+    use super::super::vms::Outcome;
+    use super::*;
+
+    fn battle(codes: &[&str], max_cycles: u128) -> BattleStats {
+        let mut vm = vm(codes, max_cycles);
+        let names = vm.warriors().iter().map(|w| w.name.clone()).collect();
+        let collector = StatsCollector::new(names, 100);
+        let handle = collector.handle();
+        vm.register(collector);
+
+        while let Outcome::Running = vm.play(i32::MAX) {}
+
+        handle.stats()
+    }
+
+    #[test]
+    fn executions_and_writes_are_counted() {
+        let stats = battle(
+            &["mov 0, 1", "add #4, 3\nmov 2, @2\njmp -2, 0\ndat #0, #0"],
+            300,
+        );
+        let imp = &stats.warriors[0];
+        let dwarf = &stats.warriors[1];
+
+        assert_eq!(Some(&300), imp.executed.get("mov"));
+        assert_eq!(300, imp.writes);
+        assert_eq!(Some(&100), dwarf.executed.get("add"));
+        assert_eq!(Some(&100), dwarf.executed.get("jmp"));
+        // the add and the bomb
+        assert_eq!(200, dwarf.writes);
+        assert_eq!(300, imp.cycles_survived);
+        assert_eq!(None, imp.died_at);
+    }
+
+    #[test]
+    fn ownership_is_sampled() {
+        let stats = battle(&["mov 0, 1", "jmp 0, 0"], 300);
+        let imp = &stats.warriors[0];
+
+        assert_eq!(vec![(0, 0), (100, 100), (200, 200), (300, 300)], imp.owned);
+        assert_eq!(
+            vec![(0, 0), (100, 0), (200, 0), (300, 0)],
+            stats.warriors[1].owned
+        );
+    }
+
+    #[test]
+    fn processes_and_deaths_are_tracked() {
+        let stats = battle(&["spl 0, 0\nmov 0, 1", "dat #0, #0"], 300);
+        let splitter = &stats.warriors[0];
+        let dead = &stats.warriors[1];
+
+        assert!(splitter.spawned > 0);
+        assert!(splitter.peak_processes > 1);
+        assert_eq!(Some(0), dead.died_at);
+        assert_eq!(Some(DeathCause::Dat), dead.death_cause);
+        assert_eq!(1, dead.killed);
+        assert_eq!(0, dead.cycles_survived);
+    }
+}
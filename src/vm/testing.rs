@@ -0,0 +1,42 @@
+use super::config::VmConfig;
+use super::parser::parse;
+use super::vms::{Placement, Vm, WarriorDefinition};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// The warrior `name` of the redcode `code`
+pub fn warrior(name: &str, code: &str) -> WarriorDefinition<8000> {
+    WarriorDefinition::new(name.to_string(), parse(code.to_string()).unwrap())
+}
+
+/// A VM with a warrior `w<ix>` for each of `codes`, evenly spaced and moving
+/// in that order, for up to `max_cycles` cycles.
+pub fn vm(codes: &[&str], max_cycles: u128) -> Vm<8000> {
+    let warriors = codes
+        .iter()
+        .enumerate()
+        .map(|(ix, code)| warrior(&format!("w{}", ix), code))
+        .collect::<Vec<_>>();
+    let config = VmConfig {
+        max_cycles,
+        ..VmConfig::default()
+    };
+
+    Vm::with_placement(
+        config,
+        warriors,
+        Placement::evenly_spaced(codes.len(), 8000),
+    )
+    .unwrap()
+}
+
+/// `name` in the temporary folder, for this test run only and removed if
+/// a previous run left it there.
+pub fn temp_path(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("core_war_{}_{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&path);
+    let _ = fs::remove_file(&path);
+
+    path
+}
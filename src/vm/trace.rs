@@ -0,0 +1,180 @@
+use super::config::VmConfig;
+use super::event::{Observer, VmEvent};
+use super::vms::{Placement, WarriorDefinition};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Lines, Write};
+use std::path::Path;
+
+// A trace is a JSONL file: the header on the first line, then one `VmEvent`
+// per line in the order the VM emitted them.
+
+/// Everything needed to rebuild the initial core of a round: the warriors
+/// and where they were loaded, the rest of the core being `dat 0, 0`.
+#[derive(Serialize, Deserialize)]
+pub struct TraceHeader<const CORE_SIZE: usize> {
+    pub config: VmConfig,
+    pub seed: u64,
+    pub round: usize,
+    pub placement: Placement,
+    pub warriors: Vec<WarriorDefinition<CORE_SIZE>>,
+}
+
+/// Observer writing every event it receives to a trace file.
+pub struct Recorder {
+    writer: RefCell<Option<BufWriter<File>>>,
+}
+
+impl Recorder {
+    pub fn create<const CORE_SIZE: usize>(
+        path: &Path,
+        header: &TraceHeader<CORE_SIZE>,
+    ) -> Result<Box<Recorder>, String> {
+        let file = File::create(path)
+            .map_err(|e| format!("Can not create file {}: {}", path.display(), e))?;
+        let mut writer = BufWriter::new(file);
+
+        serde_json::to_writer(&mut writer, header).map_err(|e| e.to_string())?;
+        writeln!(writer).map_err(|e| e.to_string())?;
+
+        Ok(Box::new(Recorder {
+            writer: RefCell::new(Some(writer)),
+        }))
+    }
+}
+
+impl<const CORE_SIZE: usize> Observer<VmEvent<CORE_SIZE>> for Recorder {
+    fn notify(&self, event: VmEvent<CORE_SIZE>) {
+        let mut writer = self.writer.borrow_mut();
+
+        if let Some(w) = writer.as_mut() {
+            let written = serde_json::to_writer(&mut *w, &event)
+                .map_err(|e| e.to_string())
+                .and_then(|_| writeln!(w).map_err(|e| e.to_string()));
+
+            if let Err(e) = written {
+                // stop at the first error rather than leaving holes in the trace
+                println!("Recording stopped: {}", e);
+                *writer = None;
+            }
+        }
+    }
+}
+
+/// Core size of a trace, needed to pick the `TraceReader` to open it with.
+pub fn trace_core_size(path: &Path) -> Result<usize, String> {
+    #[derive(Deserialize)]
+    struct PartialHeader {
+        config: VmConfig,
+    }
+
+    let mut lines = open_lines(path)?;
+    let header: PartialHeader = read_line(&mut lines)?;
+
+    Ok(header.config.core_size)
+}
+
+pub struct TraceReader<const CORE_SIZE: usize> {
+    pub header: TraceHeader<CORE_SIZE>,
+    lines: Lines<BufReader<File>>,
+}
+
+impl<const CORE_SIZE: usize> TraceReader<CORE_SIZE> {
+    pub fn open(path: &Path) -> Result<TraceReader<CORE_SIZE>, String> {
+        let mut lines = open_lines(path)?;
+        let header: TraceHeader<CORE_SIZE> = read_line(&mut lines)?;
+
+        if header.config.core_size != CORE_SIZE {
+            return Err(format!(
+                "Trace core size {} does not match {}",
+                header.config.core_size, CORE_SIZE
+            ));
+        }
+
+        Ok(TraceReader { header, lines })
+    }
+}
+
+impl<const CORE_SIZE: usize> Iterator for TraceReader<CORE_SIZE> {
+    type Item = Result<VmEvent<CORE_SIZE>, String>;
+
+    fn next(&mut self) -> Option<Result<VmEvent<CORE_SIZE>, String>> {
+        match self.lines.next() {
+            None => None,
+            Some(Err(e)) => Some(Err(e.to_string())),
+            Some(Ok(line)) => Some(serde_json::from_str(&line).map_err(|e| e.to_string())),
+        }
+    }
+}
+
+fn open_lines(path: &Path) -> Result<Lines<BufReader<File>>, String> {
+    let file =
+        File::open(path).map_err(|e| format!("Can not open file {}: {}", path.display(), e))?;
+
+    Ok(BufReader::new(file).lines())
+}
+
+fn read_line<T: for<'de> Deserialize<'de>>(
+    lines: &mut Lines<BufReader<File>>,
+) -> Result<T, String> {
+    let line = lines
+        .next()
+        .ok_or_else(|| "Empty trace".to_string())?
+        .map_err(|e| e.to_string())?;
+
+    serde_json::from_str(&line).map_err(|e| format!("Invalid trace header: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::event::{EventType, Observable};
+    use super::super::instructions::OpCode;
+    use super::super::testing::{temp_path, warrior};
+    use super::super::vms::Vm;
+    use super::*;
+
+    #[test]
+    fn recorded_events_read_back() {
+        let path = temp_path("trace.jsonl");
+        let warriors = vec![
+            warrior("imp", "mov 0, 1"),
+            warrior("dwarf", "add #4, 3\nmov 2, @2\njmp -2, 0\ndat #0, #0"),
+        ];
+        let header = TraceHeader {
+            config: VmConfig::default(),
+            seed: 11,
+            round: 3,
+            placement: Placement::evenly_spaced(2, 8000),
+            warriors: warriors.clone(),
+        };
+
+        let mut vm = Vm::new(VmConfig::default(), warriors).unwrap();
+        vm.register(Recorder::create(&path, &header).unwrap());
+        vm.play(10);
+        drop(vm);
+
+        let reader = TraceReader::<8000>::open(&path).unwrap();
+        assert_eq!(11, reader.header.seed);
+        assert_eq!(3, reader.header.round);
+        assert_eq!("dwarf", reader.header.warriors[1].name);
+        assert_eq!(4, reader.header.warriors[1].ops.len());
+
+        let events = reader.collect::<Result<Vec<_>, String>>().unwrap();
+        // the imp executes, reads its A target, copies itself to its next
+        // cell, then jumps to it
+        assert!(matches!(events[0].event_type, EventType::Execute(i) if i.op == OpCode::Mov));
+        assert!(matches!(events[1].event_type, EventType::ReadA));
+        assert!(matches!(events[2].event_type, EventType::Change(i) if i.op == OpCode::Mov));
+        assert_eq!(Some(1), events[2].offset);
+        assert!(matches!(events[3].event_type, EventType::Jump));
+        assert_eq!(Some(1), events[3].offset);
+        let executed_in_round_4 = events
+            .iter()
+            .filter(|e| e.round == 4 && matches!(e.event_type, EventType::Execute(_)))
+            .count();
+        assert_eq!(2, executed_in_round_4);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,8 +1,14 @@
-use super::event::{EventType, Observable, Observer, VmEvent};
+use super::config::{Engine, VmConfig};
+use super::event::{DeathCause, EventType, Observable, Observer, VmEvent};
 use super::instructions::*;
 use super::numeric::Numeric;
+use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
+use std::fmt;
 
+mod fast;
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct WarriorDefinition<const CORE_SIZE: usize> {
     pub name: String,
     pub ops: Vec<Instruction<CORE_SIZE>>,
@@ -14,36 +20,181 @@ impl<const CORE_SIZE: usize> WarriorDefinition<CORE_SIZE> {
     }
 }
 
-struct WarriorQueue<const CORE_SIZE: usize, const QUEUE_SIZE: usize> {
+/// Redcode that `parser::parse` reads back into the same warrior.
+impl<const CORE_SIZE: usize> fmt::Display for WarriorDefinition<CORE_SIZE> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, ";name {}", self.name)?;
+        for op in self.ops.iter() {
+            writeln!(f, "{}", op)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+struct WarriorQueue<const CORE_SIZE: usize> {
     warrior_id: usize,
     instruction_queue: VecDeque<Numeric<CORE_SIZE>>,
 }
 
-pub struct Vm<const CORE_SIZE: usize, const QUEUE_SIZE: usize> {
+/// Processes an instruction leaves in the queue, in order.
+enum Successors<const CORE_SIZE: usize> {
+    None,
+    One(Numeric<CORE_SIZE>),
+    Two(Numeric<CORE_SIZE>, Numeric<CORE_SIZE>),
+}
+
+pub enum Outcome<'a, const CORE_SIZE: usize> {
+    Running,
+    Win(usize, &'a WarriorDefinition<CORE_SIZE>),
+    Tie,
+}
+
+/// Where each warrior is loaded and in which order warriors take turns.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Placement {
+    /// Start address of each warrior, indexed by warrior id
+    pub positions: Vec<usize>,
+    /// Warrior ids in execution order, `order[0]` moves first
+    pub order: Vec<usize>,
+}
+
+impl Placement {
+    /// Warriors evenly spaced from address 0, moving in definition order.
+    pub fn evenly_spaced(warriors: usize, core_size: usize) -> Placement {
+        Placement {
+            positions: (0..warriors).map(|w| w * (core_size / warriors)).collect(),
+            order: (0..warriors).collect(),
+        }
+    }
+}
+
+/// A battle. With `OBSERVED` false events are never built nor dispatched,
+/// the reporting code compiles away: that is the VM of headless runs, which
+/// can't register observers.
+pub struct Vm<const CORE_SIZE: usize, const OBSERVED: bool = true> {
+    config: VmConfig,
     core: Box<[Instruction<CORE_SIZE>; CORE_SIZE]>,
     warriors_definitions: Vec<WarriorDefinition<CORE_SIZE>>,
-    warriors_queues: Vec<WarriorQueue<CORE_SIZE, QUEUE_SIZE>>,
-    observers: Vec<Box<dyn Observer<VmEvent>>>,
-    pub round: u128,
+    warriors_queues: Vec<WarriorQueue<CORE_SIZE>>,
+    observers: Vec<Box<dyn Observer<VmEvent<CORE_SIZE>>>>,
+    round: u128,
+    next_warrior_id: usize,
+    ticks: u128,
+}
+
+/// Full state of a `Vm`, observers excepted.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct VmSnapshot<const CORE_SIZE: usize> {
+    config: VmConfig,
+    core: Vec<Instruction<CORE_SIZE>>,
+    warriors_definitions: Vec<WarriorDefinition<CORE_SIZE>>,
+    warriors_queues: Vec<WarriorQueue<CORE_SIZE>>,
+    round: u128,
     next_warrior_id: usize,
+    ticks: u128,
 }
 
-impl<const CORE_SIZE: usize, const QUEUE_SIZE: usize> Observable<VmEvent>
-    for Vm<CORE_SIZE, QUEUE_SIZE>
-{
-    fn register(&mut self, observer: Box<dyn Observer<VmEvent>>) {
+impl<const CORE_SIZE: usize> VmSnapshot<CORE_SIZE> {
+    /// Instructions executed when the snapshot was taken.
+    pub fn ticks(&self) -> u128 {
+        self.ticks
+    }
+}
+
+impl<const CORE_SIZE: usize> Observable<VmEvent<CORE_SIZE>> for Vm<CORE_SIZE> {
+    fn register(&mut self, observer: Box<dyn Observer<VmEvent<CORE_SIZE>>>) {
         self.observers.push(observer);
     }
 }
 
-impl<const CORE_SIZE: usize, const QUEUE_SIZE: usize> Vm<CORE_SIZE, QUEUE_SIZE> {
+impl<const CORE_SIZE: usize> Vm<CORE_SIZE> {
     pub fn new(
+        config: VmConfig,
+        warriors_definitions: Vec<WarriorDefinition<CORE_SIZE>>,
+    ) -> Result<Vm<CORE_SIZE>, String> {
+        let placement = Placement::evenly_spaced(warriors_definitions.len(), CORE_SIZE);
+
+        Vm::with_placement(config, warriors_definitions, placement)
+    }
+
+    pub fn with_placement(
+        config: VmConfig,
         warriors_definitions: Vec<WarriorDefinition<CORE_SIZE>>,
-    ) -> Result<Vm<CORE_SIZE, QUEUE_SIZE>, String> {
-        if warriors_definitions.len() > 50 || warriors_definitions.len() < 2 {
-            return Err("".to_string());
+        placement: Placement,
+    ) -> Result<Vm<CORE_SIZE>, String> {
+        Vm::build(config, warriors_definitions, placement)
+    }
+
+    /// Builds a VM without observers from a snapshot.
+    pub fn from_snapshot(snapshot: &VmSnapshot<CORE_SIZE>) -> Result<Vm<CORE_SIZE>, String> {
+        let mut vm = Vm::new(
+            snapshot.config.clone(),
+            snapshot.warriors_definitions.clone(),
+        )?;
+        vm.restore(snapshot)?;
+
+        Ok(vm)
+    }
+}
+
+impl<const CORE_SIZE: usize> Vm<CORE_SIZE, false> {
+    /// A VM that reports nothing, for battles nobody watches.
+    pub fn headless(
+        config: VmConfig,
+        warriors_definitions: Vec<WarriorDefinition<CORE_SIZE>>,
+        placement: Placement,
+    ) -> Result<Vm<CORE_SIZE, false>, String> {
+        Vm::build(config, warriors_definitions, placement)
+    }
+}
+
+impl<const CORE_SIZE: usize, const OBSERVED: bool> Vm<CORE_SIZE, OBSERVED> {
+    fn build(
+        config: VmConfig,
+        warriors_definitions: Vec<WarriorDefinition<CORE_SIZE>>,
+        placement: Placement,
+    ) -> Result<Vm<CORE_SIZE, OBSERVED>, String> {
+        config.validate()?;
+
+        if config.core_size != CORE_SIZE {
+            return Err(format!(
+                "Configured core size {} does not match the VM core size {}",
+                config.core_size, CORE_SIZE
+            ));
         }
 
+        if warriors_definitions.len() > config.max_warriors || warriors_definitions.len() < 2 {
+            return Err(format!(
+                "Between 2 and {} warriors are required, {} provided",
+                config.max_warriors,
+                warriors_definitions.len()
+            ));
+        }
+
+        if let Some(w) = warriors_definitions
+            .iter()
+            .find(|w| w.ops.len() > config.max_length)
+        {
+            return Err(format!(
+                "Warrior {} is {} instructions long, the limit is {}",
+                w.name,
+                w.ops.len(),
+                config.max_length
+            ));
+        }
+
+        if CORE_SIZE / warriors_definitions.len() < config.min_distance {
+            return Err(format!(
+                "{} warriors can not be placed {} cells apart",
+                warriors_definitions.len(),
+                config.min_distance
+            ));
+        }
+
+        validate_placement::<CORE_SIZE>(&placement, warriors_definitions.len(), &config)?;
+
         let mut core = Box::new(
             [Instruction {
                 op: OpCode::Dat,
@@ -59,73 +210,146 @@ impl<const CORE_SIZE: usize, const QUEUE_SIZE: usize> Vm<CORE_SIZE, QUEUE_SIZE>
             }; CORE_SIZE],
         );
         let mut warriors_alive = Vec::new();
-        let mut instruction_pointer = 0;
 
-        for (warrior_id, warrior_definition) in warriors_definitions.iter().enumerate() {
-            for (ix, op) in warrior_definition.ops.iter().enumerate() {
-                core[instruction_pointer + ix] = op.clone();
+        for &warrior_id in placement.order.iter() {
+            let instruction_pointer = placement.positions[warrior_id];
+            for (ix, op) in warriors_definitions[warrior_id].ops.iter().enumerate() {
+                core[(instruction_pointer + ix) % CORE_SIZE] = *op;
             }
 
-            let mut instruction_queue = VecDeque::new();
+            // sized once so that spawning never reallocates
+            let mut instruction_queue = VecDeque::with_capacity(config.max_processes);
             instruction_queue.push_back(Numeric::new(instruction_pointer));
             warriors_alive.push(WarriorQueue {
                 warrior_id,
                 instruction_queue,
             });
-
-            instruction_pointer += CORE_SIZE / warriors_definitions.len();
         }
 
-        Ok(Vm::<CORE_SIZE, QUEUE_SIZE> {
+        Ok(Vm::<CORE_SIZE, OBSERVED> {
+            config,
             core,
             warriors_definitions,
             warriors_queues: warriors_alive,
             observers: Vec::new(),
             round: 0,
             next_warrior_id: 0,
+            ticks: 0,
         })
     }
 
-    pub fn notify_observers(&self, event: VmEvent) {
+    pub fn snapshot(&self) -> VmSnapshot<CORE_SIZE> {
+        VmSnapshot {
+            config: self.config.clone(),
+            core: self.core.to_vec(),
+            warriors_definitions: self.warriors_definitions.clone(),
+            warriors_queues: self.warriors_queues.clone(),
+            round: self.round,
+            next_warrior_id: self.next_warrior_id,
+            ticks: self.ticks,
+        }
+    }
+
+    /// Puts the VM back in the state of `snapshot`. Observers are kept.
+    pub fn restore(&mut self, snapshot: &VmSnapshot<CORE_SIZE>) -> Result<(), String> {
+        if snapshot.core.len() != CORE_SIZE {
+            return Err(format!(
+                "Snapshot core size {} does not match the VM core size {}",
+                snapshot.core.len(),
+                CORE_SIZE
+            ));
+        }
+
+        let warriors = snapshot.warriors_definitions.len();
+        if snapshot
+            .warriors_queues
+            .iter()
+            .any(|q| q.warrior_id >= warriors)
+            || (snapshot.next_warrior_id >= snapshot.warriors_queues.len()
+                && !snapshot.warriors_queues.is_empty())
+        {
+            return Err("Inconsistent snapshot".to_string());
+        }
+
+        self.config = snapshot.config.clone();
+        self.core.copy_from_slice(&snapshot.core);
+        self.warriors_definitions = snapshot.warriors_definitions.clone();
+        self.warriors_queues = snapshot.warriors_queues.clone();
+        self.round = snapshot.round;
+        self.next_warrior_id = snapshot.next_warrior_id;
+        self.ticks = snapshot.ticks;
+
+        Ok(())
+    }
+
+    /// Instructions executed since the start of the battle.
+    pub fn ticks(&self) -> u128 {
+        self.ticks
+    }
+
+    #[inline(always)]
+    pub fn notify_observers(&self, event: VmEvent<CORE_SIZE>) {
+        if !OBSERVED {
+            return;
+        }
+
         for obs in self.observers.iter() {
-            obs.notify(event.clone());
+            obs.notify(event);
         }
     }
 
-    pub fn play(&mut self, tick_count: i32) -> Option<&WarriorDefinition<CORE_SIZE>> {
+    pub fn play(&mut self, tick_count: i32) -> Outcome<'_, CORE_SIZE> {
         let mut ticks_played = 0;
-        while self.warriors_queues.len() > 1 && ticks_played < tick_count {
+        while self.warriors_queues.len() > 1
+            && self.round < self.config.max_cycles
+            && ticks_played < tick_count
+        {
             if let Some(instruction_pointer) = self.warriors_queues[self.next_warrior_id]
                 .instruction_queue
                 .pop_front()
             {
                 ticks_played += 1;
-                let instruction = self.core[instruction_pointer.value].clone();
-
-                for new_ix in self.execute(instruction, instruction_pointer, self.next_warrior_id) {
-                    self.notify_observers(VmEvent {
-                        event_type: EventType::Jump,
-                        moved_from: Some(instruction_pointer.value),
-                        offset: Some(new_ix.value),
-                        warrior_id: self.warriors_queues[self.next_warrior_id].warrior_id,
-                        round: self.round,
-                    });
-
-                    self.warriors_queues[self.next_warrior_id]
-                        .instruction_queue
-                        .push_back(new_ix);
-                }
-
-                self.next_warrior_id += 1;
-            } else {
-                let terminated_warrior = self.warriors_queues.remove(self.next_warrior_id);
+                self.ticks += 1;
+                let instruction = self.core[instruction_pointer.value];
                 self.notify_observers(VmEvent {
-                    event_type: EventType::TerminatedProgram,
+                    event_type: EventType::Execute(instruction),
                     moved_from: None,
-                    offset: None,
-                    warrior_id: terminated_warrior.warrior_id,
+                    offset: Some(instruction_pointer.value),
+                    warrior_id: self.warriors_queues[self.next_warrior_id].warrior_id,
                     round: self.round,
-                })
+                });
+
+                let successors = match self.config.engine {
+                    Engine::Reference => {
+                        self.execute(instruction, instruction_pointer, self.next_warrior_id)
+                    }
+                    Engine::Fast => {
+                        self.execute_fast(instruction, instruction_pointer, self.next_warrior_id)
+                    }
+                };
+
+                match successors {
+                    Successors::None => {}
+                    Successors::One(next) => self.queue_process(instruction_pointer, next),
+                    Successors::Two(next, spawned) => {
+                        self.queue_process(instruction_pointer, next);
+                        self.queue_process(instruction_pointer, spawned);
+                    }
+                }
+
+                // a warrior is out as soon as its last process dies, so
+                // that a death on the last cycle is not scored as a tie
+                if self.warriors_queues[self.next_warrior_id]
+                    .instruction_queue
+                    .is_empty()
+                {
+                    self.warriors_queues.remove(self.next_warrior_id);
+                } else {
+                    self.next_warrior_id += 1;
+                }
+            } else {
+                // only a restored snapshot can hold an empty queue
+                self.warriors_queues.remove(self.next_warrior_id);
             }
 
             if self.next_warrior_id == self.warriors_queues.len() {
@@ -135,12 +359,28 @@ impl<const CORE_SIZE: usize, const QUEUE_SIZE: usize> Vm<CORE_SIZE, QUEUE_SIZE>
         }
 
         if self.warriors_queues.len() == 1 {
-            Some(&self.warriors_definitions[self.warriors_queues.iter().nth(0).unwrap().warrior_id])
+            let warrior_id = self.warriors_queues[0].warrior_id;
+            Outcome::Win(warrior_id, &self.warriors_definitions[warrior_id])
+        } else if self.round >= self.config.max_cycles {
+            Outcome::Tie
         } else {
-            None
+            Outcome::Running
         }
     }
 
+    /// Folds an offset into the `[-limit/2, limit/2]` window around the
+    /// current instruction, as done by pMARS for read and write limits.
+    fn limit(&self, offset: Numeric<CORE_SIZE>, limit: usize) -> Numeric<CORE_SIZE> {
+        let mut folded = offset.value % limit;
+        if folded > limit / 2 {
+            folded += CORE_SIZE - limit;
+        }
+
+        Numeric::new(folded)
+    }
+
+    /// Resolves an operand to an offset from the instruction pointer,
+    /// applying the side effects of its addressing mode.
     fn fold(
         &mut self,
         operand: Operand<CORE_SIZE>,
@@ -148,41 +388,32 @@ impl<const CORE_SIZE: usize, const QUEUE_SIZE: usize> Vm<CORE_SIZE, QUEUE_SIZE>
         warrior_id: usize,
     ) -> Numeric<CORE_SIZE> {
         match operand.mode {
-            OperandMode::Immediate => instruction_pointer,
-            OperandMode::Direct => operand.pointer + instruction_pointer,
+            OperandMode::Immediate => 0.into(),
+            OperandMode::Direct => operand.pointer,
             OperandMode::Indirect => {
-                let address = operand.pointer + instruction_pointer;
+                let address =
+                    self.limit(operand.pointer, self.config.read_limit) + instruction_pointer;
 
-                address + self.core[address.value].b_operand.pointer
+                operand.pointer + self.core[address.value].b_operand.pointer
             }
             OperandMode::Increment => {
-                let address = operand.pointer + instruction_pointer;
+                let address =
+                    self.limit(operand.pointer, self.config.write_limit) + instruction_pointer;
                 let r = self.core[address.value].b_operand.pointer;
                 self.core[address.value].b_operand.pointer += 1;
 
-                self.notify_observers(VmEvent {
-                    event_type: EventType::Change,
-                    moved_from: None,
-                    offset: Some(address.value),
-                    warrior_id: warrior_id,
-                    round: self.round,
-                });
+                self.notify_change(address, warrior_id);
 
-                address + r
+                operand.pointer + r
             }
             OperandMode::Decrement => {
-                let address = operand.pointer + instruction_pointer;
+                let address =
+                    self.limit(operand.pointer, self.config.write_limit) + instruction_pointer;
                 self.core[address.value].b_operand.pointer -= 1;
 
-                self.notify_observers(VmEvent {
-                    event_type: EventType::Change,
-                    moved_from: None,
-                    offset: Some(address.value),
-                    warrior_id: warrior_id,
-                    round: self.round,
-                });
+                self.notify_change(address, warrior_id);
 
-                address + self.core[address.value].b_operand.pointer
+                operand.pointer + self.core[address.value].b_operand.pointer
             }
         }
     }
@@ -192,171 +423,189 @@ impl<const CORE_SIZE: usize, const QUEUE_SIZE: usize> Vm<CORE_SIZE, QUEUE_SIZE>
         operation: Instruction<CORE_SIZE>,
         instruction_pointer: Numeric<CORE_SIZE>,
         warrior_index: usize,
-    ) -> Vec<Numeric<CORE_SIZE>> {
+    ) -> Successors<CORE_SIZE> {
         let warrior_id = self.warriors_queues[warrior_index].warrior_id;
-        let a_address = self.fold(operation.a_operand, instruction_pointer, warrior_id);
-        let b_address = self.fold(operation.b_operand, instruction_pointer, warrior_id);
+        let a_offset = self.fold(operation.a_operand, instruction_pointer, warrior_id);
+        let b_offset = self.fold(operation.b_operand, instruction_pointer, warrior_id);
+
+        let a_address = self.limit(a_offset, self.config.read_limit) + instruction_pointer;
+        let b_address = self.limit(b_offset, self.config.read_limit) + instruction_pointer;
+        let w_address = self.limit(b_offset, self.config.write_limit) + instruction_pointer;
 
         let a_instruction = self.core[a_address.value];
         let b_instruction = self.core[b_address.value];
+
+        let (reads_a, reads_b) = match operation.op {
+            OpCode::Dat | OpCode::Jmp | OpCode::Spl => (false, false),
+            OpCode::Mov => (true, false),
+            OpCode::Jmz | OpCode::Jmn | OpCode::Djn => (false, true),
+            _ => (true, true),
+        };
+        if reads_a {
+            self.notify_read(EventType::ReadA, instruction_pointer, a_address, warrior_id);
+        }
+        if reads_b {
+            self.notify_read(EventType::ReadB, instruction_pointer, b_address, warrior_id);
+        }
+
         match operation.op {
             OpCode::Dat => {
-                self.notify_observers(VmEvent {
-                    event_type: EventType::TerminatedThread,
-                    moved_from: Some(instruction_pointer.value),
-                    offset: None,
-                    warrior_id: warrior_id,
-                    round: self.round,
-                });
+                self.notify_death(DeathCause::Dat, instruction_pointer, warrior_index);
 
-                vec![]
+                Successors::None
             }
             OpCode::Mov => {
                 match operation.modifier {
-                    Modifier::A => self.core[b_address.value].a_operand = a_instruction.a_operand,
-                    Modifier::B => self.core[b_address.value].b_operand = a_instruction.b_operand,
-                    Modifier::AB => self.core[b_address.value].b_operand = a_instruction.a_operand,
-                    Modifier::BA => self.core[b_address.value].a_operand = a_instruction.b_operand,
+                    Modifier::A => self.core[w_address.value].a_operand = a_instruction.a_operand,
+                    Modifier::B => self.core[w_address.value].b_operand = a_instruction.b_operand,
+                    Modifier::AB => self.core[w_address.value].b_operand = a_instruction.a_operand,
+                    Modifier::BA => self.core[w_address.value].a_operand = a_instruction.b_operand,
                     Modifier::F => {
-                        self.core[b_address.value].a_operand = a_instruction.a_operand;
-                        self.core[b_address.value].b_operand = a_instruction.b_operand;
+                        self.core[w_address.value].a_operand = a_instruction.a_operand;
+                        self.core[w_address.value].b_operand = a_instruction.b_operand;
                     }
                     Modifier::X => {
-                        self.core[b_address.value].a_operand = a_instruction.b_operand;
-                        self.core[b_address.value].b_operand = a_instruction.a_operand;
+                        self.core[w_address.value].a_operand = a_instruction.b_operand;
+                        self.core[w_address.value].b_operand = a_instruction.a_operand;
                     }
-                    Modifier::I => self.core[b_address.value] = a_instruction,
+                    Modifier::I => self.core[w_address.value] = a_instruction,
                 }
 
-                self.notify_observers(VmEvent {
-                    event_type: EventType::Change,
-                    moved_from: None,
-                    offset: Some(b_address.value),
-                    warrior_id: warrior_id,
-                    round: self.round,
-                });
+                self.notify_change(w_address, warrior_id);
 
-                vec![instruction_pointer + 1]
+                Successors::One(instruction_pointer + 1)
             }
-            OpCode::Add => vec![self.handle_arithmetic(
-                a_instruction,
-                b_instruction,
-                b_address,
-                operation.modifier,
-                instruction_pointer,
-                sum,
-                warrior_id,
-            )],
-            OpCode::Sub => vec![self.handle_arithmetic(
-                a_instruction,
-                b_instruction,
-                b_address,
-                operation.modifier,
-                instruction_pointer,
-                sub,
-                warrior_id,
-            )],
-            OpCode::Mul => vec![self.handle_arithmetic(
-                a_instruction,
-                b_instruction,
-                b_address,
-                operation.modifier,
-                instruction_pointer,
-                mul,
-                warrior_id,
-            )],
-            OpCode::Div => self.handle_div_arithmetic(
-                a_instruction,
-                b_instruction,
-                b_address,
-                operation.modifier,
-                instruction_pointer,
-                div,
-                warrior_id,
-            ),
-            OpCode::Mod => self.handle_div_arithmetic(
-                a_instruction,
-                b_instruction,
-                b_address,
-                operation.modifier,
-                instruction_pointer,
-                rem,
-                warrior_id,
-            ),
-            OpCode::Jmp => vec![a_address],
+            OpCode::Add => {
+                self.handle_arithmetic(
+                    a_instruction,
+                    b_instruction,
+                    w_address,
+                    operation.modifier,
+                    sum,
+                );
+                self.notify_change(w_address, warrior_id);
+
+                Successors::One(instruction_pointer + 1)
+            }
+            OpCode::Sub => {
+                self.handle_arithmetic(
+                    a_instruction,
+                    b_instruction,
+                    w_address,
+                    operation.modifier,
+                    sub,
+                );
+                self.notify_change(w_address, warrior_id);
+
+                Successors::One(instruction_pointer + 1)
+            }
+            OpCode::Mul => {
+                self.handle_arithmetic(
+                    a_instruction,
+                    b_instruction,
+                    w_address,
+                    operation.modifier,
+                    mul,
+                );
+                self.notify_change(w_address, warrior_id);
+
+                Successors::One(instruction_pointer + 1)
+            }
+            OpCode::Div | OpCode::Mod => {
+                let op = if operation.op == OpCode::Div {
+                    div
+                } else {
+                    rem
+                };
+
+                if self.handle_div_arithmetic(
+                    a_instruction,
+                    b_instruction,
+                    w_address,
+                    operation.modifier,
+                    op,
+                ) {
+                    self.notify_change(w_address, warrior_id);
+
+                    Successors::One(instruction_pointer + 1)
+                } else {
+                    self.notify_death(
+                        DeathCause::DivisionByZero,
+                        instruction_pointer,
+                        warrior_index,
+                    );
+
+                    Successors::None
+                }
+            }
+            OpCode::Jmp => Successors::One(a_address),
             OpCode::Jmz => match operation.modifier {
                 Modifier::A | Modifier::BA if b_instruction.a_operand.pointer.value == 0 => {
-                    vec![a_address]
+                    Successors::One(a_address)
                 }
-                Modifier::A | Modifier::BA => vec![instruction_pointer + 1],
+                Modifier::A | Modifier::BA => Successors::One(instruction_pointer + 1),
                 Modifier::B | Modifier::AB if b_instruction.b_operand.pointer.value == 0 => {
-                    vec![a_address]
+                    Successors::One(a_address)
                 }
-                Modifier::B | Modifier::AB => vec![instruction_pointer + 1],
+                Modifier::B | Modifier::AB => Successors::One(instruction_pointer + 1),
                 Modifier::F | Modifier::X | Modifier::I
                     if b_instruction.a_operand.pointer.value == 0
                         && b_instruction.b_operand.pointer.value == 0 =>
                 {
-                    vec![a_address]
+                    Successors::One(a_address)
                 }
-                Modifier::F | Modifier::X | Modifier::I => vec![instruction_pointer + 1],
+                Modifier::F | Modifier::X | Modifier::I => Successors::One(instruction_pointer + 1),
             },
             OpCode::Jmn => match operation.modifier {
                 Modifier::A | Modifier::BA if b_instruction.a_operand.pointer.value == 0 => {
-                    vec![instruction_pointer + 1]
+                    Successors::One(instruction_pointer + 1)
                 }
-                Modifier::A | Modifier::BA => vec![a_address],
+                Modifier::A | Modifier::BA => Successors::One(a_address),
                 Modifier::B | Modifier::AB if b_instruction.b_operand.pointer.value == 0 => {
-                    vec![instruction_pointer + 1]
+                    Successors::One(instruction_pointer + 1)
                 }
-                Modifier::B | Modifier::AB => vec![a_address],
+                Modifier::B | Modifier::AB => Successors::One(a_address),
                 Modifier::F | Modifier::X | Modifier::I
                     if b_instruction.a_operand.pointer.value == 0
                         && b_instruction.b_operand.pointer.value == 0 =>
                 {
-                    vec![instruction_pointer + 1]
+                    Successors::One(instruction_pointer + 1)
                 }
-                Modifier::F | Modifier::X | Modifier::I => vec![a_address],
+                Modifier::F | Modifier::X | Modifier::I => Successors::One(a_address),
             },
             OpCode::Djn => {
                 let result = match operation.modifier {
                     Modifier::A | Modifier::BA => {
-                        self.core[b_address.value].a_operand.pointer -= 1;
-                        if self.core[b_address.value].a_operand.pointer.value != 0 {
-                            vec![a_address]
+                        self.core[w_address.value].a_operand.pointer -= 1;
+                        if self.core[w_address.value].a_operand.pointer.value != 0 {
+                            Successors::One(a_address)
                         } else {
-                            vec![instruction_pointer + 1]
+                            Successors::One(instruction_pointer + 1)
                         }
                     }
                     Modifier::B | Modifier::AB => {
-                        self.core[b_address.value].b_operand.pointer -= 1;
-                        if self.core[b_address.value].b_operand.pointer.value != 0 {
-                            vec![a_address]
+                        self.core[w_address.value].b_operand.pointer -= 1;
+                        if self.core[w_address.value].b_operand.pointer.value != 0 {
+                            Successors::One(a_address)
                         } else {
-                            vec![instruction_pointer + 1]
+                            Successors::One(instruction_pointer + 1)
                         }
                     }
                     Modifier::F | Modifier::X | Modifier::I => {
-                        self.core[b_address.value].a_operand.pointer -= 1;
-                        self.core[b_address.value].b_operand.pointer -= 1;
+                        self.core[w_address.value].a_operand.pointer -= 1;
+                        self.core[w_address.value].b_operand.pointer -= 1;
 
-                        if self.core[b_address.value].a_operand.pointer.value != 0
-                            || self.core[b_address.value].b_operand.pointer.value != 0
+                        if self.core[w_address.value].a_operand.pointer.value != 0
+                            || self.core[w_address.value].b_operand.pointer.value != 0
                         {
-                            vec![a_address]
+                            Successors::One(a_address)
                         } else {
-                            vec![instruction_pointer + 1]
+                            Successors::One(instruction_pointer + 1)
                         }
                     }
                 };
 
-                self.notify_observers(VmEvent {
-                    event_type: EventType::Change,
-                    moved_from: None,
-                    offset: Some(b_address.value),
-                    warrior_id: warrior_id,
-                    round: self.round,
-                });
+                self.notify_change(w_address, warrior_id);
 
                 result
             }
@@ -365,122 +614,221 @@ impl<const CORE_SIZE: usize, const QUEUE_SIZE: usize> Vm<CORE_SIZE, QUEUE_SIZE>
                     if self.core[b_address.value].a_operand.pointer
                         == self.core[a_address.value].a_operand.pointer =>
                 {
-                    vec![instruction_pointer + 2]
+                    Successors::One(instruction_pointer + 2)
                 }
-                Modifier::A => vec![instruction_pointer + 1],
+                Modifier::A => Successors::One(instruction_pointer + 1),
                 Modifier::B
                     if self.core[b_address.value].b_operand.pointer
                         == self.core[a_address.value].b_operand.pointer =>
                 {
-                    vec![instruction_pointer + 2]
+                    Successors::One(instruction_pointer + 2)
                 }
-                Modifier::B => vec![instruction_pointer + 1],
+                Modifier::B => Successors::One(instruction_pointer + 1),
                 Modifier::AB
                     if self.core[b_address.value].b_operand.pointer
                         == self.core[a_address.value].a_operand.pointer =>
                 {
-                    vec![instruction_pointer + 2]
+                    Successors::One(instruction_pointer + 2)
                 }
-                Modifier::AB => vec![instruction_pointer + 1],
+                Modifier::AB => Successors::One(instruction_pointer + 1),
                 Modifier::BA
                     if self.core[b_address.value].a_operand.pointer
                         == self.core[a_address.value].b_operand.pointer =>
                 {
-                    vec![instruction_pointer + 2]
+                    Successors::One(instruction_pointer + 2)
                 }
-                Modifier::BA => vec![instruction_pointer + 1],
+                Modifier::BA => Successors::One(instruction_pointer + 1),
                 Modifier::F
                     if self.core[b_address.value].a_operand.pointer
                         == self.core[a_address.value].a_operand.pointer
                         && self.core[b_address.value].b_operand.pointer
                             == self.core[a_address.value].b_operand.pointer =>
                 {
-                    vec![instruction_pointer + 2]
+                    Successors::One(instruction_pointer + 2)
                 }
-                Modifier::F => vec![instruction_pointer + 1],
+                Modifier::F => Successors::One(instruction_pointer + 1),
                 Modifier::X
                     if self.core[b_address.value].a_operand.pointer
                         == self.core[a_address.value].b_operand.pointer
                         && self.core[b_address.value].b_operand.pointer
                             == self.core[a_address.value].a_operand.pointer =>
                 {
-                    vec![instruction_pointer + 2]
+                    Successors::One(instruction_pointer + 2)
                 }
-                Modifier::X => vec![instruction_pointer + 1],
+                Modifier::X => Successors::One(instruction_pointer + 1),
                 Modifier::I if self.core[b_address.value] == self.core[a_address.value] => {
-                    vec![instruction_pointer + 2]
+                    Successors::One(instruction_pointer + 2)
                 }
-                Modifier::I => vec![instruction_pointer + 1],
+                Modifier::I => Successors::One(instruction_pointer + 1),
             },
             OpCode::Slt => match operation.modifier {
                 Modifier::A
                     if self.core[a_address.value].a_operand.pointer
                         < self.core[b_address.value].a_operand.pointer =>
                 {
-                    vec![instruction_pointer + 2]
+                    Successors::One(instruction_pointer + 2)
                 }
-                Modifier::A => vec![instruction_pointer + 1],
+                Modifier::A => Successors::One(instruction_pointer + 1),
                 Modifier::B
                     if self.core[a_address.value].b_operand.pointer
                         < self.core[b_address.value].b_operand.pointer =>
                 {
-                    vec![instruction_pointer + 2]
+                    Successors::One(instruction_pointer + 2)
                 }
-                Modifier::B => vec![instruction_pointer + 1],
+                Modifier::B => Successors::One(instruction_pointer + 1),
                 Modifier::AB
                     if self.core[a_address.value].a_operand.pointer
                         < self.core[b_address.value].b_operand.pointer =>
                 {
-                    vec![instruction_pointer + 2]
+                    Successors::One(instruction_pointer + 2)
                 }
-                Modifier::AB => vec![instruction_pointer + 1],
+                Modifier::AB => Successors::One(instruction_pointer + 1),
                 Modifier::BA
                     if self.core[a_address.value].b_operand.pointer
                         < self.core[b_address.value].a_operand.pointer =>
                 {
-                    vec![instruction_pointer + 2]
+                    Successors::One(instruction_pointer + 2)
                 }
-                Modifier::BA => vec![instruction_pointer + 1],
+                Modifier::BA => Successors::One(instruction_pointer + 1),
                 Modifier::F | Modifier::I
                     if self.core[a_address.value].a_operand.pointer
                         < self.core[b_address.value].a_operand.pointer
                         && self.core[a_address.value].b_operand.pointer
                             < self.core[b_address.value].b_operand.pointer =>
                 {
-                    vec![instruction_pointer + 2]
+                    Successors::One(instruction_pointer + 2)
                 }
-                Modifier::F | Modifier::I => vec![instruction_pointer + 1],
+                Modifier::F | Modifier::I => Successors::One(instruction_pointer + 1),
                 Modifier::X
                     if self.core[a_address.value].a_operand.pointer
                         < self.core[b_address.value].b_operand.pointer
                         && self.core[a_address.value].b_operand.pointer
                             < self.core[b_address.value].a_operand.pointer =>
                 {
-                    vec![instruction_pointer + 2]
+                    Successors::One(instruction_pointer + 2)
                 }
-                Modifier::X => vec![instruction_pointer + 1],
+                Modifier::X => Successors::One(instruction_pointer + 1),
             },
             OpCode::Spl => {
-                if self.warriors_queues[warrior_index].instruction_queue.len() >= QUEUE_SIZE - 1 {
-                    vec![instruction_pointer + 1]
+                // the running process is out of the queue
+                let processes = self.warriors_queues[warrior_index].instruction_queue.len() + 1;
+                let (event_type, result) = if processes >= self.config.max_processes {
+                    (
+                        EventType::QueueFull,
+                        Successors::One(instruction_pointer + 1),
+                    )
                 } else {
-                    vec![instruction_pointer + 1, a_address]
-                }
+                    (
+                        EventType::Spawn {
+                            processes: processes + 1,
+                        },
+                        Successors::Two(instruction_pointer + 1, a_address),
+                    )
+                };
+
+                self.notify_spawn(event_type, instruction_pointer, a_address, warrior_id);
+
+                result
             }
         }
     }
 
+    /// Appends a process of the running warrior, coming from `from`.
+    fn queue_process(&mut self, from: Numeric<CORE_SIZE>, to: Numeric<CORE_SIZE>) {
+        let queue = &mut self.warriors_queues[self.next_warrior_id];
+        queue.instruction_queue.push_back(to);
+        let warrior_id = queue.warrior_id;
+
+        self.notify_observers(VmEvent {
+            event_type: EventType::Jump,
+            moved_from: Some(from.value),
+            offset: Some(to.value),
+            warrior_id,
+            round: self.round,
+        });
+    }
+
+    fn notify_change(&self, address: Numeric<CORE_SIZE>, warrior_id: usize) {
+        self.notify_observers(VmEvent {
+            event_type: EventType::Change(self.core[address.value]),
+            moved_from: None,
+            offset: Some(address.value),
+            warrior_id,
+            round: self.round,
+        });
+    }
+
+    fn notify_read(
+        &self,
+        event_type: EventType<CORE_SIZE>,
+        instruction_pointer: Numeric<CORE_SIZE>,
+        address: Numeric<CORE_SIZE>,
+        warrior_id: usize,
+    ) {
+        self.notify_observers(VmEvent {
+            event_type,
+            moved_from: Some(instruction_pointer.value),
+            offset: Some(address.value),
+            warrior_id,
+            round: self.round,
+        });
+    }
+
+    /// Reports a `Spawn` or `QueueFull` of `spl`.
+    fn notify_spawn(
+        &self,
+        event_type: EventType<CORE_SIZE>,
+        instruction_pointer: Numeric<CORE_SIZE>,
+        address: Numeric<CORE_SIZE>,
+        warrior_id: usize,
+    ) {
+        self.notify_observers(VmEvent {
+            event_type,
+            moved_from: Some(instruction_pointer.value),
+            offset: Some(address.value),
+            warrior_id,
+            round: self.round,
+        });
+    }
+
+    /// Reports the death of the running process, and of its warrior when it
+    /// was the last one.
+    fn notify_death(
+        &self,
+        cause: DeathCause,
+        instruction_pointer: Numeric<CORE_SIZE>,
+        warrior_index: usize,
+    ) {
+        let queue = &self.warriors_queues[warrior_index];
+        let processes = queue.instruction_queue.len();
+
+        self.notify_observers(VmEvent {
+            event_type: EventType::TerminatedThread { cause, processes },
+            moved_from: Some(instruction_pointer.value),
+            offset: None,
+            warrior_id: queue.warrior_id,
+            round: self.round,
+        });
+
+        if processes == 0 {
+            self.notify_observers(VmEvent {
+                event_type: EventType::TerminatedProgram { cause },
+                moved_from: Some(instruction_pointer.value),
+                offset: None,
+                warrior_id: queue.warrior_id,
+                round: self.round,
+            });
+        }
+    }
+
     fn handle_arithmetic<F>(
         &mut self,
         a_instruction: Instruction<CORE_SIZE>,
         b_instruction: Instruction<CORE_SIZE>,
         b_address: Numeric<CORE_SIZE>,
         modifier: Modifier,
-        instruction_pointer: Numeric<CORE_SIZE>,
         op: F,
-        warrior_id: usize,
-    ) -> Numeric<CORE_SIZE>
-    where
+    ) where
         F: Fn(Numeric<CORE_SIZE>, Numeric<CORE_SIZE>) -> Numeric<CORE_SIZE>,
     {
         match modifier {
@@ -529,39 +877,28 @@ impl<const CORE_SIZE: usize, const QUEUE_SIZE: usize> Vm<CORE_SIZE, QUEUE_SIZE>
                 );
             }
         }
-
-        self.notify_observers(VmEvent {
-            event_type: EventType::Change,
-            moved_from: None,
-            offset: Some(b_address.value),
-            warrior_id: warrior_id,
-            round: self.round,
-        });
-
-        instruction_pointer + 1
     }
 
+    /// Returns `false` when a division by zero kills the process.
     fn handle_div_arithmetic<F>(
         &mut self,
         a_instruction: Instruction<CORE_SIZE>,
         b_instruction: Instruction<CORE_SIZE>,
         b_address: Numeric<CORE_SIZE>,
         modifier: Modifier,
-        instruction_pointer: Numeric<CORE_SIZE>,
         op: F,
-        warrior_id: usize,
-    ) -> Vec<Numeric<CORE_SIZE>>
+    ) -> bool
     where
         F: Fn(Numeric<CORE_SIZE>, Numeric<CORE_SIZE>) -> Numeric<CORE_SIZE>,
     {
-        let result = match modifier {
+        match modifier {
             Modifier::A if a_instruction.a_operand.pointer.value != 0 => {
                 self.core[b_address.value].a_operand.pointer = op(
                     b_instruction.a_operand.pointer,
                     a_instruction.a_operand.pointer,
                 );
 
-                vec![instruction_pointer + 1]
+                true
             }
             Modifier::B if a_instruction.b_operand.pointer.value != 0 => {
                 self.core[b_address.value].b_operand.pointer = op(
@@ -569,23 +906,23 @@ impl<const CORE_SIZE: usize, const QUEUE_SIZE: usize> Vm<CORE_SIZE, QUEUE_SIZE>
                     a_instruction.b_operand.pointer,
                 );
 
-                vec![instruction_pointer + 1]
+                true
             }
             Modifier::AB if a_instruction.a_operand.pointer.value != 0 => {
                 self.core[b_address.value].b_operand.pointer = op(
-                    b_instruction.a_operand.pointer,
-                    a_instruction.b_operand.pointer,
+                    b_instruction.b_operand.pointer,
+                    a_instruction.a_operand.pointer,
                 );
 
-                vec![instruction_pointer + 1]
+                true
             }
             Modifier::BA if a_instruction.b_operand.pointer.value != 0 => {
                 self.core[b_address.value].a_operand.pointer = op(
-                    b_instruction.b_operand.pointer,
-                    a_instruction.a_operand.pointer,
+                    b_instruction.a_operand.pointer,
+                    a_instruction.b_operand.pointer,
                 );
 
-                vec![instruction_pointer + 1]
+                true
             }
             Modifier::F | Modifier::I => {
                 if a_instruction.a_operand.pointer.value != 0 {
@@ -602,13 +939,8 @@ impl<const CORE_SIZE: usize, const QUEUE_SIZE: usize> Vm<CORE_SIZE, QUEUE_SIZE>
                     );
                 }
 
-                if a_instruction.a_operand.pointer.value != 0
+                a_instruction.a_operand.pointer.value != 0
                     && a_instruction.b_operand.pointer.value != 0
-                {
-                    vec![instruction_pointer + 1]
-                } else {
-                    vec![]
-                }
             }
             Modifier::X => {
                 if a_instruction.a_operand.pointer.value != 0 {
@@ -625,31 +957,139 @@ impl<const CORE_SIZE: usize, const QUEUE_SIZE: usize> Vm<CORE_SIZE, QUEUE_SIZE>
                     );
                 }
 
-                if a_instruction.a_operand.pointer.value != 0
+                a_instruction.a_operand.pointer.value != 0
                     && a_instruction.b_operand.pointer.value != 0
-                {
-                    vec![instruction_pointer + 1]
-                } else {
-                    vec![]
-                }
             }
-            _ => vec![],
-        };
+            _ => false,
+        }
+    }
+}
 
-        if result.len() > 0 {
-            self.notify_observers(VmEvent {
-                event_type: EventType::Change,
-                moved_from: None,
-                offset: Some(b_address.value),
-                warrior_id: warrior_id,
-                round: self.round,
-            });
+// What tools such as the debugger look at and edit between instructions
+impl<const CORE_SIZE: usize, const OBSERVED: bool> Vm<CORE_SIZE, OBSERVED> {
+    /// Ids of the warriors still running, in execution order. A warrior is
+    /// gone as soon as its last process dies.
+    pub fn alive_warriors(&self) -> Vec<usize> {
+        self.warriors_queues.iter().map(|w| w.warrior_id).collect()
+    }
+
+    /// Cycles played: every living warrior executes one instruction per cycle.
+    pub fn cycles(&self) -> u128 {
+        self.round
+    }
+
+    pub fn config(&self) -> &VmConfig {
+        &self.config
+    }
+
+    pub fn core(&self) -> &[Instruction<CORE_SIZE>] {
+        &self.core[..]
+    }
+
+    pub fn cell(&self, address: usize) -> Instruction<CORE_SIZE> {
+        self.core[address % CORE_SIZE]
+    }
+
+    /// Overwrites a core cell. Observers are not notified.
+    pub fn set_cell(&mut self, address: usize, instruction: Instruction<CORE_SIZE>) {
+        self.core[address % CORE_SIZE] = instruction;
+    }
+
+    /// Addresses of the processes of a warrior, the next to run first.
+    /// `None` once the warrior is dead.
+    pub fn processes(&self, warrior_id: usize) -> Option<&VecDeque<Numeric<CORE_SIZE>>> {
+        self.warriors_queues
+            .iter()
+            .find(|w| w.warrior_id == warrior_id)
+            .map(|w| &w.instruction_queue)
+    }
+
+    /// Replaces the process queue of a living warrior.
+    pub fn set_processes(&mut self, warrior_id: usize, addresses: &[usize]) -> Result<(), String> {
+        // a warrior only dies executing, where observers are notified
+        if addresses.is_empty() {
+            return Err("A warrior needs at least one process".to_string());
+        }
+        if addresses.len() > self.config.max_processes {
+            return Err(format!(
+                "At most {} processes are allowed",
+                self.config.max_processes
+            ));
         }
 
-        result
+        let queue = self
+            .warriors_queues
+            .iter_mut()
+            .find(|w| w.warrior_id == warrior_id)
+            .ok_or_else(|| format!("Warrior {} is not running", warrior_id))?;
+        queue.instruction_queue = addresses.iter().map(|&a| Numeric::new(a)).collect();
+
+        Ok(())
+    }
+
+    /// Warrior whose turn it is and the address it will execute, `None` when
+    /// the battle is over.
+    pub fn next_to_move(&self) -> Option<(usize, Numeric<CORE_SIZE>)> {
+        if self.warriors_queues.len() < 2 || self.round >= self.config.max_cycles {
+            return None;
+        }
+
+        let queue = &self.warriors_queues[self.next_warrior_id];
+        queue
+            .instruction_queue
+            .front()
+            .map(|&address| (queue.warrior_id, address))
+    }
+
+    pub fn warriors(&self) -> &[WarriorDefinition<CORE_SIZE>] {
+        &self.warriors_definitions
     }
 }
 
+fn validate_placement<const CORE_SIZE: usize>(
+    placement: &Placement,
+    warriors: usize,
+    config: &VmConfig,
+) -> Result<(), String> {
+    if placement.positions.len() != warriors || placement.order.len() != warriors {
+        return Err(format!(
+            "Placement does not match the {} warriors",
+            warriors
+        ));
+    }
+
+    let mut seen = vec![false; warriors];
+    for &warrior_id in placement.order.iter() {
+        if warrior_id >= warriors || seen[warrior_id] {
+            return Err(format!("Invalid execution order {:?}", placement.order));
+        }
+        seen[warrior_id] = true;
+    }
+
+    let mut positions = placement.positions.clone();
+    positions.sort_unstable();
+    if positions[warriors - 1] >= CORE_SIZE {
+        return Err(format!(
+            "Position {} is out of the core",
+            positions[warriors - 1]
+        ));
+    }
+
+    let wrap_distance = positions[0] + CORE_SIZE - positions[warriors - 1];
+    if positions
+        .windows(2)
+        .any(|p| p[1] - p[0] < config.min_distance)
+        || wrap_distance < config.min_distance
+    {
+        return Err(format!(
+            "Warriors must be at least {} cells apart",
+            config.min_distance
+        ));
+    }
+
+    Ok(())
+}
+
 fn sum<const CORE_SIZE: usize>(u: Numeric<CORE_SIZE>, i: Numeric<CORE_SIZE>) -> Numeric<CORE_SIZE> {
     u + i
 }
@@ -669,3 +1109,311 @@ fn div<const CORE_SIZE: usize>(u: Numeric<CORE_SIZE>, i: Numeric<CORE_SIZE>) ->
 fn rem<const CORE_SIZE: usize>(u: Numeric<CORE_SIZE>, i: Numeric<CORE_SIZE>) -> Numeric<CORE_SIZE> {
     u % i
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::parser::parse;
+    use super::super::testing::warrior;
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn imp_beats_dat() {
+        let mut vm = Vm::new(
+            VmConfig::default(),
+            vec![warrior("imp", "mov 0, 1"), warrior("dat", "dat 0, 0")],
+        )
+        .unwrap();
+
+        match vm.play(10) {
+            Outcome::Win(id, w) => {
+                assert_eq!(0, id);
+                assert_eq!("imp", w.name);
+            }
+            _ => panic!("imp should have won"),
+        }
+    }
+
+    #[test]
+    fn cells_and_queues_can_be_edited() {
+        let mut vm = Vm::new(
+            VmConfig::default(),
+            vec![warrior("imp", "mov 0, 1"), warrior("dat", "dat 0, 0")],
+        )
+        .unwrap();
+
+        // turn the dat into an imp and move the process onto it
+        let imp = vm.cell(0);
+        vm.set_cell(4321, imp);
+        vm.set_processes(1, &[4321]).unwrap();
+        assert!(vm.set_processes(1, &vec![0; 8001]).is_err());
+
+        vm.play(1);
+        assert_eq!(
+            Some((1, 4321)),
+            vm.next_to_move().map(|(w, a)| (w, a.value))
+        );
+        vm.play(1);
+        assert!(vm.cell(4322) == imp);
+        assert_eq!(
+            vec![4322],
+            vm.processes(1)
+                .unwrap()
+                .iter()
+                .map(|a| a.value)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn state_is_visible_from_outside() {
+        let mut vm = Vm::new(
+            VmConfig::default(),
+            vec![warrior("spl", "spl 0, 0"), warrior("imp", "mov 0, 1")],
+        )
+        .unwrap();
+
+        assert_eq!(8000, vm.core().len());
+        assert!(vm.core()[4000] == vm.cell(12000));
+        assert!(vm.cell(4000).op == OpCode::Mov);
+        assert_eq!(Some((0, 0)), vm.next_to_move().map(|(w, a)| (w, a.value)));
+
+        vm.play(2);
+        assert_eq!(1, vm.cycles());
+        assert_eq!(vec![0, 1], vm.alive_warriors());
+        assert_eq!(2, vm.processes(0).unwrap().len());
+        assert_eq!(4001, vm.processes(1).unwrap()[0].value);
+        assert!(vm.cell(4001).op == OpCode::Mov);
+        assert!(vm.processes(2).is_none());
+    }
+
+    struct Collector {
+        events: Arc<Mutex<Vec<VmEvent<8000>>>>,
+    }
+
+    impl Observer<VmEvent<8000>> for Collector {
+        fn notify(&self, event: VmEvent<8000>) {
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    #[test]
+    fn spawns_and_deaths_are_reported() {
+        let config = VmConfig {
+            max_processes: 2,
+            ..VmConfig::default()
+        };
+        let mut vm = Vm::new(
+            config,
+            vec![
+                warrior("spl", "spl 0, 0\njmp -1, 0"),
+                warrior("div", "div #0, 1\ndat 0, 0"),
+                warrior("jmp", "jmp 0, 0"),
+            ],
+        )
+        .unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        vm.register(Box::new(Collector {
+            events: events.clone(),
+        }));
+
+        vm.play(6);
+
+        let events = events.lock().unwrap();
+        let kinds = events
+            .iter()
+            .map(|e| match e.event_type {
+                EventType::Execute(i) => format!("{} {}", e.warrior_id, i.op),
+                EventType::Spawn { processes } => format!("spawn {}", processes),
+                EventType::QueueFull => "full".to_string(),
+                EventType::TerminatedThread { cause, processes } => {
+                    format!("thread {:?} {}", cause, processes)
+                }
+                EventType::TerminatedProgram { cause } => format!("program {:?}", cause),
+                EventType::ReadA => "a".to_string(),
+                EventType::ReadB => "b".to_string(),
+                EventType::Change(_) => "change".to_string(),
+                EventType::Jump => "jump".to_string(),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![
+                "0 spl",
+                "spawn 2",
+                "jump",
+                "jump",
+                "1 div",
+                "a",
+                "b",
+                "thread DivisionByZero 0",
+                "program DivisionByZero",
+                "2 jmp",
+                "jump",
+                "0 jmp",
+                "jump",
+                "2 jmp",
+                "jump",
+                "0 spl",
+                "full",
+                "jump",
+            ],
+            kinds
+        );
+    }
+
+    #[test]
+    fn headless_plays_the_same_battle() {
+        let warriors = vec![
+            warrior("spl", "spl 0, 0\nmov 0, 1"),
+            warrior("dwarf", "add #4, 3\nmov 2, @2\njmp -2, 0\ndat #0, #0"),
+        ];
+        let placement = Placement::evenly_spaced(2, 8000);
+        let mut observed =
+            Vm::with_placement(VmConfig::default(), warriors.clone(), placement.clone()).unwrap();
+        let mut headless = Vm::headless(VmConfig::default(), warriors, placement).unwrap();
+
+        observed.play(5000);
+        headless.play(5000);
+        assert!(observed.core() == headless.core());
+        assert_eq!(observed.cycles(), headless.cycles());
+        assert_eq!(observed.alive_warriors(), headless.alive_warriors());
+    }
+
+    #[test]
+    fn max_cycles_ends_in_tie() {
+        let config = VmConfig {
+            max_cycles: 100,
+            ..VmConfig::default()
+        };
+        let mut vm = Vm::new(
+            config,
+            vec![warrior("imp1", "mov 0, 1"), warrior("imp2", "mov 0, 1")],
+        )
+        .unwrap();
+
+        assert!(matches!(vm.play(1000), Outcome::Tie));
+        assert_eq!(100, vm.cycles());
+    }
+
+    #[test]
+    fn death_on_the_last_cycle_is_a_loss() {
+        let config = VmConfig {
+            max_cycles: 5,
+            ..VmConfig::default()
+        };
+        // the dat runs on cycle 4, the last one
+        let late = "jmp 1, 0\njmp 1, 0\njmp 1, 0\njmp 1, 0\ndat 0, 0";
+
+        for order in [vec![0, 1], vec![1, 0]] {
+            let placement = Placement {
+                positions: vec![0, 4000],
+                order,
+            };
+            let mut vm = Vm::with_placement(
+                config.clone(),
+                vec![warrior("late", late), warrior("jmp", "jmp 0, 0")],
+                placement,
+            )
+            .unwrap();
+
+            assert!(matches!(vm.play(1000), Outcome::Win(1, _)));
+            assert_eq!(vec![1], vm.alive_warriors());
+        }
+    }
+
+    #[test]
+    fn dead_warriors_are_gone_at_once() {
+        let mut vm = Vm::with_placement(
+            VmConfig::default(),
+            vec![
+                warrior("dat", "dat 0, 0"),
+                warrior("imp", "mov 0, 1"),
+                warrior("jmp", "jmp 0, 0"),
+            ],
+            Placement {
+                positions: vec![0, 2000, 4000],
+                order: vec![0, 1, 2],
+            },
+        )
+        .unwrap();
+
+        vm.play(1);
+        assert_eq!(vec![1, 2], vm.alive_warriors());
+        assert!(vm.processes(0).is_none());
+        assert!(vm.set_processes(0, &[0]).is_err());
+        assert_eq!(Some(1), vm.next_to_move().map(|(id, _)| id));
+    }
+
+    #[test]
+    fn warriors_are_written_as_redcode() {
+        let warrior = WarriorDefinition::<8000>::new(
+            "dwarf".to_string(),
+            parse("add #4, 3\nmov 2, @2\njmp -2, 0\ndat #0, #0".to_string()).unwrap(),
+        );
+        let redcode = warrior.to_string();
+
+        assert!(redcode.starts_with(";name dwarf\nadd.ab #4, $3\n"));
+        assert!(parse::<8000>(redcode).unwrap() == warrior.ops);
+    }
+
+    #[test]
+    fn warriors_are_validated() {
+        let single = Vm::new(VmConfig::default(), vec![warrior("imp", "mov 0, 1")]);
+        assert!(single.is_err());
+
+        let config = VmConfig {
+            max_length: 1,
+            min_distance: 1,
+            ..VmConfig::default()
+        };
+        let too_long = Vm::new(
+            config,
+            vec![
+                warrior("imp", "mov 0, 1"),
+                warrior("long", "mov 0, 1\nmov 0, 1"),
+            ],
+        );
+        assert!(too_long.is_err());
+
+        let wrong_size = Vm::<800>::new(VmConfig::default(), vec![]);
+        assert!(wrong_size.is_err());
+    }
+
+    #[test]
+    fn placement_sets_order_and_positions() {
+        let placement = Placement {
+            positions: vec![7990, 4000],
+            order: vec![1, 0],
+        };
+        let vm = Vm::with_placement(
+            VmConfig::default(),
+            vec![
+                warrior("imp", &"mov 0, 1\n".repeat(12)),
+                warrior("dat", "dat 0, 0"),
+            ],
+            placement,
+        )
+        .unwrap();
+
+        assert_eq!(vec![1, 0], vm.alive_warriors());
+        assert_eq!(OpCode::Mov, vm.core[7990].op);
+        assert_eq!(OpCode::Mov, vm.core[1].op);
+        assert_eq!(OpCode::Dat, vm.core[2].op);
+    }
+
+    #[test]
+    fn placement_respects_min_distance() {
+        let placement = Placement {
+            positions: vec![0, 7950],
+            order: vec![0, 1],
+        };
+        let vm = Vm::with_placement(
+            VmConfig::default(),
+            vec![warrior("imp", "mov 0, 1"), warrior("dat", "dat 0, 0")],
+            placement,
+        );
+
+        assert!(vm.is_err());
+    }
+}
@@ -0,0 +1,578 @@
+//! `Engine::Fast`: every combination of opcode, modifier and addressing
+//! modes gets its own handler, monomorphized from `execute` with the
+//! combination as const parameters so that all the decoding folds away. An
+//! instruction is dispatched with a single lookup in `Vm::HANDLERS`, the way
+//! exMARS switches on a combined opcode.
+//!
+//! The behavior, events included, must stay identical to `Vm::execute`.
+
+use super::{Successors, Vm};
+use crate::vm::event::{DeathCause, EventType};
+use crate::vm::instructions::{Instruction, Modifier, OpCode, OperandMode};
+use crate::vm::numeric::Numeric;
+
+type Handler<const CORE_SIZE: usize, const OBSERVED: bool> = fn(
+    &mut Vm<CORE_SIZE, OBSERVED>,
+    Instruction<CORE_SIZE>,
+    Numeric<CORE_SIZE>,
+    usize,
+) -> Successors<CORE_SIZE>;
+
+// const parameters can't be enums yet, handlers take discriminants
+
+mod op {
+    use super::OpCode;
+
+    pub const DAT: u8 = OpCode::Dat as u8;
+    pub const MOV: u8 = OpCode::Mov as u8;
+    pub const ADD: u8 = OpCode::Add as u8;
+    pub const SUB: u8 = OpCode::Sub as u8;
+    pub const MUL: u8 = OpCode::Mul as u8;
+    pub const DIV: u8 = OpCode::Div as u8;
+    pub const MOD: u8 = OpCode::Mod as u8;
+    pub const JMP: u8 = OpCode::Jmp as u8;
+    pub const JMZ: u8 = OpCode::Jmz as u8;
+    pub const JMN: u8 = OpCode::Jmn as u8;
+    pub const DJN: u8 = OpCode::Djn as u8;
+    pub const CMP: u8 = OpCode::Cmp as u8;
+    pub const SLT: u8 = OpCode::Slt as u8;
+    pub const SPL: u8 = OpCode::Spl as u8;
+}
+
+mod modifier {
+    use super::Modifier;
+
+    pub const A: u8 = Modifier::A as u8;
+    pub const B: u8 = Modifier::B as u8;
+    pub const AB: u8 = Modifier::AB as u8;
+    pub const BA: u8 = Modifier::BA as u8;
+    pub const F: u8 = Modifier::F as u8;
+    pub const X: u8 = Modifier::X as u8;
+    pub const I: u8 = Modifier::I as u8;
+}
+
+mod mode {
+    use super::OperandMode;
+
+    pub const IMMEDIATE: u8 = OperandMode::Immediate as u8;
+    pub const DIRECT: u8 = OperandMode::Direct as u8;
+    pub const INDIRECT: u8 = OperandMode::Indirect as u8;
+    pub const DECREMENT: u8 = OperandMode::Decrement as u8;
+    pub const INCREMENT: u8 = OperandMode::Increment as u8;
+}
+
+// handlers[op][modifier][a mode][b mode], indexed by discriminant
+
+macro_rules! by_b_mode {
+    ($op:literal, $modifier:literal, $a_mode:literal) => {
+        [
+            execute::<CORE_SIZE, OBSERVED, $op, $modifier, $a_mode, 0>
+                as Handler<CORE_SIZE, OBSERVED>,
+            execute::<CORE_SIZE, OBSERVED, $op, $modifier, $a_mode, 1>,
+            execute::<CORE_SIZE, OBSERVED, $op, $modifier, $a_mode, 2>,
+            execute::<CORE_SIZE, OBSERVED, $op, $modifier, $a_mode, 3>,
+            execute::<CORE_SIZE, OBSERVED, $op, $modifier, $a_mode, 4>,
+        ]
+    };
+}
+
+macro_rules! by_a_mode {
+    ($op:literal, $modifier:literal) => {
+        [
+            by_b_mode!($op, $modifier, 0),
+            by_b_mode!($op, $modifier, 1),
+            by_b_mode!($op, $modifier, 2),
+            by_b_mode!($op, $modifier, 3),
+            by_b_mode!($op, $modifier, 4),
+        ]
+    };
+}
+
+macro_rules! by_modifier {
+    ($op:literal) => {
+        [
+            by_a_mode!($op, 0),
+            by_a_mode!($op, 1),
+            by_a_mode!($op, 2),
+            by_a_mode!($op, 3),
+            by_a_mode!($op, 4),
+            by_a_mode!($op, 5),
+            by_a_mode!($op, 6),
+        ]
+    };
+}
+
+impl<const CORE_SIZE: usize, const OBSERVED: bool> Vm<CORE_SIZE, OBSERVED> {
+    const HANDLERS: [[[[Handler<CORE_SIZE, OBSERVED>; 5]; 5]; 7]; 14] = [
+        by_modifier!(0),
+        by_modifier!(1),
+        by_modifier!(2),
+        by_modifier!(3),
+        by_modifier!(4),
+        by_modifier!(5),
+        by_modifier!(6),
+        by_modifier!(7),
+        by_modifier!(8),
+        by_modifier!(9),
+        by_modifier!(10),
+        by_modifier!(11),
+        by_modifier!(12),
+        by_modifier!(13),
+    ];
+
+    pub(super) fn execute_fast(
+        &mut self,
+        instruction: Instruction<CORE_SIZE>,
+        instruction_pointer: Numeric<CORE_SIZE>,
+        warrior_index: usize,
+    ) -> Successors<CORE_SIZE> {
+        let handlers: &'static [[[[Handler<CORE_SIZE, OBSERVED>; 5]; 5]; 7]; 14] = &Self::HANDLERS;
+        let handler = handlers[instruction.op as usize][instruction.modifier as usize]
+            [instruction.a_operand.mode as usize][instruction.b_operand.mode as usize];
+
+        handler(self, instruction, instruction_pointer, warrior_index)
+    }
+}
+
+fn execute<
+    const CORE_SIZE: usize,
+    const OBSERVED: bool,
+    const OP: u8,
+    const MODIFIER: u8,
+    const A_MODE: u8,
+    const B_MODE: u8,
+>(
+    vm: &mut Vm<CORE_SIZE, OBSERVED>,
+    instruction: Instruction<CORE_SIZE>,
+    ip: Numeric<CORE_SIZE>,
+    warrior_index: usize,
+) -> Successors<CORE_SIZE> {
+    let warrior_id = vm.warriors_queues[warrior_index].warrior_id;
+    let a_offset =
+        fold::<CORE_SIZE, OBSERVED, A_MODE>(vm, instruction.a_operand.pointer, ip, warrior_id);
+    let b_offset =
+        fold::<CORE_SIZE, OBSERVED, B_MODE>(vm, instruction.b_operand.pointer, ip, warrior_id);
+
+    let a_address = limit(a_offset, vm.config.read_limit) + ip;
+    let b_address = limit(b_offset, vm.config.read_limit) + ip;
+    let w_address = limit(b_offset, vm.config.write_limit) + ip;
+    let a = vm.core[a_address.value];
+    let b = vm.core[b_address.value];
+
+    if matches!(
+        OP,
+        op::MOV | op::ADD | op::SUB | op::MUL | op::DIV | op::MOD | op::CMP | op::SLT
+    ) {
+        vm.notify_read(EventType::ReadA, ip, a_address, warrior_id);
+    }
+    if matches!(
+        OP,
+        op::ADD
+            | op::SUB
+            | op::MUL
+            | op::DIV
+            | op::MOD
+            | op::JMZ
+            | op::JMN
+            | op::DJN
+            | op::CMP
+            | op::SLT
+    ) {
+        vm.notify_read(EventType::ReadB, ip, b_address, warrior_id);
+    }
+
+    match OP {
+        op::DAT => {
+            vm.notify_death(DeathCause::Dat, ip, warrior_index);
+
+            Successors::None
+        }
+        op::MOV => {
+            let target = &mut vm.core[w_address.value];
+            match MODIFIER {
+                modifier::A => target.a_operand = a.a_operand,
+                modifier::B => target.b_operand = a.b_operand,
+                modifier::AB => target.b_operand = a.a_operand,
+                modifier::BA => target.a_operand = a.b_operand,
+                modifier::F => {
+                    target.a_operand = a.a_operand;
+                    target.b_operand = a.b_operand;
+                }
+                modifier::X => {
+                    target.a_operand = a.b_operand;
+                    target.b_operand = a.a_operand;
+                }
+                modifier::I => *target = a,
+                _ => unreachable!(),
+            }
+            vm.notify_change(w_address, warrior_id);
+
+            Successors::One(ip + 1)
+        }
+        op::ADD | op::SUB | op::MUL => {
+            let target = &mut vm.core[w_address.value];
+            let (a_a, a_b) = (a.a_operand.pointer, a.b_operand.pointer);
+            let (b_a, b_b) = (b.a_operand.pointer, b.b_operand.pointer);
+            match MODIFIER {
+                modifier::A => target.a_operand.pointer = arithmetic::<CORE_SIZE, OP>(b_a, a_a),
+                modifier::B => target.b_operand.pointer = arithmetic::<CORE_SIZE, OP>(b_b, a_b),
+                modifier::AB => target.b_operand.pointer = arithmetic::<CORE_SIZE, OP>(b_b, a_a),
+                modifier::BA => target.a_operand.pointer = arithmetic::<CORE_SIZE, OP>(b_a, a_b),
+                modifier::F | modifier::I => {
+                    target.a_operand.pointer = arithmetic::<CORE_SIZE, OP>(b_a, a_a);
+                    target.b_operand.pointer = arithmetic::<CORE_SIZE, OP>(b_b, a_b);
+                }
+                modifier::X => {
+                    target.b_operand.pointer = arithmetic::<CORE_SIZE, OP>(b_b, a_a);
+                    target.a_operand.pointer = arithmetic::<CORE_SIZE, OP>(b_a, a_b);
+                }
+                _ => unreachable!(),
+            }
+            vm.notify_change(w_address, warrior_id);
+
+            Successors::One(ip + 1)
+        }
+        op::DIV | op::MOD => {
+            let target = &mut vm.core[w_address.value];
+            let (a_a, a_b) = (a.a_operand.pointer, a.b_operand.pointer);
+            let (b_a, b_b) = (b.a_operand.pointer, b.b_operand.pointer);
+            // each field is divided unless its divisor is zero, which kills
+            // the process
+            let (divides_a, divides_b) = match MODIFIER {
+                modifier::A => (Some((b_a, a_a)), None),
+                modifier::B => (None, Some((b_b, a_b))),
+                modifier::AB => (None, Some((b_b, a_a))),
+                modifier::BA => (Some((b_a, a_b)), None),
+                modifier::F | modifier::I => (Some((b_a, a_a)), Some((b_b, a_b))),
+                modifier::X => (Some((b_a, a_b)), Some((b_b, a_a))),
+                _ => unreachable!(),
+            };
+
+            let mut alive = true;
+            if let Some((dividend, divisor)) = divides_a {
+                if divisor.value == 0 {
+                    alive = false;
+                } else {
+                    target.a_operand.pointer = arithmetic::<CORE_SIZE, OP>(dividend, divisor);
+                }
+            }
+            if let Some((dividend, divisor)) = divides_b {
+                if divisor.value == 0 {
+                    alive = false;
+                } else {
+                    target.b_operand.pointer = arithmetic::<CORE_SIZE, OP>(dividend, divisor);
+                }
+            }
+
+            if alive {
+                vm.notify_change(w_address, warrior_id);
+
+                Successors::One(ip + 1)
+            } else {
+                vm.notify_death(DeathCause::DivisionByZero, ip, warrior_index);
+
+                Successors::None
+            }
+        }
+        op::JMP => Successors::One(a_address),
+        op::JMZ | op::JMN => {
+            let zero = match MODIFIER {
+                modifier::A | modifier::BA => b.a_operand.pointer.value == 0,
+                modifier::B | modifier::AB => b.b_operand.pointer.value == 0,
+                modifier::F | modifier::X | modifier::I => {
+                    b.a_operand.pointer.value == 0 && b.b_operand.pointer.value == 0
+                }
+                _ => unreachable!(),
+            };
+
+            if zero == (OP == op::JMZ) {
+                Successors::One(a_address)
+            } else {
+                Successors::One(ip + 1)
+            }
+        }
+        op::DJN => {
+            let target = &mut vm.core[w_address.value];
+            let non_zero = match MODIFIER {
+                modifier::A | modifier::BA => {
+                    target.a_operand.pointer -= 1;
+                    target.a_operand.pointer.value != 0
+                }
+                modifier::B | modifier::AB => {
+                    target.b_operand.pointer -= 1;
+                    target.b_operand.pointer.value != 0
+                }
+                modifier::F | modifier::X | modifier::I => {
+                    target.a_operand.pointer -= 1;
+                    target.b_operand.pointer -= 1;
+                    target.a_operand.pointer.value != 0 || target.b_operand.pointer.value != 0
+                }
+                _ => unreachable!(),
+            };
+            vm.notify_change(w_address, warrior_id);
+
+            if non_zero {
+                Successors::One(a_address)
+            } else {
+                Successors::One(ip + 1)
+            }
+        }
+        op::CMP => {
+            let equal = match MODIFIER {
+                modifier::A => b.a_operand.pointer == a.a_operand.pointer,
+                modifier::B => b.b_operand.pointer == a.b_operand.pointer,
+                modifier::AB => b.b_operand.pointer == a.a_operand.pointer,
+                modifier::BA => b.a_operand.pointer == a.b_operand.pointer,
+                modifier::F => {
+                    b.a_operand.pointer == a.a_operand.pointer
+                        && b.b_operand.pointer == a.b_operand.pointer
+                }
+                modifier::X => {
+                    b.a_operand.pointer == a.b_operand.pointer
+                        && b.b_operand.pointer == a.a_operand.pointer
+                }
+                modifier::I => b == a,
+                _ => unreachable!(),
+            };
+
+            Successors::One(ip + if equal { 2 } else { 1 })
+        }
+        op::SLT => {
+            let lower = match MODIFIER {
+                modifier::A => a.a_operand.pointer < b.a_operand.pointer,
+                modifier::B => a.b_operand.pointer < b.b_operand.pointer,
+                modifier::AB => a.a_operand.pointer < b.b_operand.pointer,
+                modifier::BA => a.b_operand.pointer < b.a_operand.pointer,
+                modifier::X => {
+                    a.a_operand.pointer < b.b_operand.pointer
+                        && a.b_operand.pointer < b.a_operand.pointer
+                }
+                modifier::F | modifier::I => {
+                    a.a_operand.pointer < b.a_operand.pointer
+                        && a.b_operand.pointer < b.b_operand.pointer
+                }
+                _ => unreachable!(),
+            };
+
+            Successors::One(ip + if lower { 2 } else { 1 })
+        }
+        op::SPL => {
+            let processes = vm.warriors_queues[warrior_index].instruction_queue.len() + 1;
+            if processes >= vm.config.max_processes {
+                vm.notify_spawn(EventType::QueueFull, ip, a_address, warrior_id);
+
+                Successors::One(ip + 1)
+            } else {
+                vm.notify_spawn(
+                    EventType::Spawn {
+                        processes: processes + 1,
+                    },
+                    ip,
+                    a_address,
+                    warrior_id,
+                );
+
+                Successors::Two(ip + 1, a_address)
+            }
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Offset of an operand from the instruction pointer, see `Vm::fold`.
+#[inline(always)]
+fn fold<const CORE_SIZE: usize, const OBSERVED: bool, const MODE: u8>(
+    vm: &mut Vm<CORE_SIZE, OBSERVED>,
+    pointer: Numeric<CORE_SIZE>,
+    ip: Numeric<CORE_SIZE>,
+    warrior_id: usize,
+) -> Numeric<CORE_SIZE> {
+    match MODE {
+        mode::IMMEDIATE => Numeric::new(0),
+        mode::DIRECT => pointer,
+        mode::INDIRECT => {
+            let address = limit(pointer, vm.config.read_limit) + ip;
+
+            pointer + vm.core[address.value].b_operand.pointer
+        }
+        mode::DECREMENT => {
+            let address = limit(pointer, vm.config.write_limit) + ip;
+            let field = &mut vm.core[address.value].b_operand.pointer;
+            *field -= 1;
+            let value = *field;
+            vm.notify_change(address, warrior_id);
+
+            pointer + value
+        }
+        mode::INCREMENT => {
+            let address = limit(pointer, vm.config.write_limit) + ip;
+            let field = &mut vm.core[address.value].b_operand.pointer;
+            let value = *field;
+            *field += 1;
+            vm.notify_change(address, warrior_id);
+
+            pointer + value
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// `Vm::limit`, skipped when the limit is the whole core.
+#[inline(always)]
+fn limit<const CORE_SIZE: usize>(offset: Numeric<CORE_SIZE>, limit: usize) -> Numeric<CORE_SIZE> {
+    if limit == CORE_SIZE {
+        return offset;
+    }
+
+    let mut folded = offset.value % limit;
+    if folded > limit / 2 {
+        folded += CORE_SIZE - limit;
+    }
+
+    Numeric::new(folded)
+}
+
+#[inline(always)]
+fn arithmetic<const CORE_SIZE: usize, const OP: u8>(
+    b: Numeric<CORE_SIZE>,
+    a: Numeric<CORE_SIZE>,
+) -> Numeric<CORE_SIZE> {
+    match OP {
+        op::ADD => b + a,
+        op::SUB => b - a,
+        op::MUL => b * a,
+        op::DIV => b / a,
+        op::MOD => b % a,
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::config::{Engine, VmConfig};
+    use crate::vm::event::{Observable, Observer, VmEvent};
+    use crate::vm::instructions::Operand;
+    use crate::vm::rng::Rng;
+    use crate::vm::vms::{Outcome, Placement, WarriorDefinition};
+    use std::sync::{Arc, Mutex};
+
+    /// Every event, serialized since events can't be compared.
+    struct Log {
+        events: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Observer<VmEvent<800>> for Log {
+        fn notify(&self, event: VmEvent<800>) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(serde_json::to_string(&event).unwrap());
+        }
+    }
+
+    /// Instructions of any kind pointing mostly inside the warrior, so that
+    /// it messes with its own code as much as possible, ending with a jump
+    /// back to the start.
+    fn random_warrior(rng: &mut Rng) -> WarriorDefinition<800> {
+        let length = 1 + rng.below(20);
+        // relative pointer from `index` to somewhere in or around the warrior
+        let operand = |rng: &mut Rng, index: usize| Operand {
+            pointer: Numeric::new(800 + rng.below(length + 5) - 2 - index),
+            mode: OperandMode::ALL[rng.below(OperandMode::ALL.len())],
+        };
+
+        let mut ops: Vec<_> = (0..length)
+            .map(|index| Instruction {
+                // dat only half as often as the others, plenty die anyway
+                op: match rng.below(2 * OpCode::ALL.len()) {
+                    n if n < OpCode::ALL.len() => OpCode::ALL[n],
+                    n => OpCode::ALL[1 + (n - OpCode::ALL.len()) % (OpCode::ALL.len() - 1)],
+                },
+                modifier: Modifier::ALL[rng.below(Modifier::ALL.len())],
+                a_operand: operand(rng, index),
+                b_operand: operand(rng, index),
+            })
+            .collect();
+        // loop back rather than running into the empty core
+        ops.push(Instruction {
+            op: OpCode::Jmp,
+            modifier: Modifier::B,
+            a_operand: Operand {
+                pointer: Numeric::new(800 - length),
+                mode: OperandMode::Direct,
+            },
+            b_operand: operand(rng, length),
+        });
+
+        WarriorDefinition::new("random".to_string(), ops)
+    }
+
+    fn play(
+        config: &VmConfig,
+        engine: Engine,
+        warriors: &[WarriorDefinition<800>],
+    ) -> (Vm<800>, Vec<String>) {
+        let config = VmConfig {
+            engine,
+            ..config.clone()
+        };
+        let mut vm = Vm::new(config, warriors.to_vec()).unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        vm.register(Box::new(Log {
+            events: events.clone(),
+        }));
+
+        while let Outcome::Running = vm.play(i32::MAX) {}
+        drop(vm.observers.pop());
+
+        let events = Arc::try_unwrap(events).unwrap().into_inner().unwrap();
+        (vm, events)
+    }
+
+    #[test]
+    fn fast_engine_matches_the_reference() {
+        let mut rng = Rng::new(36);
+
+        for battle in 0..1000 {
+            let limit = if battle % 2 == 0 { 800 } else { 200 };
+            let config = VmConfig {
+                core_size: 800,
+                max_processes: 1 + rng.below(16),
+                max_cycles: 500,
+                read_limit: limit,
+                write_limit: limit,
+                ..VmConfig::default()
+            };
+            let warriors = [random_warrior(&mut rng), random_warrior(&mut rng)];
+
+            let (reference, reference_events) = play(&config, Engine::Reference, &warriors);
+            let (fast, fast_events) = play(&config, Engine::Fast, &warriors);
+
+            assert_eq!(
+                reference_events.len(),
+                fast_events.len(),
+                "battle {}",
+                battle
+            );
+            for (r, f) in reference_events.iter().zip(fast_events.iter()) {
+                assert_eq!(r, f, "battle {}", battle);
+            }
+            let mut fast_snapshot = fast.snapshot();
+            fast_snapshot.config.engine = Engine::Reference;
+            assert!(reference.snapshot() == fast_snapshot, "battle {}", battle);
+
+            let mut headless = Vm::headless(
+                VmConfig {
+                    engine: Engine::Fast,
+                    ..config
+                },
+                warriors.to_vec(),
+                Placement::evenly_spaced(2, 800),
+            )
+            .unwrap();
+            while let Outcome::Running = headless.play(i32::MAX) {}
+            assert!(reference.core() == headless.core(), "battle {}", battle);
+        }
+    }
+}